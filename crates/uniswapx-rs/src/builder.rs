@@ -0,0 +1,453 @@
+//! Builders for synthetic orders and a local signer to sign them, for tests.
+
+use crate::hash::PERMIT2_ADDRESS;
+use crate::order::{
+    CosignerData, DutchInput, DutchOutput, ExclusiveDutchOrder, Order, OrderInfo,
+    PriorityCosignerData, PriorityInput, PriorityOrder, PriorityOutput, V2DutchOrder,
+};
+use alloy_primitives::{Address, Uint, B256};
+use anyhow::{anyhow, Result};
+use ethers::{
+    signers::{LocalWallet, Signer},
+    types::H256,
+};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+const EXCLUSIVE_DUTCH_REACTOR: &str = "0x6000da47483062A0D734Ba3dc7576Ce6A0B645C4";
+const V2_DUTCH_REACTOR: &str = "0x00000011F84B9aa48e5f8aA8B9897600006289Be";
+const PRIORITY_REACTOR: &str = "0x000000001Ec5656dcdB24D90DFa42742738De729";
+
+// mainnet WETH and USDC, the default pair for dutch orders
+const WETH: &str = "0xC02aaA39b223FE8D0A0e5C4F27eAD9083C756Cc2";
+const USDC: &str = "0xA0b86991c6218b36c1d19D4a2e9Eb0cE3606eB48";
+// base WETH and USDC, the default pair for priority orders
+const BASE_WETH: &str = "0x4200000000000000000000000000000000000006";
+const BASE_USDC: &str = "0x833589fCD6eDb6E08f4c7C32D4f71b54bdA02913";
+
+const ONE_ETHER: u64 = 1_000_000_000_000_000_000;
+/// How long default orders decay for and stay open after that.
+const DEFAULT_DECAY_SECS: u64 = 60;
+const DEFAULT_DEADLINE_SECS: u64 = 300;
+
+fn address(address: &str) -> Address {
+    address.parse().expect("valid address")
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("time after epoch")
+        .as_secs()
+}
+
+fn default_info(reactor: &str, deadline: u64) -> OrderInfo {
+    OrderInfo {
+        reactor: address(reactor),
+        swapper: Address::default(),
+        nonce: Uint::from(1),
+        deadline: Uint::from(deadline),
+        additionalValidationContract: Address::default(),
+        additionalValidationData: vec![],
+    }
+}
+
+// outputs without a recipient pay the swapper
+fn dutch_outputs(outputs: Vec<DutchOutput>, swapper: Address) -> Vec<DutchOutput> {
+    outputs
+        .into_iter()
+        .map(|mut output| {
+            if output.recipient.is_zero() {
+                output.recipient = swapper;
+            }
+            output
+        })
+        .collect()
+}
+
+// the setters every builder has: the order info, and the input and outputs under the field
+// names of the order type
+macro_rules! order_setters {
+    ($input:ident: $input_type:ty, $outputs:ident: $output_type:ty) => {
+        pub fn info(mut self, info: OrderInfo) -> Self {
+            self.order.info = info;
+            self
+        }
+
+        pub fn reactor(mut self, reactor: Address) -> Self {
+            self.order.info.reactor = reactor;
+            self
+        }
+
+        pub fn swapper(mut self, swapper: Address) -> Self {
+            self.order.info.swapper = swapper;
+            self
+        }
+
+        pub fn nonce(mut self, nonce: Uint<256, 4>) -> Self {
+            self.order.info.nonce = nonce;
+            self
+        }
+
+        pub fn deadline(mut self, deadline: u64) -> Self {
+            self.order.info.deadline = Uint::from(deadline);
+            self
+        }
+
+        pub fn input(mut self, input: $input_type) -> Self {
+            self.order.$input = input;
+            self
+        }
+
+        /// Outputs with a zero recipient pay the swapper.
+        pub fn outputs(mut self, outputs: Vec<$output_type>) -> Self {
+            self.order.$outputs = outputs;
+            self
+        }
+    };
+}
+
+/// Builds an `ExclusiveDutchOrder` selling 1 WETH for 2000 USDC decaying to 1990 USDC over
+/// the next minute, without an exclusive filler.
+#[derive(Debug, Clone)]
+pub struct ExclusiveDutchOrderBuilder {
+    order: ExclusiveDutchOrder,
+}
+
+impl Default for ExclusiveDutchOrderBuilder {
+    fn default() -> Self {
+        let now = now();
+        Self {
+            order: ExclusiveDutchOrder {
+                info: default_info(EXCLUSIVE_DUTCH_REACTOR, now + DEFAULT_DEADLINE_SECS),
+                decayStartTime: Uint::from(now),
+                decayEndTime: Uint::from(now + DEFAULT_DECAY_SECS),
+                exclusiveFiller: Address::default(),
+                exclusivityOverrideBps: Uint::ZERO,
+                input: DutchInput {
+                    token: address(WETH),
+                    startAmount: Uint::from(ONE_ETHER),
+                    endAmount: Uint::from(ONE_ETHER),
+                },
+                outputs: vec![DutchOutput {
+                    token: address(USDC),
+                    startAmount: Uint::from(2_000_000_000u64),
+                    endAmount: Uint::from(1_990_000_000u64),
+                    recipient: Address::default(),
+                }],
+            },
+        }
+    }
+}
+
+impl ExclusiveDutchOrderBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    order_setters!(input: DutchInput, outputs: DutchOutput);
+
+    pub fn decay(mut self, start_time: u64, end_time: u64) -> Self {
+        self.order.decayStartTime = Uint::from(start_time);
+        self.order.decayEndTime = Uint::from(end_time);
+        self
+    }
+
+    pub fn exclusive_filler(mut self, filler: Address, override_bps: u64) -> Self {
+        self.order.exclusiveFiller = filler;
+        self.order.exclusivityOverrideBps = Uint::from(override_bps);
+        self
+    }
+
+    pub fn build(self) -> ExclusiveDutchOrder {
+        let mut order = self.order;
+        order.outputs = dutch_outputs(order.outputs, order.info.swapper);
+        order
+    }
+}
+
+/// Builds a `V2DutchOrder` selling 1 WETH for 2000 USDC decaying to 1990 USDC over the next
+/// minute, with cosigner data that overrides nothing. Sign it with [`OrderSigner::cosign`] to fill it.
+#[derive(Debug, Clone)]
+pub struct V2DutchOrderBuilder {
+    order: V2DutchOrder,
+}
+
+impl Default for V2DutchOrderBuilder {
+    fn default() -> Self {
+        let now = now();
+        Self {
+            order: V2DutchOrder {
+                info: default_info(V2_DUTCH_REACTOR, now + DEFAULT_DEADLINE_SECS),
+                cosigner: Address::default(),
+                baseInput: DutchInput {
+                    token: address(WETH),
+                    startAmount: Uint::from(ONE_ETHER),
+                    endAmount: Uint::from(ONE_ETHER),
+                },
+                baseOutputs: vec![DutchOutput {
+                    token: address(USDC),
+                    startAmount: Uint::from(2_000_000_000u64),
+                    endAmount: Uint::from(1_990_000_000u64),
+                    recipient: Address::default(),
+                }],
+                cosignerData: CosignerData {
+                    decayStartTime: Uint::from(now),
+                    decayEndTime: Uint::from(now + DEFAULT_DECAY_SECS),
+                    exclusiveFiller: Address::default(),
+                    exclusivityOverrideBps: Uint::ZERO,
+                    inputOverride: Uint::ZERO,
+                    outputOverrides: vec![],
+                },
+                cosignature: vec![],
+            },
+        }
+    }
+}
+
+impl V2DutchOrderBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    order_setters!(baseInput: DutchInput, baseOutputs: DutchOutput);
+
+    pub fn cosigner_data(mut self, cosigner_data: CosignerData) -> Self {
+        self.order.cosignerData = cosigner_data;
+        self
+    }
+
+    /// Orders without output overrides get a zero override, meaning none, for every output,
+    /// as the reactor requires one per output.
+    pub fn build(self) -> V2DutchOrder {
+        let mut order = self.order;
+        order.baseOutputs = dutch_outputs(order.baseOutputs, order.info.swapper);
+        if order.cosignerData.outputOverrides.is_empty() {
+            order.cosignerData.outputOverrides = vec![Uint::ZERO; order.baseOutputs.len()];
+        }
+        order
+    }
+}
+
+/// Builds a `PriorityOrder` on base selling 1 WETH for at least 2000 USDC, scaling the output
+/// up by 1 mps per wei of priority fee from block 0.
+#[derive(Debug, Clone)]
+pub struct PriorityOrderBuilder {
+    order: PriorityOrder,
+}
+
+impl Default for PriorityOrderBuilder {
+    fn default() -> Self {
+        Self {
+            order: PriorityOrder {
+                info: default_info(PRIORITY_REACTOR, now() + DEFAULT_DEADLINE_SECS),
+                cosigner: Address::default(),
+                auctionStartBlock: Uint::ZERO,
+                baselinePriorityFeeWei: Uint::ZERO,
+                input: PriorityInput {
+                    token: address(BASE_WETH),
+                    amount: Uint::from(ONE_ETHER),
+                    mpsPerPriorityFeeWei: Uint::ZERO,
+                },
+                outputs: vec![PriorityOutput {
+                    token: address(BASE_USDC),
+                    amount: Uint::from(2_000_000_000u64),
+                    mpsPerPriorityFeeWei: Uint::from(1),
+                    recipient: Address::default(),
+                }],
+                cosignerData: PriorityCosignerData {
+                    auctionTargetBlock: Uint::ZERO,
+                },
+                cosignature: vec![],
+            },
+        }
+    }
+}
+
+impl PriorityOrderBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    order_setters!(input: PriorityInput, outputs: PriorityOutput);
+
+    pub fn auction_start_block(mut self, block_number: u64) -> Self {
+        self.order.auctionStartBlock = Uint::from(block_number);
+        self
+    }
+
+    pub fn baseline_priority_fee(mut self, priority_fee: Uint<256, 4>) -> Self {
+        self.order.baselinePriorityFeeWei = priority_fee;
+        self
+    }
+
+    pub fn auction_target_block(mut self, block_number: u64) -> Self {
+        self.order.cosignerData.auctionTargetBlock = Uint::from(block_number);
+        self
+    }
+
+    pub fn build(self) -> PriorityOrder {
+        let mut order = self.order;
+        let swapper = order.info.swapper;
+        for output in order.outputs.iter_mut() {
+            if output.recipient.is_zero() {
+                output.recipient = swapper;
+            }
+        }
+        order
+    }
+}
+
+/// An order with its swapper signature, formatted like the UniswapX API returns them.
+#[derive(Debug, Clone)]
+pub struct SignedOrder {
+    pub order: Order,
+    pub signature: Vec<u8>,
+}
+
+impl SignedOrder {
+    pub fn encoded_order(&self) -> String {
+        format!("0x{}", hex::encode(self.order.encode()))
+    }
+
+    pub fn signature(&self) -> String {
+        format!("0x{}", hex::encode(&self.signature))
+    }
+
+    pub fn order_hash(&self) -> String {
+        format!("0x{}", hex::encode(self.order.hash()))
+    }
+}
+
+/// Signs orders with a local key, as the swapper or as the cosigner.
+#[derive(Debug, Clone)]
+pub struct OrderSigner {
+    wallet: LocalWallet,
+    chain_id: u64,
+}
+
+impl OrderSigner {
+    pub fn new(wallet: LocalWallet, chain_id: u64) -> Self {
+        Self { wallet, chain_id }
+    }
+
+    pub fn random(chain_id: u64) -> Self {
+        Self::new(
+            LocalWallet::new(&mut ethers::core::rand::thread_rng()),
+            chain_id,
+        )
+    }
+
+    pub fn address(&self) -> Address {
+        Address::from_slice(self.wallet.address().as_bytes())
+    }
+
+    fn sign_digest(&self, digest: B256) -> Result<Vec<u8>> {
+        let signature = self.wallet.sign_hash(H256::from_slice(digest.as_slice()))?;
+        Ok(signature.to_vec())
+    }
+
+    /// Signs the Permit2 witness transfer of the order as its swapper. The order's swapper
+    /// must be this signer, as it is part of the signed order hash.
+    pub fn sign(&self, order: Order) -> Result<SignedOrder> {
+        if order.info().swapper != self.address() {
+            return Err(anyhow!("order swapper is not the signer"));
+        }
+        let permit2 = address(PERMIT2_ADDRESS);
+        let signature = self.sign_digest(order.permit2_digest(self.chain_id, permit2))?;
+        Ok(SignedOrder { order, signature })
+    }
+
+    /// Makes this signer the order's cosigner and cosigns its cosigner data. Orders without
+    /// a cosigner are left as they are.
+    pub fn cosign(&self, order: &mut Order) -> Result<()> {
+        match order {
+            Order::V2Dutch(order) => {
+                order.cosigner = self.address();
+                order.cosignature = self.sign_digest(order.cosigner_digest(self.chain_id))?;
+            }
+            Order::Priority(order) => {
+                order.cosigner = self.address();
+                order.cosignature = self.sign_digest(order.cosigner_digest(self.chain_id))?;
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::order::OrderResolution;
+    use ethers::{providers::Provider, types::Bytes};
+
+    #[tokio::test]
+    async fn test_sign_exclusive_dutch_order() {
+        let signer = OrderSigner::random(1);
+        let order = ExclusiveDutchOrderBuilder::new()
+            .swapper(signer.address())
+            .build();
+        assert_eq!(order.outputs[0].recipient, signer.address());
+
+        let signed = signer.sign(Order::ExclusiveDutch(order)).unwrap();
+
+        // the swapper has no code, so the signature is checked with ECDSA
+        let (provider, mock) = Provider::mocked();
+        mock.push::<Bytes, _>(Bytes::default()).unwrap();
+        let permit2 = address(PERMIT2_ADDRESS);
+        assert!(signed
+            .order
+            .verify_swapper_signature(&provider, &signed.signature, 1, permit2)
+            .await
+            .unwrap());
+
+        let decoded = Order::decode(&signed.encoded_order(), None).unwrap();
+        assert_eq!(decoded.hash(), signed.order.hash());
+    }
+
+    #[test]
+    fn test_sign_wrong_swapper() {
+        let signer = OrderSigner::random(1);
+        let order = ExclusiveDutchOrderBuilder::new().build();
+
+        assert!(signer.sign(Order::ExclusiveDutch(order)).is_err());
+    }
+
+    #[test]
+    fn test_cosign_v2_dutch_order() {
+        let signer = OrderSigner::random(1);
+        let cosigner = OrderSigner::random(1);
+        let mut order =
+            Order::V2Dutch(V2DutchOrderBuilder::new().swapper(signer.address()).build());
+
+        cosigner.cosign(&mut order).unwrap();
+        let signed = signer.sign(order).unwrap();
+
+        assert!(signed.order.verify_cosignature(1));
+        match &signed.order {
+            Order::V2Dutch(order) => {
+                assert_eq!(order.cosignerData.outputOverrides, vec![Uint::ZERO])
+            }
+            _ => panic!("expected a V2 Dutch order"),
+        }
+        assert_eq!(
+            signed.order.validate(&[signed.order.info().reactor]),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn test_priority_order_defaults() {
+        let order = PriorityOrderBuilder::new().auction_start_block(10).build();
+
+        assert_eq!(order.validate(), Ok(()));
+        assert!(matches!(
+            order.resolve(9, now(), Uint::ZERO),
+            OrderResolution::NotFillableYet
+        ));
+        assert!(matches!(
+            order.resolve(10, now(), Uint::ZERO),
+            OrderResolution::Resolved(_)
+        ));
+    }
+}
@@ -0,0 +1,422 @@
+use crate::order::{
+    mul_div_down, Order, OrderInfo, OrderResolution, ResolutionParams, ResolvedOrder,
+    ResolvedOutput, BPS,
+};
+use crate::validation::OrderError;
+use alloy_primitives::{Address, Uint, B256};
+use anyhow::Result;
+use bindings_uniswapx::i_protocol_fee_controller::{self, IProtocolFeeController};
+use ethers::{
+    providers::Middleware,
+    types::{Bytes, H160, U256},
+};
+use std::{collections::HashMap, sync::Arc};
+
+/// The largest fee ProtocolFees accepts, in basis points of the token's value in the order.
+const MAX_FEE_BPS: u64 = 5;
+
+fn to_h160(address: Address) -> H160 {
+    H160::from_slice(address.as_slice())
+}
+
+fn to_u256(value: Uint<256, 4>) -> U256 {
+    U256::from_big_endian(&value.to_be_bytes::<32>())
+}
+
+fn order_info(info: &OrderInfo) -> i_protocol_fee_controller::OrderInfo {
+    i_protocol_fee_controller::OrderInfo {
+        reactor: to_h160(info.reactor),
+        swapper: to_h160(info.swapper),
+        nonce: to_u256(info.nonce),
+        deadline: to_u256(info.deadline),
+        additional_validation_contract: to_h160(info.additionalValidationContract),
+        additional_validation_data: Bytes::from(info.additionalValidationData.clone()),
+    }
+}
+
+/// The amount of input the swapper permitted, which reactors resolve as `input.maxAmount`.
+fn max_input_amount(order: &Order) -> Uint<256, 4> {
+    match order {
+        Order::ExclusiveDutch(order) => order.input.endAmount,
+        Order::Dutch(order) => order.input.endAmount,
+        Order::V2Dutch(order) => order.baseInput.endAmount,
+        Order::Priority(order) => order.input.amount,
+        Order::Limit(order) => order.input.maxAmount,
+    }
+}
+
+/// The resolved order the way reactors pass it to the fee controller. Fee controllers only
+/// price the resolved amounts, so the signature is left empty.
+fn resolved_order(
+    order: &Order,
+    resolved: &ResolvedOrder,
+) -> i_protocol_fee_controller::ResolvedOrder {
+    i_protocol_fee_controller::ResolvedOrder {
+        info: order_info(order.info()),
+        input: i_protocol_fee_controller::InputToken {
+            token: to_h160(resolved.input.token),
+            amount: to_u256(resolved.input.amount),
+            max_amount: to_u256(max_input_amount(order)),
+        },
+        outputs: resolved
+            .outputs
+            .iter()
+            .map(|output| i_protocol_fee_controller::OutputToken {
+                token: to_h160(output.token),
+                amount: to_u256(output.amount),
+                recipient: to_h160(output.recipient),
+            })
+            .collect(),
+        sig: Bytes::default(),
+        hash: order.hash().0,
+    }
+}
+
+/// Queries the protocol fee controller for the fee outputs a reactor would add to `resolved`.
+pub async fn get_fee_outputs<M>(
+    client: Arc<M>,
+    fee_controller: Address,
+    order: &Order,
+    resolved: &ResolvedOrder,
+) -> Result<Vec<ResolvedOutput>>
+where
+    M: Middleware + 'static,
+{
+    let fee_outputs = IProtocolFeeController::new(to_h160(fee_controller), client)
+        .get_fee_outputs(resolved_order(order, resolved))
+        .call()
+        .await?;
+
+    Ok(fee_outputs
+        .into_iter()
+        .map(|output| {
+            let mut amount_bytes = [0u8; 32];
+            output.amount.to_big_endian(&mut amount_bytes);
+            ResolvedOutput {
+                token: Address::from_slice(output.token.as_bytes()),
+                amount: Uint::from_be_bytes(amount_bytes),
+                recipient: Address::from_slice(output.recipient.as_bytes()),
+            }
+        })
+        .collect())
+}
+
+/// Fee outputs of orders resolved against a block. An order resolves the same way for the
+/// whole block, so its fee controller only has to be queried once per block.
+#[derive(Debug, Default)]
+pub struct FeeOutputsCache {
+    block_number: u64,
+    fee_outputs: HashMap<B256, Vec<ResolvedOutput>>,
+}
+
+impl FeeOutputsCache {
+    pub fn get(&self, order_hash: B256, block_number: u64) -> Option<&Vec<ResolvedOutput>> {
+        if block_number != self.block_number {
+            return None;
+        }
+        self.fee_outputs.get(&order_hash)
+    }
+
+    /// Caches the fee outputs of an order, dropping those of earlier blocks.
+    pub fn insert(
+        &mut self,
+        order_hash: B256,
+        block_number: u64,
+        fee_outputs: Vec<ResolvedOutput>,
+    ) {
+        if block_number != self.block_number {
+            self.block_number = block_number;
+            self.fee_outputs.clear();
+        }
+        self.fee_outputs.insert(order_hash, fee_outputs);
+    }
+}
+
+impl ResolvedOrder {
+    /// Checks fee outputs the way ProtocolFees does before they are added to the order.
+    pub fn validate_fee_outputs(&self, fee_outputs: &[ResolvedOutput]) -> Result<(), OrderError> {
+        for (i, fee_output) in fee_outputs.iter().enumerate() {
+            if fee_outputs[..i]
+                .iter()
+                .any(|other| other.token == fee_output.token)
+            {
+                return Err(OrderError::DuplicateFeeOutput {
+                    token: fee_output.token,
+                });
+            }
+
+            let fee_too_large = || OrderError::FeeTooLarge {
+                token: fee_output.token,
+                amount: fee_output.amount,
+                recipient: fee_output.recipient,
+            };
+
+            let mut token_value = Uint::<256, 4>::ZERO;
+            for output in self.outputs.iter() {
+                if output.token == fee_output.token {
+                    token_value = token_value
+                        .checked_add(output.amount)
+                        .ok_or_else(fee_too_large)?;
+                }
+            }
+
+            // fees may also be taken in the input token, but not in both
+            if self.input.token == fee_output.token {
+                if !token_value.is_zero() {
+                    return Err(OrderError::InputAndOutputFees);
+                }
+                token_value = self.input.amount;
+            }
+
+            if token_value.is_zero() {
+                return Err(OrderError::InvalidFeeToken {
+                    token: fee_output.token,
+                });
+            }
+
+            match mul_div_down(token_value, Uint::from(MAX_FEE_BPS), Uint::from(BPS)) {
+                Some(max_fee) if fee_output.amount.le(&max_fee) => {}
+                _ => return Err(fee_too_large()),
+            }
+        }
+        Ok(())
+    }
+
+    /// Appends fee outputs to the order. Fee outputs the reactor would revert on make the
+    /// order `Invalid`.
+    pub fn with_fee_outputs(mut self, fee_outputs: Vec<ResolvedOutput>) -> OrderResolution {
+        if self.validate_fee_outputs(&fee_outputs).is_err() {
+            return OrderResolution::Invalid;
+        }
+
+        self.outputs.extend(fee_outputs);
+        OrderResolution::Resolved(self)
+    }
+}
+
+impl Order {
+    /// Resolves the order like `resolve`, including the protocol fee outputs the reactor's
+    /// fee controller adds. Reactors without a fee controller take no fees. Fee outputs are
+    /// looked up in `cache` before querying the fee controller.
+    pub async fn resolve_with_fees<M>(
+        &self,
+        client: Arc<M>,
+        fee_controller: Address,
+        params: &ResolutionParams,
+        cache: &mut FeeOutputsCache,
+    ) -> Result<OrderResolution>
+    where
+        M: Middleware + 'static,
+    {
+        let resolved = match self.resolve(params) {
+            OrderResolution::Resolved(resolved) => resolved,
+            resolution => return Ok(resolution),
+        };
+        if fee_controller.is_zero() {
+            return Ok(OrderResolution::Resolved(resolved));
+        }
+
+        let order_hash = self.hash();
+        let fee_outputs = match cache.get(order_hash, params.block_number) {
+            Some(fee_outputs) => fee_outputs.clone(),
+            None => {
+                let fee_outputs = get_fee_outputs(client, fee_controller, self, &resolved).await?;
+                cache.insert(order_hash, params.block_number, fee_outputs.clone());
+                fee_outputs
+            }
+        };
+        Ok(resolved.with_fee_outputs(fee_outputs))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::order::tests::{cosigner_data, filler, v2_order};
+    use crate::order::ResolvedInput;
+    use ethers::{
+        abi::{self, Token, Tokenizable},
+        providers::Provider,
+    };
+
+    fn resolved_order() -> ResolvedOrder {
+        ResolvedOrder {
+            input: ResolvedInput {
+                token: Address::from([4u8; 20]),
+                amount: Uint::from(1_000_000),
+            },
+            outputs: vec![ResolvedOutput {
+                token: Address::from([5u8; 20]),
+                amount: Uint::from(2_000_000),
+                recipient: Address::from([2u8; 20]),
+            }],
+        }
+    }
+
+    fn fee_output(token: Address, amount: u64) -> ResolvedOutput {
+        ResolvedOutput {
+            token,
+            amount: Uint::from(amount),
+            recipient: Address::from([9u8; 20]),
+        }
+    }
+
+    fn params() -> ResolutionParams {
+        ResolutionParams {
+            block_number: 1,
+            timestamp: 5,
+            priority_fee: Uint::ZERO,
+            filler: filler(),
+        }
+    }
+
+    fn encode_fee_outputs(fee_outputs: &[ResolvedOutput]) -> Bytes {
+        let outputs = fee_outputs
+            .iter()
+            .map(|output| {
+                i_protocol_fee_controller::OutputToken {
+                    token: to_h160(output.token),
+                    amount: to_u256(output.amount),
+                    recipient: to_h160(output.recipient),
+                }
+                .into_token()
+            })
+            .collect();
+        Bytes::from(abi::encode(&[Token::Array(outputs)]))
+    }
+
+    #[test]
+    fn test_with_fee_outputs() {
+        // 5 bps of the 2_000_000 output
+        let fee = fee_output(Address::from([5u8; 20]), 1000);
+
+        match resolved_order().with_fee_outputs(vec![fee]) {
+            OrderResolution::Resolved(resolved) => {
+                assert_eq!(resolved.outputs.len(), 2);
+                assert_eq!(resolved.outputs[1].amount, Uint::from(1000));
+            }
+            _ => panic!("expected order to resolve"),
+        }
+    }
+
+    #[test]
+    fn test_with_fee_outputs_input_token() {
+        let fee = fee_output(Address::from([4u8; 20]), 500);
+
+        assert!(matches!(
+            resolved_order().with_fee_outputs(vec![fee]),
+            OrderResolution::Resolved(_)
+        ));
+    }
+
+    #[test]
+    fn test_with_fee_outputs_fee_too_large() {
+        let fee = fee_output(Address::from([5u8; 20]), 1001);
+
+        assert!(matches!(
+            resolved_order().validate_fee_outputs(&[fee]),
+            Err(OrderError::FeeTooLarge { .. })
+        ));
+    }
+
+    #[test]
+    fn test_with_fee_outputs_invalid_fee_token() {
+        let fee = fee_output(Address::from([6u8; 20]), 1);
+
+        assert!(matches!(
+            resolved_order().validate_fee_outputs(&[fee]),
+            Err(OrderError::InvalidFeeToken { .. })
+        ));
+    }
+
+    #[test]
+    fn test_with_fee_outputs_duplicate() {
+        let fee = fee_output(Address::from([5u8; 20]), 1);
+
+        assert!(matches!(
+            resolved_order().validate_fee_outputs(&[fee.clone(), fee]),
+            Err(OrderError::DuplicateFeeOutput { .. })
+        ));
+    }
+
+    #[test]
+    fn test_with_fee_outputs_input_and_output_fees() {
+        let mut resolved = resolved_order();
+        resolved.outputs[0].token = resolved.input.token;
+        let fee = fee_output(Address::from([4u8; 20]), 1);
+
+        assert_eq!(
+            resolved.validate_fee_outputs(&[fee.clone()]),
+            Err(OrderError::InputAndOutputFees)
+        );
+        assert!(matches!(
+            resolved.with_fee_outputs(vec![fee]),
+            OrderResolution::Invalid
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_resolve_with_fees() {
+        let (provider, mock) = Provider::mocked();
+        let provider = Arc::new(provider);
+        let order = Order::V2Dutch(v2_order(cosigner_data(vec![Uint::ZERO])));
+        let output_token = Address::from([5u8; 20]);
+        let fee_controller = Address::from([8u8; 20]);
+        let mut cache = FeeOutputsCache::default();
+        // 5 bps of the 2000 output
+        mock.push::<Bytes, _>(encode_fee_outputs(&[fee_output(output_token, 1)]))
+            .unwrap();
+
+        match order
+            .resolve_with_fees(provider.clone(), fee_controller, &params(), &mut cache)
+            .await
+            .unwrap()
+        {
+            OrderResolution::Resolved(resolved) => {
+                assert_eq!(resolved.outputs.len(), 2);
+                assert_eq!(resolved.outputs[1].amount, Uint::from(1));
+                assert_eq!(resolved.outputs[1].token, output_token);
+            }
+            _ => panic!("expected order to resolve"),
+        }
+
+        // the only mocked response is used up, so resolving again in the block hits the cache
+        match order
+            .resolve_with_fees(provider.clone(), fee_controller, &params(), &mut cache)
+            .await
+            .unwrap()
+        {
+            OrderResolution::Resolved(resolved) => assert_eq!(resolved.outputs.len(), 2),
+            _ => panic!("expected order to resolve"),
+        }
+
+        // but the next block queries the fee controller again
+        let mut next_block = params();
+        next_block.block_number += 1;
+        assert!(order
+            .resolve_with_fees(provider, fee_controller, &next_block, &mut cache)
+            .await
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn test_resolve_without_fee_controller() {
+        // no mocked responses, so any rpc call would fail
+        let (provider, _mock) = Provider::mocked();
+        let order = Order::V2Dutch(v2_order(cosigner_data(vec![Uint::ZERO])));
+
+        match order
+            .resolve_with_fees(
+                Arc::new(provider),
+                Address::default(),
+                &params(),
+                &mut FeeOutputsCache::default(),
+            )
+            .await
+            .unwrap()
+        {
+            OrderResolution::Resolved(resolved) => assert_eq!(resolved.outputs.len(), 1),
+            _ => panic!("expected order to resolve"),
+        }
+    }
+}
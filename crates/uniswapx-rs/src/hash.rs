@@ -0,0 +1,466 @@
+use crate::order::{
+    CosignerData, DutchOrder, DutchOutput, ExclusiveDutchOrder, LimitOrder, Order, OrderInfo,
+    OutputToken, PriorityCosignerData, PriorityInput, PriorityOrder, PriorityOutput, V2DutchOrder,
+};
+use alloy_primitives::{keccak256, Address, Uint, B256};
+use alloy_sol_types::SolType;
+
+/// Canonical Permit2 deployment, the same on every chain.
+pub const PERMIT2_ADDRESS: &str = "0x000000000022D473030F116dDEE9F6B43aC78BA3";
+
+const EIP712_DOMAIN_TYPE: &str =
+    "EIP712Domain(string name,uint256 chainId,address verifyingContract)";
+const PERMIT2_DOMAIN_NAME: &str = "Permit2";
+const PERMIT_WITNESS_TRANSFER_FROM_TYPE_STUB: &str = "PermitWitnessTransferFrom(TokenPermissions permitted,address spender,uint256 nonce,uint256 deadline,";
+const TOKEN_PERMISSIONS_TYPE: &str = "TokenPermissions(address token,uint256 amount)";
+
+const ORDER_INFO_TYPE: &str = "OrderInfo(address reactor,address swapper,uint256 nonce,uint256 deadline,address additionalValidationContract,bytes additionalValidationData)";
+const DUTCH_OUTPUT_TYPE: &str =
+    "DutchOutput(address token,uint256 startAmount,uint256 endAmount,address recipient)";
+const OUTPUT_TOKEN_TYPE: &str = "OutputToken(address token,uint256 amount,address recipient)";
+const PRIORITY_INPUT_TYPE: &str =
+    "PriorityInput(address token,uint256 amount,uint256 mpsPerPriorityFeeWei)";
+const PRIORITY_OUTPUT_TYPE: &str =
+    "PriorityOutput(address token,uint256 amount,uint256 mpsPerPriorityFeeWei,address recipient)";
+
+const EXCLUSIVE_DUTCH_ORDER_TYPE: &str = "ExclusiveDutchOrder(OrderInfo info,uint256 decayStartTime,uint256 decayEndTime,address exclusiveFiller,uint256 exclusivityOverrideBps,address inputToken,uint256 inputStartAmount,uint256 inputEndAmount,DutchOutput[] outputs)";
+const DUTCH_ORDER_TYPE: &str = "DutchOrder(OrderInfo info,uint256 decayStartTime,uint256 decayEndTime,address inputToken,uint256 inputStartAmount,uint256 inputEndAmount,DutchOutput[] outputs)";
+const V2_DUTCH_ORDER_TYPE: &str = "V2DutchOrder(OrderInfo info,address cosigner,address baseInputToken,uint256 baseInputStartAmount,uint256 baseInputEndAmount,DutchOutput[] baseOutputs)";
+const PRIORITY_ORDER_TYPE: &str = "PriorityOrder(OrderInfo info,address cosigner,uint256 auctionStartBlock,uint256 baselinePriorityFeeWei,PriorityInput input,PriorityOutput[] outputs)";
+const LIMIT_ORDER_TYPE: &str =
+    "LimitOrder(OrderInfo info,address inputToken,uint256 inputAmount,OutputToken[] outputs)";
+
+/// Builds the abi encoding of a struct made of static values, starting with its type hash.
+struct StructEncoder(Vec<u8>);
+
+impl StructEncoder {
+    fn new(type_hash: B256) -> Self {
+        Self(type_hash.to_vec())
+    }
+
+    fn word(mut self, word: B256) -> Self {
+        self.0.extend_from_slice(word.as_slice());
+        self
+    }
+
+    fn address(mut self, address: Address) -> Self {
+        self.0.extend_from_slice(&[0u8; 12]);
+        self.0.extend_from_slice(address.as_slice());
+        self
+    }
+
+    fn uint(mut self, value: Uint<256, 4>) -> Self {
+        self.0.extend_from_slice(&value.to_be_bytes::<32>());
+        self
+    }
+
+    fn hash(self) -> B256 {
+        keccak256(self.0)
+    }
+}
+
+// witness type strings list the referenced structs in alphabetical order, as EIP-712 requires
+fn type_hash(types: &[&str]) -> B256 {
+    keccak256(types.concat())
+}
+
+/// `keccak256(abi.encodePacked(orderHash, chainId, abi.encode(cosignerData)))`, the digest
+/// CosignerLib checks the cosignature against.
+fn cosigner_digest(order_hash: B256, chain_id: u64, encoded_cosigner_data: Vec<u8>) -> B256 {
+    let mut digest = order_hash.to_vec();
+    digest.extend_from_slice(&Uint::<256, 4>::from(chain_id).to_be_bytes::<32>());
+    digest.extend(encoded_cosigner_data);
+    keccak256(digest)
+}
+
+fn hash_array(hashes: impl Iterator<Item = B256>) -> B256 {
+    let mut packed = Vec::new();
+    for hash in hashes {
+        packed.extend_from_slice(hash.as_slice());
+    }
+    keccak256(packed)
+}
+
+/// Computes the EIP-712 digest Permit2 verifies the swapper's signature against
+/// in `permitWitnessTransferFrom`, with the order hash as the witness.
+fn permit2_digest(
+    chain_id: u64,
+    permit2: Address,
+    witness_type: &[&str],
+    order_hash: B256,
+    info: &OrderInfo,
+    input_token: Address,
+    max_input_amount: Uint<256, 4>,
+) -> B256 {
+    let token_permissions = StructEncoder::new(type_hash(&[TOKEN_PERMISSIONS_TYPE]))
+        .address(input_token)
+        .uint(max_input_amount)
+        .hash();
+
+    let mut permit_type = vec![PERMIT_WITNESS_TRANSFER_FROM_TYPE_STUB];
+    permit_type.extend_from_slice(witness_type);
+    // the reactor is the spender of the swapper's tokens
+    let struct_hash = StructEncoder::new(type_hash(&permit_type))
+        .word(token_permissions)
+        .address(info.reactor)
+        .uint(info.nonce)
+        .uint(info.deadline)
+        .word(order_hash)
+        .hash();
+
+    let domain_separator = StructEncoder::new(type_hash(&[EIP712_DOMAIN_TYPE]))
+        .word(keccak256(PERMIT2_DOMAIN_NAME))
+        .uint(Uint::from(chain_id))
+        .address(permit2)
+        .hash();
+
+    let mut digest = Vec::with_capacity(66);
+    digest.extend_from_slice(b"\x19\x01");
+    digest.extend_from_slice(domain_separator.as_slice());
+    digest.extend_from_slice(struct_hash.as_slice());
+    keccak256(digest)
+}
+
+impl OrderInfo {
+    pub fn hash(&self) -> B256 {
+        StructEncoder::new(type_hash(&[ORDER_INFO_TYPE]))
+            .address(self.reactor)
+            .address(self.swapper)
+            .uint(self.nonce)
+            .uint(self.deadline)
+            .address(self.additionalValidationContract)
+            .word(keccak256(&self.additionalValidationData))
+            .hash()
+    }
+}
+
+impl DutchOutput {
+    pub fn hash(&self) -> B256 {
+        StructEncoder::new(type_hash(&[DUTCH_OUTPUT_TYPE]))
+            .address(self.token)
+            .uint(self.startAmount)
+            .uint(self.endAmount)
+            .address(self.recipient)
+            .hash()
+    }
+}
+
+impl OutputToken {
+    pub fn hash(&self) -> B256 {
+        StructEncoder::new(type_hash(&[OUTPUT_TOKEN_TYPE]))
+            .address(self.token)
+            .uint(self.amount)
+            .address(self.recipient)
+            .hash()
+    }
+}
+
+impl PriorityInput {
+    pub fn hash(&self) -> B256 {
+        StructEncoder::new(type_hash(&[PRIORITY_INPUT_TYPE]))
+            .address(self.token)
+            .uint(self.amount)
+            .uint(self.mpsPerPriorityFeeWei)
+            .hash()
+    }
+}
+
+impl PriorityOutput {
+    pub fn hash(&self) -> B256 {
+        StructEncoder::new(type_hash(&[PRIORITY_OUTPUT_TYPE]))
+            .address(self.token)
+            .uint(self.amount)
+            .uint(self.mpsPerPriorityFeeWei)
+            .address(self.recipient)
+            .hash()
+    }
+}
+
+impl ExclusiveDutchOrder {
+    /// The order hash used by the reactor, e.g. in `Fill` events.
+    pub fn hash(&self) -> B256 {
+        StructEncoder::new(type_hash(&[
+            EXCLUSIVE_DUTCH_ORDER_TYPE,
+            DUTCH_OUTPUT_TYPE,
+            ORDER_INFO_TYPE,
+        ]))
+        .word(self.info.hash())
+        .uint(self.decayStartTime)
+        .uint(self.decayEndTime)
+        .address(self.exclusiveFiller)
+        .uint(self.exclusivityOverrideBps)
+        .address(self.input.token)
+        .uint(self.input.startAmount)
+        .uint(self.input.endAmount)
+        .word(hash_array(self.outputs.iter().map(DutchOutput::hash)))
+        .hash()
+    }
+
+    pub fn permit2_digest(&self, chain_id: u64, permit2: Address) -> B256 {
+        permit2_digest(
+            chain_id,
+            permit2,
+            &[
+                "ExclusiveDutchOrder witness)",
+                DUTCH_OUTPUT_TYPE,
+                EXCLUSIVE_DUTCH_ORDER_TYPE,
+                ORDER_INFO_TYPE,
+                TOKEN_PERMISSIONS_TYPE,
+            ],
+            self.hash(),
+            &self.info,
+            self.input.token,
+            self.input.endAmount,
+        )
+    }
+}
+
+impl DutchOrder {
+    /// The order hash used by the reactor, e.g. in `Fill` events.
+    pub fn hash(&self) -> B256 {
+        StructEncoder::new(type_hash(&[
+            DUTCH_ORDER_TYPE,
+            DUTCH_OUTPUT_TYPE,
+            ORDER_INFO_TYPE,
+        ]))
+        .word(self.info.hash())
+        .uint(self.decayStartTime)
+        .uint(self.decayEndTime)
+        .address(self.input.token)
+        .uint(self.input.startAmount)
+        .uint(self.input.endAmount)
+        .word(hash_array(self.outputs.iter().map(DutchOutput::hash)))
+        .hash()
+    }
+
+    pub fn permit2_digest(&self, chain_id: u64, permit2: Address) -> B256 {
+        permit2_digest(
+            chain_id,
+            permit2,
+            &[
+                "DutchOrder witness)",
+                DUTCH_ORDER_TYPE,
+                DUTCH_OUTPUT_TYPE,
+                ORDER_INFO_TYPE,
+                TOKEN_PERMISSIONS_TYPE,
+            ],
+            self.hash(),
+            &self.info,
+            self.input.token,
+            self.input.endAmount,
+        )
+    }
+}
+
+impl V2DutchOrder {
+    /// The order hash used by the reactor, e.g. in `Fill` events. Cosigner data is not
+    /// part of the hash.
+    pub fn hash(&self) -> B256 {
+        StructEncoder::new(type_hash(&[
+            V2_DUTCH_ORDER_TYPE,
+            DUTCH_OUTPUT_TYPE,
+            ORDER_INFO_TYPE,
+        ]))
+        .word(self.info.hash())
+        .address(self.cosigner)
+        .address(self.baseInput.token)
+        .uint(self.baseInput.startAmount)
+        .uint(self.baseInput.endAmount)
+        .word(hash_array(self.baseOutputs.iter().map(DutchOutput::hash)))
+        .hash()
+    }
+
+    pub fn permit2_digest(&self, chain_id: u64, permit2: Address) -> B256 {
+        permit2_digest(
+            chain_id,
+            permit2,
+            &[
+                "V2DutchOrder witness)",
+                DUTCH_OUTPUT_TYPE,
+                ORDER_INFO_TYPE,
+                TOKEN_PERMISSIONS_TYPE,
+                V2_DUTCH_ORDER_TYPE,
+            ],
+            self.hash(),
+            &self.info,
+            self.baseInput.token,
+            self.baseInput.endAmount,
+        )
+    }
+    pub fn cosigner_digest(&self, chain_id: u64) -> B256 {
+        cosigner_digest(
+            self.hash(),
+            chain_id,
+            CosignerData::encode(&self.cosignerData),
+        )
+    }
+}
+
+impl PriorityOrder {
+    /// The order hash used by the reactor, e.g. in `Fill` events. Cosigner data is not
+    /// part of the hash.
+    pub fn hash(&self) -> B256 {
+        StructEncoder::new(type_hash(&[
+            PRIORITY_ORDER_TYPE,
+            ORDER_INFO_TYPE,
+            PRIORITY_INPUT_TYPE,
+            PRIORITY_OUTPUT_TYPE,
+        ]))
+        .word(self.info.hash())
+        .address(self.cosigner)
+        .uint(self.auctionStartBlock)
+        .uint(self.baselinePriorityFeeWei)
+        .word(self.input.hash())
+        .word(hash_array(self.outputs.iter().map(PriorityOutput::hash)))
+        .hash()
+    }
+
+    pub fn permit2_digest(&self, chain_id: u64, permit2: Address) -> B256 {
+        permit2_digest(
+            chain_id,
+            permit2,
+            &[
+                "PriorityOrder witness)",
+                ORDER_INFO_TYPE,
+                PRIORITY_INPUT_TYPE,
+                PRIORITY_ORDER_TYPE,
+                PRIORITY_OUTPUT_TYPE,
+                TOKEN_PERMISSIONS_TYPE,
+            ],
+            self.hash(),
+            &self.info,
+            self.input.token,
+            self.input.amount,
+        )
+    }
+    pub fn cosigner_digest(&self, chain_id: u64) -> B256 {
+        cosigner_digest(
+            self.hash(),
+            chain_id,
+            PriorityCosignerData::encode(&self.cosignerData),
+        )
+    }
+}
+
+impl LimitOrder {
+    /// The order hash used by the reactor, e.g. in `Fill` events.
+    pub fn hash(&self) -> B256 {
+        StructEncoder::new(type_hash(&[
+            LIMIT_ORDER_TYPE,
+            ORDER_INFO_TYPE,
+            OUTPUT_TOKEN_TYPE,
+        ]))
+        .word(self.info.hash())
+        .address(self.input.token)
+        .uint(self.input.amount)
+        .word(hash_array(self.outputs.iter().map(OutputToken::hash)))
+        .hash()
+    }
+
+    pub fn permit2_digest(&self, chain_id: u64, permit2: Address) -> B256 {
+        permit2_digest(
+            chain_id,
+            permit2,
+            &[
+                "LimitOrder witness)",
+                LIMIT_ORDER_TYPE,
+                ORDER_INFO_TYPE,
+                OUTPUT_TOKEN_TYPE,
+                TOKEN_PERMISSIONS_TYPE,
+            ],
+            self.hash(),
+            &self.info,
+            self.input.token,
+            self.input.maxAmount,
+        )
+    }
+}
+
+impl Order {
+    /// The order hash used by the reactor, e.g. in `Fill` events and by the UniswapX API.
+    pub fn hash(&self) -> B256 {
+        match self {
+            Order::ExclusiveDutch(order) => order.hash(),
+            Order::Dutch(order) => order.hash(),
+            Order::V2Dutch(order) => order.hash(),
+            Order::Priority(order) => order.hash(),
+            Order::Limit(order) => order.hash(),
+        }
+    }
+
+    /// The EIP-712 digest the swapper signs, a Permit2 transfer with the order as witness.
+    pub fn permit2_digest(&self, chain_id: u64, permit2: Address) -> B256 {
+        match self {
+            Order::ExclusiveDutch(order) => order.permit2_digest(chain_id, permit2),
+            Order::Dutch(order) => order.permit2_digest(chain_id, permit2),
+            Order::V2Dutch(order) => order.permit2_digest(chain_id, permit2),
+            Order::Priority(order) => order.permit2_digest(chain_id, permit2),
+            Order::Limit(order) => order.permit2_digest(chain_id, permit2),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::order::{
+        decode_order,
+        tests::{cosigner_data, priority_order, v2_order},
+    };
+
+    const ENCODED_EXCLUSIVE_DUTCH_ORDER: &str = "0x0000000000000000000000000000000000000000000000000000000000000020000000000000000000000000000000000000000000000000000000000000012000000000000000000000000000000000000000000000000000000000647cb78400000000000000000000000000000000000000000000000000000000647cb7c0000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000002791bca1f2de4661ed88a30c99a7a9449aa841740000000000000000000000000000000000000000000000000000000005915ddf0000000000000000000000000000000000000000000000000000000005915ddf0000000000000000000000000000000000000000000000000000000000000200000000000000000000000000bd7f9d0239f81c94b728d827a87b9864972661ec000000000000000000000000a7152fad7467857dc2d4060fecaadf9f6b8227d304683201ee09ab48f5120a626b494a18097ae556b98be2a2b837f27680c3c10100000000000000000000000000000000000000000000000000000000647cb7c0000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000c0000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000010000000000000000000000007ceb23fd6bc0add59e62ac25578270cff1b9f61900000000000000000000000000000000000000000000000000aeb06158f08cf900000000000000000000000000000000000000000000000000add0c742bc25de000000000000000000000000a7152fad7467857dc2d4060fecaadf9f6b8227d3";
+
+    fn b256(hash: &str) -> B256 {
+        hash.parse().unwrap()
+    }
+
+    #[test]
+    fn test_exclusive_dutch_order_hash() {
+        let order = decode_order(ENCODED_EXCLUSIVE_DUTCH_ORDER).unwrap();
+
+        assert_eq!(
+            order.hash(),
+            b256("0xe8b960f27f5e70e67aa958780641d0c3a53575e3b170f725444e520c20ff013b")
+        );
+        assert_eq!(
+            order.permit2_digest(137, PERMIT2_ADDRESS.parse().unwrap()),
+            b256("0x84372adcba69630e9d3d6fda6916625fef2bb4224d9dc42263e8dfd8e0239e92")
+        );
+    }
+
+    #[test]
+    fn test_v2_dutch_order_hash() {
+        let order = v2_order(cosigner_data(vec![]));
+
+        assert_eq!(
+            order.hash(),
+            b256("0x4ea848d535b93e32806c377cbba1df9a10096d42099be93044635f062dd90995")
+        );
+        assert_eq!(
+            order.permit2_digest(1, PERMIT2_ADDRESS.parse().unwrap()),
+            b256("0x74a1cac3c9d12628d56e85cb391e286ed799f7b5c181d93f39a2bd288b3d8d93")
+        );
+    }
+
+    #[test]
+    fn test_v2_dutch_order_hash_ignores_cosigner_data() {
+        let order = v2_order(cosigner_data(vec![]));
+        let cosigned = v2_order(cosigner_data(vec![Uint::from(2500)]));
+
+        assert_eq!(order.hash(), cosigned.hash());
+    }
+
+    #[test]
+    fn test_priority_order_hash() {
+        let order = Order::Priority(priority_order(0, 1));
+
+        assert_eq!(
+            order.hash(),
+            b256("0x67c36a7439b0b55fdff1318aceb9a22bba646251745ce7ce1a0bb13f96d86880")
+        );
+        assert_eq!(
+            order.permit2_digest(8453, PERMIT2_ADDRESS.parse().unwrap()),
+            b256("0x6514493f48cc1858b1a81ab16afec3c3fc13f4e97a26a0cb8609dab935aa5db6")
+        );
+    }
+}
@@ -0,0 +1,288 @@
+//! Serde support for orders and resolutions. Addresses are written checksummed and amounts as
+//! decimal strings, so the JSON is stable and readable in logs.
+
+use crate::order::{DutchInput, DutchOutput, ExclusiveDutchOrder, OrderInfo};
+use alloy_primitives::{Address, Uint};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+/// Serializes an `Address` as a checksummed hex string. Any casing is accepted when
+/// deserializing.
+pub mod address {
+    use super::*;
+
+    pub fn serialize<S: Serializer>(address: &Address, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&address.to_checksum(None))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Address, D::Error> {
+        let value = String::deserialize(deserializer)?;
+        value.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+/// Serializes a `Uint<256, 4>` as a decimal string, since JSON numbers can't hold 256 bits.
+pub mod amount {
+    use super::*;
+
+    pub fn serialize<S: Serializer>(
+        amount: &Uint<256, 4>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&amount.to_string())
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Uint<256, 4>, D::Error> {
+        let value = String::deserialize(deserializer)?;
+        Uint::from_str_radix(&value, 10).map_err(serde::de::Error::custom)
+    }
+}
+
+/// Serializes bytes as a 0x prefixed hex string.
+pub mod bytes {
+    use super::*;
+
+    pub fn serialize<S: Serializer>(bytes: &[u8], serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&format!("0x{}", hex::encode(bytes)))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<u8>, D::Error> {
+        let value = String::deserialize(deserializer)?;
+        hex::decode(value.strip_prefix("0x").unwrap_or(&value)).map_err(serde::de::Error::custom)
+    }
+}
+
+// the sol! structs can't carry field attributes, so they are serialized through remote
+// definitions that keep the solidity field names
+#[allow(non_snake_case)]
+#[derive(Serialize, Deserialize)]
+#[serde(remote = "OrderInfo")]
+struct OrderInfoDef {
+    #[serde(with = "address")]
+    reactor: Address,
+    #[serde(with = "address")]
+    swapper: Address,
+    #[serde(with = "amount")]
+    nonce: Uint<256, 4>,
+    #[serde(with = "amount")]
+    deadline: Uint<256, 4>,
+    #[serde(with = "address")]
+    additionalValidationContract: Address,
+    #[serde(with = "bytes")]
+    additionalValidationData: Vec<u8>,
+}
+
+#[allow(non_snake_case)]
+#[derive(Serialize, Deserialize)]
+#[serde(remote = "DutchInput")]
+struct DutchInputDef {
+    #[serde(with = "address")]
+    token: Address,
+    #[serde(with = "amount")]
+    startAmount: Uint<256, 4>,
+    #[serde(with = "amount")]
+    endAmount: Uint<256, 4>,
+}
+
+#[allow(non_snake_case)]
+#[derive(Serialize, Deserialize)]
+#[serde(remote = "DutchOutput")]
+struct DutchOutputDef {
+    #[serde(with = "address")]
+    token: Address,
+    #[serde(with = "amount")]
+    startAmount: Uint<256, 4>,
+    #[serde(with = "amount")]
+    endAmount: Uint<256, 4>,
+    #[serde(with = "address")]
+    recipient: Address,
+}
+
+mod dutch_outputs {
+    use super::*;
+
+    pub fn serialize<S: Serializer>(
+        outputs: &[DutchOutput],
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        #[derive(Serialize)]
+        struct Output<'a>(#[serde(with = "DutchOutputDef")] &'a DutchOutput);
+
+        serializer.collect_seq(outputs.iter().map(Output))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Vec<DutchOutput>, D::Error> {
+        #[derive(Deserialize)]
+        struct Output(#[serde(with = "DutchOutputDef")] DutchOutput);
+
+        let outputs = Vec::<Output>::deserialize(deserializer)?;
+        Ok(outputs.into_iter().map(|Output(output)| output).collect())
+    }
+}
+
+#[allow(non_snake_case)]
+#[derive(Serialize, Deserialize)]
+#[serde(remote = "ExclusiveDutchOrder")]
+struct ExclusiveDutchOrderDef {
+    #[serde(with = "OrderInfoDef")]
+    info: OrderInfo,
+    #[serde(with = "amount")]
+    decayStartTime: Uint<256, 4>,
+    #[serde(with = "amount")]
+    decayEndTime: Uint<256, 4>,
+    #[serde(with = "address")]
+    exclusiveFiller: Address,
+    #[serde(with = "amount")]
+    exclusivityOverrideBps: Uint<256, 4>,
+    #[serde(with = "DutchInputDef")]
+    input: DutchInput,
+    #[serde(with = "dutch_outputs")]
+    outputs: Vec<DutchOutput>,
+}
+
+impl Serialize for ExclusiveDutchOrder {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        ExclusiveDutchOrderDef::serialize(self, serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for ExclusiveDutchOrder {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        ExclusiveDutchOrderDef::deserialize(deserializer)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::order::{Order, ResolvedInput, ResolvedOrder, ResolvedOutput};
+    use serde_json::json;
+
+    const WETH: &str = "0xC02aaA39b223FE8D0A0e5C4F27eAD9083C756Cc2";
+    const USDC: &str = "0xA0b86991c6218b36c1d19D4a2e9Eb0cE3606eB48";
+    const SWAPPER: &str = "0xd8dA6BF26964aF9D7eEd9e03E53415D37aA96045";
+
+    fn resolved_order() -> ResolvedOrder {
+        ResolvedOrder {
+            input: ResolvedInput {
+                token: WETH.to_lowercase().parse().unwrap(),
+                amount: Uint::from(1_000_000_000_000_000_000u64),
+            },
+            outputs: vec![ResolvedOutput {
+                token: USDC.to_lowercase().parse().unwrap(),
+                amount: Uint::from(1_990_000_000u64),
+                recipient: SWAPPER.to_lowercase().parse().unwrap(),
+            }],
+        }
+    }
+
+    #[test]
+    fn test_resolved_order_json() {
+        let value = serde_json::to_value(resolved_order()).unwrap();
+
+        assert_eq!(
+            value,
+            json!({
+                "input": {
+                    "token": WETH,
+                    "amount": "1000000000000000000",
+                },
+                "outputs": [{
+                    "token": USDC,
+                    "amount": "1990000000",
+                    "recipient": SWAPPER,
+                }],
+            })
+        );
+        let resolved: ResolvedOrder = serde_json::from_value(value).unwrap();
+        assert_eq!(resolved, resolved_order());
+    }
+
+    #[test]
+    fn test_resolved_output_json_invalid() {
+        let output = json!({
+            "token": USDC,
+            "amount": "0x10",
+            "recipient": SWAPPER,
+        });
+        assert!(serde_json::from_value::<ResolvedOutput>(output).is_err());
+
+        let output = json!({
+            "token": "0x1234",
+            "amount": "16",
+            "recipient": SWAPPER,
+        });
+        assert!(serde_json::from_value::<ResolvedOutput>(output).is_err());
+    }
+
+    #[test]
+    fn test_exclusive_dutch_order_json() {
+        let swapper: Address = SWAPPER.parse().unwrap();
+        let order = ExclusiveDutchOrder {
+            info: OrderInfo {
+                reactor: "0x6000da47483062A0D734Ba3dc7576Ce6A0B645C4"
+                    .parse()
+                    .unwrap(),
+                swapper,
+                nonce: Uint::from(1),
+                deadline: Uint::from(1_700_000_300),
+                additionalValidationContract: Address::default(),
+                additionalValidationData: vec![0xab, 0xcd],
+            },
+            decayStartTime: Uint::from(1_700_000_000),
+            decayEndTime: Uint::from(1_700_000_060),
+            exclusiveFiller: Address::default(),
+            exclusivityOverrideBps: Uint::from(100),
+            input: DutchInput {
+                token: WETH.parse().unwrap(),
+                startAmount: Uint::from(1000),
+                endAmount: Uint::from(1000),
+            },
+            outputs: vec![DutchOutput {
+                token: USDC.parse().unwrap(),
+                startAmount: Uint::from(2000),
+                endAmount: Uint::from(1990),
+                recipient: swapper,
+            }],
+        };
+
+        let value = serde_json::to_value(&order).unwrap();
+        assert_eq!(
+            value,
+            json!({
+                "info": {
+                    "reactor": "0x6000da47483062A0D734Ba3dc7576Ce6A0B645C4",
+                    "swapper": SWAPPER,
+                    "nonce": "1",
+                    "deadline": "1700000300",
+                    "additionalValidationContract": "0x0000000000000000000000000000000000000000",
+                    "additionalValidationData": "0xabcd",
+                },
+                "decayStartTime": "1700000000",
+                "decayEndTime": "1700000060",
+                "exclusiveFiller": "0x0000000000000000000000000000000000000000",
+                "exclusivityOverrideBps": "100",
+                "input": {
+                    "token": WETH,
+                    "startAmount": "1000",
+                    "endAmount": "1000",
+                },
+                "outputs": [{
+                    "token": USDC,
+                    "startAmount": "2000",
+                    "endAmount": "1990",
+                    "recipient": SWAPPER,
+                }],
+            })
+        );
+
+        let decoded: ExclusiveDutchOrder = serde_json::from_value(value).unwrap();
+        assert_eq!(
+            Order::ExclusiveDutch(decoded).encode(),
+            Order::ExclusiveDutch(order).encode()
+        );
+    }
+}
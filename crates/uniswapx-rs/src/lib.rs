@@ -1 +1,10 @@
+#[cfg(any(test, feature = "test-utils"))]
+pub mod builder;
+pub mod fees;
+pub mod hash;
+pub mod json;
+pub mod nonce;
 pub mod order;
+pub mod profitability;
+pub mod signature;
+pub mod validation;
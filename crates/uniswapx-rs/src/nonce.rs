@@ -0,0 +1,171 @@
+use crate::order::Order;
+use alloy_primitives::{Address, Uint};
+use anyhow::Result;
+use bindings_uniswapx::i_permit_2::IPermit2;
+use ethers::{
+    providers::Middleware,
+    types::{H160, U256},
+};
+use futures::future::join_all;
+use std::{collections::HashMap, sync::Arc};
+
+/// Where Permit2 records a swapper's unordered nonce: bit `nonce & 0xff` of the bitmap word
+/// `nonceBitmap(swapper, nonce >> 8)`. The bit is set once the nonce is spent by a fill or
+/// invalidated by the swapper.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct NonceBitmapPosition {
+    pub swapper: Address,
+    pub word: Uint<256, 4>,
+    pub bit: u8,
+}
+
+impl NonceBitmapPosition {
+    pub fn new(swapper: Address, nonce: Uint<256, 4>) -> Self {
+        Self {
+            swapper,
+            word: nonce >> 8,
+            // the lowest byte of the nonce
+            bit: nonce.as_limbs()[0] as u8,
+        }
+    }
+
+    /// Whether the nonce is used according to `bitmap`, the value of its bitmap word.
+    pub fn is_used(&self, bitmap: Uint<256, 4>) -> bool {
+        bitmap.bit(self.bit as usize)
+    }
+
+    /// Whether a Permit2 `UnorderedNonceInvalidation(owner, word, mask)` event invalidated the
+    /// nonce.
+    pub fn is_invalidated_by(&self, owner: H160, word: U256, mask: U256) -> bool {
+        let mut word_bytes = [0u8; 32];
+        word.to_big_endian(&mut word_bytes);
+        let mut mask_bytes = [0u8; 32];
+        mask.to_big_endian(&mut mask_bytes);
+        owner.as_bytes() == self.swapper.as_slice()
+            && Uint::from_be_bytes(word_bytes) == self.word
+            && self.is_used(Uint::from_be_bytes(mask_bytes))
+    }
+}
+
+impl Order {
+    pub fn nonce_bitmap_position(&self) -> NonceBitmapPosition {
+        let info = self.info();
+        NonceBitmapPosition::new(info.swapper, info.nonce)
+    }
+}
+
+/// Queries Permit2 for whether each of `positions` is used, in order. Positions that share a
+/// bitmap word, like consecutive nonces of the same swapper, are checked with a single call,
+/// and the words are queried concurrently.
+pub async fn get_used_nonces<M>(
+    client: Arc<M>,
+    permit2: Address,
+    positions: &[NonceBitmapPosition],
+) -> Result<Vec<bool>>
+where
+    M: Middleware + 'static,
+{
+    let permit2 = IPermit2::new(H160::from_slice(permit2.as_slice()), client);
+
+    let mut words: HashMap<(Address, Uint<256, 4>), usize> = HashMap::new();
+    let mut calls = Vec::new();
+    for position in positions {
+        words
+            .entry((position.swapper, position.word))
+            .or_insert_with(|| {
+                calls.push(permit2.nonce_bitmap(
+                    H160::from_slice(position.swapper.as_slice()),
+                    U256::from_big_endian(&position.word.to_be_bytes::<32>()),
+                ));
+                calls.len() - 1
+            });
+    }
+
+    let mut bitmaps = Vec::with_capacity(calls.len());
+    for bitmap in join_all(calls.iter().map(|call| call.call())).await {
+        let mut bitmap_bytes = [0u8; 32];
+        bitmap?.to_big_endian(&mut bitmap_bytes);
+        bitmaps.push(Uint::from_be_bytes(bitmap_bytes));
+    }
+    Ok(positions
+        .iter()
+        .map(|position| position.is_used(bitmaps[words[&(position.swapper, position.word)]]))
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ethers::{providers::Provider, types::Bytes};
+
+    fn encode_bitmap(bitmap: Uint<256, 4>) -> Bytes {
+        Bytes::from(bitmap.to_be_bytes::<32>().to_vec())
+    }
+
+    #[test]
+    fn test_nonce_bitmap_position() {
+        let swapper = Address::from([2u8; 20]);
+
+        let position = NonceBitmapPosition::new(swapper, Uint::from(0));
+        assert_eq!(position.word, Uint::from(0));
+        assert_eq!(position.bit, 0);
+
+        let position = NonceBitmapPosition::new(swapper, Uint::from(255));
+        assert_eq!(position.word, Uint::from(0));
+        assert_eq!(position.bit, 255);
+
+        let position = NonceBitmapPosition::new(swapper, Uint::from(0x1234));
+        assert_eq!(position.word, Uint::from(0x12));
+        assert_eq!(position.bit, 0x34);
+
+        let position = NonceBitmapPosition::new(swapper, Uint::MAX);
+        assert_eq!(position.word, Uint::MAX >> 8);
+        assert_eq!(position.bit, 255);
+    }
+
+    #[test]
+    fn test_nonce_bitmap_position_is_used() {
+        let position = NonceBitmapPosition::new(Address::from([2u8; 20]), Uint::from(0x0103));
+
+        assert!(position.is_used(Uint::from(0b1000)));
+        assert!(!position.is_used(Uint::from(0b0111)));
+        assert!(!position.is_used(Uint::ZERO));
+    }
+
+    #[test]
+    fn test_nonce_bitmap_position_is_invalidated_by() {
+        let swapper = Address::from([2u8; 20]);
+        let owner = H160::from([2u8; 20]);
+        let position = NonceBitmapPosition::new(swapper, Uint::from(0x0103));
+
+        assert!(position.is_invalidated_by(owner, U256::from(1), U256::from(0b1000)));
+        assert!(!position.is_invalidated_by(owner, U256::from(1), U256::from(0b0111)));
+        assert!(!position.is_invalidated_by(owner, U256::from(0), U256::from(0b1000)));
+        assert!(!position.is_invalidated_by(
+            H160::from([3u8; 20]),
+            U256::from(1),
+            U256::from(0b1000)
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_get_used_nonces() {
+        let (provider, mock) = Provider::mocked();
+        let swapper = Address::from([2u8; 20]);
+        let positions = [
+            NonceBitmapPosition::new(swapper, Uint::from(1)),
+            NonceBitmapPosition::new(swapper, Uint::from(256)),
+            NonceBitmapPosition::new(swapper, Uint::from(2)),
+        ];
+        // responses are returned last in first out, and nonces 1 and 2 share the first word
+        mock.push::<Bytes, _>(encode_bitmap(Uint::from(0b1)))
+            .unwrap();
+        mock.push::<Bytes, _>(encode_bitmap(Uint::from(0b010)))
+            .unwrap();
+
+        let used = get_used_nonces(Arc::new(provider), Address::from([8u8; 20]), &positions)
+            .await
+            .unwrap();
+        assert_eq!(used, vec![true, true, false]);
+    }
+}
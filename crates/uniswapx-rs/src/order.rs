@@ -1,6 +1,9 @@
-use alloy_primitives::Uint;
+use alloy_primitives::{Address, Uint};
 use alloy_sol_types::{sol, SolType};
-use anyhow::Result;
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::json;
 
 sol! {
     #[derive(Debug)]
@@ -28,6 +31,15 @@ sol! {
         uint256 endAmount;
     }
 
+    #[derive(Debug)]
+    struct DutchOrder {
+        OrderInfo info;
+        uint256 decayStartTime;
+        uint256 decayEndTime;
+        DutchInput input;
+        DutchOutput[] outputs;
+    }
+
     #[derive(Debug)]
     struct ExclusiveDutchOrder {
         OrderInfo info;
@@ -38,15 +50,125 @@ sol! {
         DutchInput input;
         DutchOutput[] outputs;
     }
+
+    #[derive(Debug)]
+    struct CosignerData {
+        uint256 decayStartTime;
+        uint256 decayEndTime;
+        address exclusiveFiller;
+        uint256 exclusivityOverrideBps;
+        uint256 inputOverride;
+        uint256[] outputOverrides;
+    }
+
+    #[derive(Debug)]
+    struct V2DutchOrder {
+        OrderInfo info;
+        address cosigner;
+        DutchInput baseInput;
+        DutchOutput[] baseOutputs;
+        CosignerData cosignerData;
+        bytes cosignature;
+    }
+
+    #[derive(Debug)]
+    struct PriorityInput {
+        address token;
+        uint256 amount;
+        uint256 mpsPerPriorityFeeWei;
+    }
+
+    #[derive(Debug)]
+    struct PriorityOutput {
+        address token;
+        uint256 amount;
+        uint256 mpsPerPriorityFeeWei;
+        address recipient;
+    }
+
+    #[derive(Debug)]
+    struct PriorityCosignerData {
+        uint256 auctionTargetBlock;
+    }
+
+    #[derive(Debug)]
+    struct PriorityOrder {
+        OrderInfo info;
+        address cosigner;
+        uint256 auctionStartBlock;
+        uint256 baselinePriorityFeeWei;
+        PriorityInput input;
+        PriorityOutput[] outputs;
+        PriorityCosignerData cosignerData;
+        bytes cosignature;
+    }
+
+    #[derive(Debug)]
+    struct InputToken {
+        address token;
+        uint256 amount;
+        uint256 maxAmount;
+    }
+
+    #[derive(Debug)]
+    struct OutputToken {
+        address token;
+        uint256 amount;
+        address recipient;
+    }
+
+    #[derive(Debug)]
+    struct LimitOrder {
+        OrderInfo info;
+        InputToken input;
+        OutputToken[] outputs;
+    }
 }
 
-pub fn decode_order(encoded_order: &str) -> Result<ExclusiveDutchOrder> {
-    let encoded_order = if encoded_order.starts_with("0x") {
-        &encoded_order[2..]
+/// Known reactor deployments, used to detect the type of an encoded order.
+const KNOWN_REACTORS: [(&str, OrderType); 5] = [
+    // mainnet
+    (
+        "0xe80bF394d190851E215D5F67B67f8F5A52783F1E",
+        OrderType::ExclusiveDutch,
+    ),
+    (
+        "0x6000da47483062A0D734Ba3dc7576Ce6A0B645C4",
+        OrderType::ExclusiveDutch,
+    ),
+    (
+        "0x00000011F84B9aa48e5f8aA8B9897600006289Be",
+        OrderType::V2Dutch,
+    ),
+    // arbitrum
+    (
+        "0x1bd1aAdc9E230626C44a139d7E70d842749351eb",
+        OrderType::V2Dutch,
+    ),
+    // base
+    (
+        "0x000000001Ec5656dcdB24D90DFa42742738De729",
+        OrderType::Priority,
+    ),
+];
+
+/// Milli-basis points, the unit PriorityFeeLib scales amounts in.
+const MPS: u64 = 10_000_000;
+
+/// Basis points, the unit of the exclusivity override.
+pub(crate) const BPS: u64 = 10_000;
+
+fn decode_hex(encoded: &str) -> Result<Vec<u8>> {
+    let encoded = if encoded.starts_with("0x") {
+        &encoded[2..]
     } else {
-        encoded_order
+        encoded
     };
-    let order_hex = hex::decode(encoded_order)?;
+    Ok(hex::decode(encoded)?)
+}
+
+pub fn decode_order(encoded_order: &str) -> Result<ExclusiveDutchOrder> {
+    let order_hex = decode_hex(encoded_order)?;
 
     Ok(ExclusiveDutchOrder::decode(&order_hex, false)?)
 }
@@ -55,20 +177,179 @@ pub fn encode_order(order: &ExclusiveDutchOrder) -> Vec<u8> {
     ExclusiveDutchOrder::encode(order)
 }
 
+pub fn decode_v2_dutch_order(encoded_order: &str) -> Result<V2DutchOrder> {
+    let order_hex = decode_hex(encoded_order)?;
+
+    Ok(V2DutchOrder::decode(&order_hex, false)?)
+}
+
+pub fn encode_v2_dutch_order(order: &V2DutchOrder) -> Vec<u8> {
+    V2DutchOrder::encode(order)
+}
+
+pub fn decode_priority_order(encoded_order: &str) -> Result<PriorityOrder> {
+    let order_hex = decode_hex(encoded_order)?;
+
+    Ok(PriorityOrder::decode(&order_hex, false)?)
+}
+
+pub fn encode_priority_order(order: &PriorityOrder) -> Vec<u8> {
+    PriorityOrder::encode(order)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum OrderType {
+    ExclusiveDutch,
+    Dutch,
+    V2Dutch,
+    Priority,
+    Limit,
+}
+
+impl OrderType {
+    /// Maps the `type` field of the UniswapX API to an order type.
+    pub fn from_api_type(order_type: &str) -> Option<Self> {
+        match order_type {
+            "Dutch" | "DutchLimit" => Some(OrderType::ExclusiveDutch),
+            "Dutch_V2" => Some(OrderType::V2Dutch),
+            "Priority" => Some(OrderType::Priority),
+            "Limit" => Some(OrderType::Limit),
+            _ => None,
+        }
+    }
+
+    /// Looks up the order type of a known reactor deployment.
+    pub fn from_reactor(reactor: Address) -> Option<Self> {
+        KNOWN_REACTORS
+            .iter()
+            .find(|(address, _)| address.parse::<Address>().ok() == Some(reactor))
+            .map(|(_, order_type)| *order_type)
+    }
+}
+
+/// Any order type supported by the UniswapX reactors.
 #[derive(Debug, Clone)]
+pub enum Order {
+    ExclusiveDutch(ExclusiveDutchOrder),
+    Dutch(DutchOrder),
+    V2Dutch(V2DutchOrder),
+    Priority(PriorityOrder),
+    Limit(LimitOrder),
+}
+
+/// The chain state an order is resolved against.
+#[derive(Debug, Clone, Copy)]
+pub struct ResolutionParams {
+    pub block_number: u64,
+    pub timestamp: u64,
+    /// priority fee per gas paid above the base fee, only used by priority orders
+    pub priority_fee: Uint<256, 4>,
+    /// the address calling the reactor, which exclusive orders are checked against
+    pub filler: Address,
+}
+
+impl Order {
+    /// Decodes an order, detecting its type from the reactor in its `OrderInfo`.
+    /// Falls back to `order_type`, e.g. from the API, if the reactor is unknown.
+    pub fn decode(encoded_order: &str, order_type: Option<OrderType>) -> Result<Self> {
+        let order_hex = decode_hex(encoded_order)?;
+        let order_type = decode_reactor(&order_hex)
+            .and_then(OrderType::from_reactor)
+            .or(order_type)
+            .ok_or_else(|| anyhow!("unable to detect order type"))?;
+
+        Ok(match order_type {
+            OrderType::ExclusiveDutch => {
+                Order::ExclusiveDutch(ExclusiveDutchOrder::decode(&order_hex, false)?)
+            }
+            OrderType::Dutch => Order::Dutch(DutchOrder::decode(&order_hex, false)?),
+            OrderType::V2Dutch => Order::V2Dutch(V2DutchOrder::decode(&order_hex, false)?),
+            OrderType::Priority => Order::Priority(PriorityOrder::decode(&order_hex, false)?),
+            OrderType::Limit => Order::Limit(LimitOrder::decode(&order_hex, false)?),
+        })
+    }
+
+    pub fn encode(&self) -> Vec<u8> {
+        match self {
+            Order::ExclusiveDutch(order) => ExclusiveDutchOrder::encode(order),
+            Order::Dutch(order) => DutchOrder::encode(order),
+            Order::V2Dutch(order) => V2DutchOrder::encode(order),
+            Order::Priority(order) => PriorityOrder::encode(order),
+            Order::Limit(order) => LimitOrder::encode(order),
+        }
+    }
+
+    pub fn order_type(&self) -> OrderType {
+        match self {
+            Order::ExclusiveDutch(_) => OrderType::ExclusiveDutch,
+            Order::Dutch(_) => OrderType::Dutch,
+            Order::V2Dutch(_) => OrderType::V2Dutch,
+            Order::Priority(_) => OrderType::Priority,
+            Order::Limit(_) => OrderType::Limit,
+        }
+    }
+
+    pub fn info(&self) -> &OrderInfo {
+        match self {
+            Order::ExclusiveDutch(order) => &order.info,
+            Order::Dutch(order) => &order.info,
+            Order::V2Dutch(order) => &order.info,
+            Order::Priority(order) => &order.info,
+            Order::Limit(order) => &order.info,
+        }
+    }
+
+    pub fn resolve(&self, params: &ResolutionParams) -> OrderResolution {
+        match self {
+            Order::ExclusiveDutch(order) => order.resolve(params.timestamp, params.filler),
+            Order::Dutch(order) => order.resolve(params.timestamp),
+            Order::V2Dutch(order) => order.resolve(params.timestamp, params.filler),
+            Order::Priority(order) => {
+                order.resolve(params.block_number, params.timestamp, params.priority_fee)
+            }
+            Order::Limit(order) => order.resolve(params.timestamp),
+        }
+    }
+}
+
+// every order is abi encoded as a dynamic tuple whose first member is the dynamic OrderInfo,
+// so the reactor can be read before knowing the order type
+fn decode_reactor(order_hex: &[u8]) -> Option<Address> {
+    let order_offset = decode_offset(order_hex, 0)?;
+    let info_offset = order_offset.checked_add(decode_offset(order_hex, order_offset)?)?;
+    let reactor = order_hex.get(info_offset.checked_add(12)?..info_offset.checked_add(32)?)?;
+    Some(Address::from_slice(reactor))
+}
+
+fn decode_offset(data: &[u8], at: usize) -> Option<usize> {
+    let word = data.get(at..at.checked_add(32)?)?;
+    if word[..24].iter().any(|byte| *byte != 0) {
+        return None;
+    }
+    let mut offset = [0u8; 8];
+    offset.copy_from_slice(&word[24..]);
+    usize::try_from(u64::from_be_bytes(offset)).ok()
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct ResolvedInput {
-    pub token: String,
+    #[serde(with = "json::address")]
+    pub token: Address,
+    #[serde(with = "json::amount")]
     pub amount: Uint<256, 4>,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct ResolvedOutput {
-    pub token: String,
+    #[serde(with = "json::address")]
+    pub token: Address,
+    #[serde(with = "json::amount")]
     pub amount: Uint<256, 4>,
-    pub recipient: String,
+    #[serde(with = "json::address")]
+    pub recipient: Address,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct ResolvedOrder {
     pub input: ResolvedInput,
     pub outputs: Vec<ResolvedOutput>,
@@ -79,53 +360,143 @@ pub enum OrderResolution {
     Resolved(ResolvedOrder),
     Expired,
     Invalid,
+    // the auction for the order has not started yet
+    NotFillableYet,
+    // the order is strictly exclusive to another filler until its exclusivity window ends
+    ExclusiveToOtherFiller,
 }
 
 impl ExclusiveDutchOrder {
-    pub fn resolve(&self, timestamp: u64) -> OrderResolution {
+    /// Resolves the order for a fill by `filler` at `timestamp`. Until `decayStartTime` only
+    /// the exclusive filler fills at the decayed amounts, other fillers pay the exclusivity
+    /// override on top, or can't fill at all if the order has none.
+    pub fn resolve(&self, timestamp: u64, filler: Address) -> OrderResolution {
         let timestamp = Uint::from(timestamp);
 
         if self.info.deadline.lt(&timestamp) {
             return OrderResolution::Expired;
         };
 
+        let override_outputs =
+            !has_filling_rights(self.exclusiveFiller, self.decayStartTime, timestamp, filler);
+        if override_outputs && self.exclusivityOverrideBps.is_zero() {
+            return OrderResolution::ExclusiveToOtherFiller;
+        }
+
         // resolve over the decay curve
 
         let input = ResolvedInput {
-            token: self.input.token.to_string(),
-            amount: resolve_decay(
+            token: self.input.token,
+            amount: match resolve_decay(
                 timestamp,
                 self.decayStartTime,
                 self.decayEndTime,
                 self.input.startAmount,
                 self.input.endAmount,
-            ),
+            ) {
+                Some(amount) => amount,
+                None => return OrderResolution::Invalid,
+            },
+        };
+
+        let mut outputs = Vec::with_capacity(self.outputs.len());
+        for output in self.outputs.iter() {
+            let mut amount = match resolve_decay(
+                timestamp,
+                self.decayStartTime,
+                self.decayEndTime,
+                output.startAmount,
+                output.endAmount,
+            ) {
+                Some(amount) => amount,
+                None => return OrderResolution::Invalid,
+            };
+
+            // add exclusivity override to amount
+            if override_outputs {
+                amount = match apply_exclusivity_override(amount, self.exclusivityOverrideBps) {
+                    Some(amount) => amount,
+                    None => return OrderResolution::Invalid,
+                };
+            };
+
+            outputs.push(ResolvedOutput {
+                token: output.token,
+                amount,
+                recipient: output.recipient,
+            });
+        }
+
+        OrderResolution::Resolved(ResolvedOrder { input, outputs })
+    }
+}
+
+impl DutchOrder {
+    pub fn resolve(&self, timestamp: u64) -> OrderResolution {
+        let timestamp = Uint::from(timestamp);
+
+        if self.info.deadline.lt(&timestamp) {
+            return OrderResolution::Expired;
+        };
+
+        let input = ResolvedInput {
+            token: self.input.token,
+            amount: match resolve_decay(
+                timestamp,
+                self.decayStartTime,
+                self.decayEndTime,
+                self.input.startAmount,
+                self.input.endAmount,
+            ) {
+                Some(amount) => amount,
+                None => return OrderResolution::Invalid,
+            },
+        };
+
+        let mut outputs = Vec::with_capacity(self.outputs.len());
+        for output in self.outputs.iter() {
+            let amount = match resolve_decay(
+                timestamp,
+                self.decayStartTime,
+                self.decayEndTime,
+                output.startAmount,
+                output.endAmount,
+            ) {
+                Some(amount) => amount,
+                None => return OrderResolution::Invalid,
+            };
+
+            outputs.push(ResolvedOutput {
+                token: output.token,
+                amount,
+                recipient: output.recipient,
+            });
+        }
+
+        OrderResolution::Resolved(ResolvedOrder { input, outputs })
+    }
+}
+
+impl LimitOrder {
+    pub fn resolve(&self, timestamp: u64) -> OrderResolution {
+        let timestamp = Uint::from(timestamp);
+
+        if self.info.deadline.lt(&timestamp) {
+            return OrderResolution::Expired;
+        };
+
+        let input = ResolvedInput {
+            token: self.input.token,
+            amount: self.input.amount,
         };
 
         let outputs = self
             .outputs
             .iter()
-            .map(|output| {
-                let mut amount = resolve_decay(
-                    timestamp,
-                    self.decayStartTime,
-                    self.decayEndTime,
-                    output.startAmount,
-                    output.endAmount,
-                );
-
-                // add exclusivity override to amount
-                if self.decayStartTime.gt(&timestamp) && !self.exclusiveFiller.is_zero() {
-                    let exclusivity = self.exclusivityOverrideBps.wrapping_add(Uint::from(10000));
-                    let exclusivity = exclusivity.wrapping_mul(amount);
-                    amount = exclusivity.wrapping_div(Uint::from(10000));
-                };
-
-                ResolvedOutput {
-                    token: output.token.to_string(),
-                    amount,
-                    recipient: output.recipient.to_string(),
-                }
+            .map(|output| ResolvedOutput {
+                token: output.token,
+                amount: output.amount,
+                recipient: output.recipient,
             })
             .collect();
 
@@ -133,52 +504,277 @@ impl ExclusiveDutchOrder {
     }
 }
 
+impl V2DutchOrder {
+    /// Resolves the order the same way the V2DutchOrderReactor does: cosigner overrides are
+    /// applied to the base amounts first, then amounts decay over the cosigned decay window
+    /// and fillers other than the exclusive filler pay the exclusivity override until
+    /// `decayStartTime`.
+    pub fn resolve(&self, timestamp: u64, filler: Address) -> OrderResolution {
+        let timestamp = Uint::from(timestamp);
+
+        if self.info.deadline.lt(&timestamp) {
+            return OrderResolution::Expired;
+        };
+
+        let cosigner_data = &self.cosignerData;
+
+        let override_outputs = !has_filling_rights(
+            cosigner_data.exclusiveFiller,
+            cosigner_data.decayStartTime,
+            timestamp,
+            filler,
+        );
+        if override_outputs && cosigner_data.exclusivityOverrideBps.is_zero() {
+            return OrderResolution::ExclusiveToOtherFiller;
+        }
+
+        // the cosigner may only improve the input amount for the swapper
+        let mut input_start_amount = self.baseInput.startAmount;
+        if !cosigner_data.inputOverride.is_zero() {
+            if cosigner_data.inputOverride.gt(&input_start_amount) {
+                return OrderResolution::Invalid;
+            }
+            input_start_amount = cosigner_data.inputOverride;
+        }
+
+        // the reactor needs an override, zero for none, for every output
+        if cosigner_data.outputOverrides.len() != self.baseOutputs.len() {
+            return OrderResolution::Invalid;
+        }
+
+        let input = ResolvedInput {
+            token: self.baseInput.token,
+            amount: match resolve_decay(
+                timestamp,
+                cosigner_data.decayStartTime,
+                cosigner_data.decayEndTime,
+                input_start_amount,
+                self.baseInput.endAmount,
+            ) {
+                Some(amount) => amount,
+                None => return OrderResolution::Invalid,
+            },
+        };
+
+        let mut outputs = Vec::with_capacity(self.baseOutputs.len());
+        for (i, output) in self.baseOutputs.iter().enumerate() {
+            // the cosigner may only improve output amounts for the swapper
+            let mut output_start_amount = output.startAmount;
+            if let Some(output_override) = cosigner_data.outputOverrides.get(i) {
+                if !output_override.is_zero() {
+                    if output_override.lt(&output_start_amount) {
+                        return OrderResolution::Invalid;
+                    }
+                    output_start_amount = *output_override;
+                }
+            }
+
+            let mut amount = match resolve_decay(
+                timestamp,
+                cosigner_data.decayStartTime,
+                cosigner_data.decayEndTime,
+                output_start_amount,
+                output.endAmount,
+            ) {
+                Some(amount) => amount,
+                None => return OrderResolution::Invalid,
+            };
+
+            // add exclusivity override to amount
+            if override_outputs {
+                amount = match apply_exclusivity_override(
+                    amount,
+                    cosigner_data.exclusivityOverrideBps,
+                ) {
+                    Some(amount) => amount,
+                    None => return OrderResolution::Invalid,
+                };
+            };
+
+            outputs.push(ResolvedOutput {
+                token: output.token,
+                amount,
+                recipient: output.recipient,
+            });
+        }
+
+        OrderResolution::Resolved(ResolvedOrder { input, outputs })
+    }
+}
+
+impl PriorityOrder {
+    /// Resolves the order the same way the PriorityOrderReactor does for a fill in
+    /// `block_number` at `timestamp`, paying `priority_fee` wei per gas above the base fee.
+    pub fn resolve(
+        &self,
+        block_number: u64,
+        timestamp: u64,
+        priority_fee: Uint<256, 4>,
+    ) -> OrderResolution {
+        let timestamp = Uint::from(timestamp);
+
+        if self.info.deadline.lt(&timestamp) {
+            return OrderResolution::Expired;
+        };
+
+        if !self.input.mpsPerPriorityFeeWei.is_zero()
+            && self
+                .outputs
+                .iter()
+                .any(|output| !output.mpsPerPriorityFeeWei.is_zero())
+        {
+            return OrderResolution::Invalid;
+        }
+
+        if Uint::from(block_number).lt(&self.auction_start_block()) {
+            return OrderResolution::NotFillableYet;
+        }
+
+        // only the priority fee above the baseline is used to scale amounts
+        let priority_fee = priority_fee.saturating_sub(self.baselinePriorityFeeWei);
+
+        let input_scaling = match priority_fee.checked_mul(self.input.mpsPerPriorityFeeWei) {
+            Some(scaling) => scaling,
+            None => return OrderResolution::Invalid,
+        };
+        let input_amount = if input_scaling.ge(&Uint::from(MPS)) {
+            Uint::ZERO
+        } else {
+            match mul_div_down(
+                self.input.amount,
+                Uint::from(MPS).wrapping_sub(input_scaling),
+                Uint::from(MPS),
+            ) {
+                Some(amount) => amount,
+                None => return OrderResolution::Invalid,
+            }
+        };
+
+        let input = ResolvedInput {
+            token: self.input.token,
+            amount: input_amount,
+        };
+
+        let mut outputs = Vec::with_capacity(self.outputs.len());
+        for output in self.outputs.iter() {
+            let amount = match priority_fee
+                .checked_mul(output.mpsPerPriorityFeeWei)
+                .and_then(|scaling| scaling.checked_add(Uint::from(MPS)))
+                .and_then(|scaling| mul_div_up(output.amount, scaling, Uint::from(MPS)))
+            {
+                Some(amount) => amount,
+                None => return OrderResolution::Invalid,
+            };
+
+            outputs.push(ResolvedOutput {
+                token: output.token,
+                amount,
+                recipient: output.recipient,
+            });
+        }
+
+        OrderResolution::Resolved(ResolvedOrder { input, outputs })
+    }
+
+    /// The block the auction opens at, taking the cosigned target block into account.
+    /// The cosigner may only move the auction start earlier.
+    pub fn auction_start_block(&self) -> Uint<256, 4> {
+        if self.has_cosigner_override() {
+            self.cosignerData.auctionTargetBlock
+        } else {
+            self.auctionStartBlock
+        }
+    }
+
+    /// Whether the reactor applies the cosigned target block, which it skips for orders
+    /// without a cosigner.
+    pub(crate) fn has_cosigner_override(&self) -> bool {
+        let target_block = self.cosignerData.auctionTargetBlock;
+        !self.cosigner.is_zero()
+            && !target_block.is_zero()
+            && target_block.lt(&self.auctionStartBlock)
+    }
+}
+
+pub(crate) fn mul_div_down(
+    x: Uint<256, 4>,
+    y: Uint<256, 4>,
+    denominator: Uint<256, 4>,
+) -> Option<Uint<256, 4>> {
+    x.checked_mul(y)?.checked_div(denominator)
+}
+
+fn mul_div_up(x: Uint<256, 4>, y: Uint<256, 4>, denominator: Uint<256, 4>) -> Option<Uint<256, 4>> {
+    let product = x.checked_mul(y)?;
+    let quotient = product.checked_div(denominator)?;
+    if (product % denominator).is_zero() {
+        Some(quotient)
+    } else {
+        quotient.checked_add(Uint::from(1))
+    }
+}
+
+/// Decays an amount linearly between the start and end time, matching DutchDecayLib:
+/// amounts round towards the start amount and overflows revert, which resolves to `None`.
 fn resolve_decay(
     at_time: Uint<256, 4>,
     start_time: Uint<256, 4>,
     end_time: Uint<256, 4>,
     start_amount: Uint<256, 4>,
     end_amount: Uint<256, 4>,
-) -> Uint<256, 4> {
-    if end_time.le(&at_time) {
-        return end_amount;
-    }
-
-    if at_time.le(&start_time) {
-        return start_amount;
+) -> Option<Uint<256, 4>> {
+    if end_time.lt(&start_time) {
+        return None;
     }
 
-    if end_time.eq(&start_time) {
-        return start_amount;
+    if end_time.le(&at_time) {
+        return Some(end_amount);
     }
 
-    if start_amount.eq(&end_amount) {
-        return start_amount;
+    if at_time.le(&start_time) {
+        return Some(start_amount);
     }
 
-    let duration = end_time.wrapping_sub(start_time);
-    let elapsed = at_time.wrapping_sub(start_time);
-    // TODO: better handle overflows
-    if start_amount.gt(&end_amount) {
+    // start_time < at_time < end_time, so neither can underflow
+    let duration = end_time - start_time;
+    let elapsed = at_time - start_time;
+    if end_amount.lt(&start_amount) {
         // decaying downward
-        let decay = start_amount
-            .wrapping_sub(end_amount)
-            .wrapping_mul(elapsed)
-            .wrapping_div(duration);
-        return start_amount.wrapping_sub(decay);
+        let decay = mul_div_down(start_amount - end_amount, elapsed, duration)?;
+        Some(start_amount - decay)
     } else {
         // decaying upward
-        let decay = end_amount
-            .wrapping_sub(start_amount)
-            .wrapping_mul(elapsed)
-            .wrapping_div(duration);
-        return start_amount.wrapping_add(decay);
+        let decay = mul_div_down(end_amount - start_amount, elapsed, duration)?;
+        Some(start_amount + decay)
     }
 }
 
+/// Whether `filler` may fill at the decayed amounts, as in ExclusivityLib: anyone may once the
+/// exclusivity window has ended or if the order has no exclusive filler.
+fn has_filling_rights(
+    exclusive_filler: Address,
+    exclusivity_end_time: Uint<256, 4>,
+    timestamp: Uint<256, 4>,
+    filler: Address,
+) -> bool {
+    exclusive_filler.is_zero() || timestamp.gt(&exclusivity_end_time) || exclusive_filler == filler
+}
+
+/// Scales an output up by the exclusivity override, rounding up as in ExclusivityLib.
+fn apply_exclusivity_override(
+    amount: Uint<256, 4>,
+    override_bps: Uint<256, 4>,
+) -> Option<Uint<256, 4>> {
+    mul_div_up(
+        amount,
+        Uint::from(BPS).checked_add(override_bps)?,
+        Uint::from(BPS),
+    )
+}
+
 // tests
 #[cfg(test)]
-mod tests {
+pub(crate) mod tests {
     use super::*;
 
     #[test]
@@ -190,7 +786,8 @@ mod tests {
 
         let at_time = Uint::from(11);
 
-        let result = resolve_decay(at_time, start_time, end_time, start_amount, end_amount);
+        let result =
+            resolve_decay(at_time, start_time, end_time, start_amount, end_amount).unwrap();
 
         assert_eq!(result, end_amount);
     }
@@ -204,7 +801,8 @@ mod tests {
 
         let at_time = Uint::from(10);
 
-        let result = resolve_decay(at_time, start_time, end_time, start_amount, end_amount);
+        let result =
+            resolve_decay(at_time, start_time, end_time, start_amount, end_amount).unwrap();
 
         assert_eq!(result, end_amount);
     }
@@ -218,7 +816,8 @@ mod tests {
 
         let at_time = Uint::from(5);
 
-        let result = resolve_decay(at_time, start_time, end_time, start_amount, end_amount);
+        let result =
+            resolve_decay(at_time, start_time, end_time, start_amount, end_amount).unwrap();
 
         assert_eq!(result, start_amount);
     }
@@ -232,7 +831,8 @@ mod tests {
 
         let at_time = Uint::from(10);
 
-        let result = resolve_decay(at_time, start_time, end_time, start_amount, end_amount);
+        let result =
+            resolve_decay(at_time, start_time, end_time, start_amount, end_amount).unwrap();
 
         assert_eq!(result, start_amount);
     }
@@ -246,7 +846,8 @@ mod tests {
 
         let at_time = Uint::from(15);
 
-        let result = resolve_decay(at_time, start_time, end_time, start_amount, end_amount);
+        let result =
+            resolve_decay(at_time, start_time, end_time, start_amount, end_amount).unwrap();
 
         assert_eq!(result, Uint::from(150000));
     }
@@ -260,8 +861,437 @@ mod tests {
 
         let at_time = Uint::from(15);
 
-        let result = resolve_decay(at_time, start_time, end_time, start_amount, end_amount);
+        let result =
+            resolve_decay(at_time, start_time, end_time, start_amount, end_amount).unwrap();
 
         assert_eq!(result, Uint::from(150000));
     }
+
+    #[test]
+    fn test_decay_rounds_towards_start_amount() {
+        let start_time = Uint::from(0);
+        let end_time = Uint::from(3);
+        let at_time = Uint::from(1);
+
+        // 100 / 3 rounds down in both directions, as mulDivDown does
+        let downward = resolve_decay(
+            at_time,
+            start_time,
+            end_time,
+            Uint::from(100),
+            Uint::from(0),
+        )
+        .unwrap();
+        let upward = resolve_decay(
+            at_time,
+            start_time,
+            end_time,
+            Uint::from(0),
+            Uint::from(100),
+        )
+        .unwrap();
+
+        assert_eq!(downward, Uint::from(67));
+        assert_eq!(upward, Uint::from(33));
+    }
+
+    #[test]
+    fn test_decay_end_time_before_start_time() {
+        let result = resolve_decay(
+            Uint::from(15),
+            Uint::from(20),
+            Uint::from(10),
+            Uint::from(100),
+            Uint::from(200),
+        );
+
+        assert_eq!(result, None);
+    }
+
+    #[test]
+    fn test_decay_overflow() {
+        // (end - start) * elapsed overflows uint256, which reverts in mulDivDown
+        let result = resolve_decay(
+            Uint::from(2),
+            Uint::from(0),
+            Uint::from(3),
+            Uint::from(0),
+            Uint::MAX,
+        );
+
+        assert_eq!(result, None);
+    }
+
+    #[test]
+    fn test_v2_resolve_exclusivity_override_overflow() {
+        let mut data = cosigner_data(vec![Uint::ZERO]);
+        data.exclusiveFiller = Address::from([6u8; 20]);
+        data.exclusivityOverrideBps = Uint::MAX;
+        let order = v2_order(data);
+
+        assert!(matches!(
+            order.resolve(5, filler()),
+            OrderResolution::Invalid
+        ));
+    }
+
+    pub(crate) fn filler() -> Address {
+        Address::from([7u8; 20])
+    }
+
+    pub(crate) fn v2_order(cosigner_data: CosignerData) -> V2DutchOrder {
+        V2DutchOrder {
+            info: OrderInfo {
+                reactor: Address::from([1u8; 20]),
+                swapper: Address::from([2u8; 20]),
+                nonce: Uint::from(1),
+                deadline: Uint::from(100),
+                additionalValidationContract: Address::default(),
+                additionalValidationData: vec![],
+            },
+            cosigner: Address::from([3u8; 20]),
+            baseInput: DutchInput {
+                token: Address::from([4u8; 20]),
+                startAmount: Uint::from(1000),
+                endAmount: Uint::from(1000),
+            },
+            baseOutputs: vec![DutchOutput {
+                token: Address::from([5u8; 20]),
+                startAmount: Uint::from(2000),
+                endAmount: Uint::from(1000),
+                recipient: Address::from([2u8; 20]),
+            }],
+            cosignerData: cosigner_data,
+            cosignature: vec![],
+        }
+    }
+
+    pub(crate) fn cosigner_data(output_overrides: Vec<Uint<256, 4>>) -> CosignerData {
+        CosignerData {
+            decayStartTime: Uint::from(10),
+            decayEndTime: Uint::from(20),
+            exclusiveFiller: Address::default(),
+            exclusivityOverrideBps: Uint::from(0),
+            inputOverride: Uint::from(0),
+            outputOverrides: output_overrides,
+        }
+    }
+
+    #[test]
+    fn test_v2_encode_decode() {
+        let order = v2_order(cosigner_data(vec![Uint::from(2500)]));
+        let encoded = format!("0x{}", hex::encode(encode_v2_dutch_order(&order)));
+
+        let decoded = decode_v2_dutch_order(&encoded).unwrap();
+
+        assert_eq!(decoded.cosigner, order.cosigner);
+        assert_eq!(decoded.baseOutputs[0].startAmount, Uint::from(2000));
+        assert_eq!(decoded.cosignerData.outputOverrides, vec![Uint::from(2500)]);
+    }
+
+    #[test]
+    fn test_v2_resolve_output_override() {
+        let order = v2_order(cosigner_data(vec![Uint::from(3000)]));
+
+        match order.resolve(15, filler()) {
+            OrderResolution::Resolved(resolved) => {
+                assert_eq!(resolved.input.amount, Uint::from(1000));
+                assert_eq!(resolved.outputs[0].amount, Uint::from(2000));
+            }
+            _ => panic!("expected order to resolve"),
+        }
+    }
+
+    #[test]
+    fn test_v2_resolve_invalid_output_override() {
+        let order = v2_order(cosigner_data(vec![Uint::from(1500)]));
+
+        assert!(matches!(
+            order.resolve(15, filler()),
+            OrderResolution::Invalid
+        ));
+    }
+
+    #[test]
+    fn test_v2_resolve_output_override_count() {
+        let order = v2_order(cosigner_data(vec![]));
+        assert!(matches!(
+            order.resolve(15, filler()),
+            OrderResolution::Invalid
+        ));
+
+        let order = v2_order(cosigner_data(vec![Uint::ZERO, Uint::ZERO]));
+        assert!(matches!(
+            order.resolve(15, filler()),
+            OrderResolution::Invalid
+        ));
+    }
+
+    #[test]
+    fn test_v2_resolve_exclusivity_override() {
+        let mut data = cosigner_data(vec![Uint::ZERO]);
+        data.exclusiveFiller = Address::from([6u8; 20]);
+        data.exclusivityOverrideBps = Uint::from(100);
+        let order = v2_order(data);
+
+        match order.resolve(5, filler()) {
+            OrderResolution::Resolved(resolved) => {
+                assert_eq!(resolved.outputs[0].amount, Uint::from(2020));
+            }
+            _ => panic!("expected order to resolve"),
+        }
+    }
+
+    #[test]
+    fn test_v2_resolve_exclusive_filler() {
+        let mut data = cosigner_data(vec![Uint::ZERO]);
+        data.exclusiveFiller = filler();
+        data.exclusivityOverrideBps = Uint::from(100);
+        let order = v2_order(data);
+
+        match order.resolve(5, filler()) {
+            OrderResolution::Resolved(resolved) => {
+                assert_eq!(resolved.outputs[0].amount, Uint::from(2000));
+            }
+            _ => panic!("expected order to resolve"),
+        }
+    }
+
+    #[test]
+    fn test_v2_resolve_strict_exclusivity() {
+        let mut data = cosigner_data(vec![Uint::ZERO]);
+        data.exclusiveFiller = Address::from([6u8; 20]);
+        let order = v2_order(data);
+
+        // the exclusivity window includes decayStartTime
+        assert!(matches!(
+            order.resolve(10, filler()),
+            OrderResolution::ExclusiveToOtherFiller
+        ));
+        assert!(matches!(
+            order.resolve(11, filler()),
+            OrderResolution::Resolved(_)
+        ));
+    }
+
+    #[test]
+    fn test_exclusive_dutch_resolve_exclusivity_override() {
+        let order = v2_order(cosigner_data(vec![Uint::ZERO]));
+        let order = ExclusiveDutchOrder {
+            info: order.info,
+            decayStartTime: Uint::from(10),
+            decayEndTime: Uint::from(20),
+            exclusiveFiller: Address::from([6u8; 20]),
+            exclusivityOverrideBps: Uint::from(100),
+            input: order.baseInput,
+            outputs: order.baseOutputs,
+        };
+
+        // the override still applies at decayStartTime
+        match order.resolve(10, filler()) {
+            OrderResolution::Resolved(resolved) => {
+                assert_eq!(resolved.outputs[0].amount, Uint::from(2020));
+            }
+            _ => panic!("expected order to resolve"),
+        }
+        match order.resolve(10, Address::from([6u8; 20])) {
+            OrderResolution::Resolved(resolved) => {
+                assert_eq!(resolved.outputs[0].amount, Uint::from(2000));
+            }
+            _ => panic!("expected order to resolve"),
+        }
+    }
+
+    #[test]
+    fn test_v2_resolve_expired() {
+        let order = v2_order(cosigner_data(vec![Uint::ZERO]));
+
+        assert!(matches!(
+            order.resolve(101, filler()),
+            OrderResolution::Expired
+        ));
+    }
+
+    pub(crate) fn priority_order(input_mps: u64, output_mps: u64) -> PriorityOrder {
+        PriorityOrder {
+            info: OrderInfo {
+                reactor: Address::from([1u8; 20]),
+                swapper: Address::from([2u8; 20]),
+                nonce: Uint::from(1),
+                deadline: Uint::from(100),
+                additionalValidationContract: Address::default(),
+                additionalValidationData: vec![],
+            },
+            cosigner: Address::from([3u8; 20]),
+            auctionStartBlock: Uint::from(10),
+            baselinePriorityFeeWei: Uint::from(0),
+            input: PriorityInput {
+                token: Address::from([4u8; 20]),
+                amount: Uint::from(1_000_000),
+                mpsPerPriorityFeeWei: Uint::from(input_mps),
+            },
+            outputs: vec![PriorityOutput {
+                token: Address::from([5u8; 20]),
+                amount: Uint::from(1_000_000),
+                mpsPerPriorityFeeWei: Uint::from(output_mps),
+                recipient: Address::from([2u8; 20]),
+            }],
+            cosignerData: PriorityCosignerData {
+                auctionTargetBlock: Uint::from(0),
+            },
+            cosignature: vec![],
+        }
+    }
+
+    #[test]
+    fn test_priority_encode_decode() {
+        let order = priority_order(0, 1);
+        let encoded = format!("0x{}", hex::encode(encode_priority_order(&order)));
+
+        let decoded = decode_priority_order(&encoded).unwrap();
+
+        assert_eq!(decoded.auctionStartBlock, Uint::from(10));
+        assert_eq!(decoded.outputs[0].mpsPerPriorityFeeWei, Uint::from(1));
+    }
+
+    #[test]
+    fn test_priority_scales_outputs_up() {
+        let order = priority_order(0, 1);
+
+        // 100 wei priority fee * 1 mps = 0.001% more output, rounded up
+        match order.resolve(10, 50, Uint::from(100)) {
+            OrderResolution::Resolved(resolved) => {
+                assert_eq!(resolved.input.amount, Uint::from(1_000_000));
+                assert_eq!(resolved.outputs[0].amount, Uint::from(1_000_010));
+            }
+            _ => panic!("expected order to resolve"),
+        }
+    }
+
+    #[test]
+    fn test_priority_scales_input_down() {
+        let mut order = priority_order(1, 0);
+        order.baselinePriorityFeeWei = Uint::from(50);
+
+        match order.resolve(10, 50, Uint::from(150)) {
+            OrderResolution::Resolved(resolved) => {
+                assert_eq!(resolved.input.amount, Uint::from(999_990));
+                assert_eq!(resolved.outputs[0].amount, Uint::from(1_000_000));
+            }
+            _ => panic!("expected order to resolve"),
+        }
+
+        // input scales to zero once the scaling factor reaches MPS
+        match order.resolve(10, 50, Uint::from(MPS + 50)) {
+            OrderResolution::Resolved(resolved) => {
+                assert_eq!(resolved.input.amount, Uint::from(0));
+            }
+            _ => panic!("expected order to resolve"),
+        }
+    }
+
+    #[test]
+    fn test_priority_auction_start_block() {
+        let mut order = priority_order(0, 1);
+
+        assert!(matches!(
+            order.resolve(9, 50, Uint::from(0)),
+            OrderResolution::NotFillableYet
+        ));
+
+        // cosigner can only move the auction earlier
+        order.cosignerData.auctionTargetBlock = Uint::from(8);
+        assert!(matches!(
+            order.resolve(9, 50, Uint::from(0)),
+            OrderResolution::Resolved(_)
+        ));
+
+        order.cosignerData.auctionTargetBlock = Uint::from(12);
+        assert!(matches!(
+            order.resolve(11, 50, Uint::from(0)),
+            OrderResolution::Resolved(_)
+        ));
+    }
+
+    #[test]
+    fn test_priority_auction_start_block_without_cosigner() {
+        let mut order = priority_order(0, 1);
+        order.cosigner = Address::ZERO;
+
+        assert_eq!(order.auction_start_block(), Uint::from(10));
+        assert!(matches!(
+            order.resolve(9, 50, Uint::from(0)),
+            OrderResolution::NotFillableYet
+        ));
+
+        // without a cosigner the target block is ignored rather than checked
+        order.cosignerData.auctionTargetBlock = Uint::from(8);
+        assert_eq!(order.auction_start_block(), Uint::from(10));
+        assert!(matches!(
+            order.resolve(9, 50, Uint::from(0)),
+            OrderResolution::NotFillableYet
+        ));
+    }
+
+    #[test]
+    fn test_priority_input_and_output_scaling() {
+        let order = priority_order(1, 1);
+
+        assert!(matches!(
+            order.resolve(10, 50, Uint::from(0)),
+            OrderResolution::Invalid
+        ));
+    }
+
+    #[test]
+    fn test_order_decode_from_api_type() {
+        let order = v2_order(cosigner_data(vec![Uint::ZERO]));
+        let encoded = hex::encode(encode_v2_dutch_order(&order));
+
+        let decoded = Order::decode(&encoded, OrderType::from_api_type("Dutch_V2")).unwrap();
+
+        assert_eq!(decoded.order_type(), OrderType::V2Dutch);
+        assert_eq!(decoded.info().swapper, order.info.swapper);
+        assert_eq!(decoded.encode(), encode_v2_dutch_order(&order));
+    }
+
+    #[test]
+    fn test_order_decode_from_reactor() {
+        let mut order = priority_order(0, 1);
+        order.info.reactor = "0x000000001Ec5656dcdB24D90DFa42742738De729"
+            .parse()
+            .unwrap();
+        let encoded = hex::encode(encode_priority_order(&order));
+
+        // the reactor takes precedence over the api type
+        let decoded = Order::decode(&encoded, Some(OrderType::ExclusiveDutch)).unwrap();
+
+        assert_eq!(decoded.order_type(), OrderType::Priority);
+    }
+
+    #[test]
+    fn test_order_decode_unknown_type() {
+        let order = v2_order(cosigner_data(vec![Uint::ZERO]));
+        let encoded = hex::encode(encode_v2_dutch_order(&order));
+
+        assert!(Order::decode(&encoded, None).is_err());
+    }
+
+    #[test]
+    fn test_order_resolve() {
+        let order = Order::Priority(priority_order(0, 1));
+        let params = ResolutionParams {
+            block_number: 10,
+            timestamp: 50,
+            priority_fee: Uint::from(100),
+            filler: filler(),
+        };
+
+        match order.resolve(&params) {
+            OrderResolution::Resolved(resolved) => {
+                assert_eq!(resolved.outputs[0].amount, Uint::from(1_000_010));
+            }
+            _ => panic!("expected order to resolve"),
+        }
+    }
 }
@@ -0,0 +1,369 @@
+use crate::order::{Order, OrderResolution, ResolutionParams};
+use alloy_primitives::{Address, Uint};
+
+/// The linear decay of an order's amounts, after any cosigner overrides.
+struct DecayCurve {
+    start_time: Uint<256, 4>,
+    end_time: Uint<256, 4>,
+    input_start_amount: Uint<256, 4>,
+    input_end_amount: Uint<256, 4>,
+    // (token, start amount, end amount)
+    outputs: Vec<(Address, Uint<256, 4>, Uint<256, 4>)>,
+}
+
+fn decay_curve(order: &Order) -> Option<DecayCurve> {
+    match order {
+        Order::ExclusiveDutch(order) => Some(DecayCurve {
+            start_time: order.decayStartTime,
+            end_time: order.decayEndTime,
+            input_start_amount: order.input.startAmount,
+            input_end_amount: order.input.endAmount,
+            outputs: order
+                .outputs
+                .iter()
+                .map(|output| (output.token, output.startAmount, output.endAmount))
+                .collect(),
+        }),
+        Order::Dutch(order) => Some(DecayCurve {
+            start_time: order.decayStartTime,
+            end_time: order.decayEndTime,
+            input_start_amount: order.input.startAmount,
+            input_end_amount: order.input.endAmount,
+            outputs: order
+                .outputs
+                .iter()
+                .map(|output| (output.token, output.startAmount, output.endAmount))
+                .collect(),
+        }),
+        Order::V2Dutch(order) => {
+            let cosigner_data = &order.cosignerData;
+            let input_start_amount = if cosigner_data.inputOverride.is_zero() {
+                order.baseInput.startAmount
+            } else {
+                cosigner_data.inputOverride
+            };
+            let outputs = order
+                .baseOutputs
+                .iter()
+                .enumerate()
+                .map(|(i, output)| {
+                    let start_amount = match cosigner_data.outputOverrides.get(i) {
+                        Some(output_override) if !output_override.is_zero() => *output_override,
+                        _ => output.startAmount,
+                    };
+                    (output.token, start_amount, output.endAmount)
+                })
+                .collect();
+            Some(DecayCurve {
+                start_time: cosigner_data.decayStartTime,
+                end_time: cosigner_data.decayEndTime,
+                input_start_amount,
+                input_end_amount: order.baseInput.endAmount,
+                outputs,
+            })
+        }
+        Order::Priority(_) | Order::Limit(_) => None,
+    }
+}
+
+/// Whether `quote`, the output a swap of `amount_in` returns, covers the order's first output
+/// token and `gas_cost`. The quote is scaled to the resolved input amount.
+fn is_profitable(
+    resolution: OrderResolution,
+    amount_in: Uint<256, 4>,
+    quote: Uint<256, 4>,
+    gas_cost: Uint<256, 4>,
+) -> bool {
+    let resolved = match resolution {
+        OrderResolution::Resolved(resolved) => resolved,
+        _ => return false,
+    };
+    let token_out = match resolved.outputs.first() {
+        Some(output) => output.token,
+        None => return false,
+    };
+
+    let mut amount_out_required = gas_cost;
+    for output in resolved.outputs.iter() {
+        if output.token == token_out {
+            amount_out_required = match amount_out_required.checked_add(output.amount) {
+                Some(amount) => amount,
+                None => return false,
+            };
+        }
+    }
+
+    // (required + gas) / amount_in <= quote / resolved input, without rounding
+    match (
+        amount_out_required.checked_mul(amount_in),
+        quote.checked_mul(resolved.input.amount),
+    ) {
+        (Some(required), Some(available)) => required.le(&available),
+        _ => false,
+    }
+}
+
+fn div_ceil(x: Uint<256, 4>, y: Uint<256, 4>) -> Option<Uint<256, 4>> {
+    let quotient = x.checked_div(y)?;
+    if (x % y).is_zero() {
+        Some(quotient)
+    } else {
+        quotient.checked_add(Uint::from(1))
+    }
+}
+
+/// The earliest timestamp, no earlier than `params.timestamp`, at which filling the order is
+/// profitable: `quote` of its first output token for `amount_in` of its input covers the
+/// order's outputs in that token plus `gas_cost`, in the same token. Returns `None` if the
+/// order never becomes profitable. Priority and limit orders don't decay over time, so they
+/// are only checked at `params.timestamp`. Protocol fee outputs are not included.
+pub fn earliest_profitable_time(
+    order: &Order,
+    params: &ResolutionParams,
+    amount_in: Uint<256, 4>,
+    quote: Uint<256, 4>,
+    gas_cost: Uint<256, 4>,
+) -> Option<u64> {
+    let profitable_at = |timestamp: u64| {
+        let params = ResolutionParams {
+            timestamp,
+            ..*params
+        };
+        is_profitable(order.resolve(&params), amount_in, quote, gas_cost)
+    };
+
+    if profitable_at(params.timestamp) {
+        return Some(params.timestamp);
+    }
+
+    let curve = decay_curve(order)?;
+    let from = Uint::from(params.timestamp);
+    if curve.end_time.le(&from) || curve.end_time.le(&curve.start_time) {
+        // the amounts no longer change
+        return None;
+    }
+    let duration = curve.end_time - curve.start_time;
+
+    let token_out = curve.outputs.first()?.0;
+    let mut outputs_start = gas_cost;
+    let mut outputs_decay = Uint::<256, 4>::ZERO;
+    let mut decaying_outputs = Uint::<256, 4>::ZERO;
+    for (token, start_amount, end_amount) in curve.outputs.iter() {
+        if *token == token_out {
+            outputs_start = outputs_start.checked_add(*start_amount)?;
+            outputs_decay = outputs_decay.checked_add(start_amount.saturating_sub(*end_amount))?;
+            decaying_outputs += Uint::from(1);
+        }
+    }
+    let input_decay = curve
+        .input_end_amount
+        .saturating_sub(curve.input_start_amount);
+
+    // Ignoring rounding, after `elapsed` seconds of decay the order needs
+    //   (outputs_start - outputs_decay * elapsed / duration) * amount_in
+    //     <= quote * (input_start + input_decay * elapsed / duration)
+    // which is linear in `elapsed`:
+    //   elapsed >= (outputs_start * amount_in - quote * input_start) * duration / rate
+    let rate = outputs_decay
+        .checked_mul(amount_in)?
+        .checked_add(quote.checked_mul(input_decay)?)?;
+    if rate.is_zero() {
+        return None;
+    }
+    let required = outputs_start.checked_mul(amount_in)?;
+    let available = quote.checked_mul(curve.input_start_amount)?;
+    let shortfall = required.saturating_sub(available);
+
+    // DutchDecayLib rounds every decayed amount down, which leaves each output up to 1 higher
+    // and the input up to 1 lower than the linear curve, so the exact time lies in between
+    let lower = div_ceil(shortfall.checked_mul(duration)?, rate)?;
+    let rounding = decaying_outputs
+        .checked_mul(amount_in)?
+        .checked_add(quote)?;
+    let upper = div_ceil(
+        shortfall.checked_add(rounding)?.checked_mul(duration)?,
+        rate,
+    )?;
+    if lower.gt(&duration) {
+        return None;
+    }
+
+    let mut lower = u64::try_from(curve.start_time.checked_add(lower)?)
+        .ok()?
+        .max(params.timestamp + 1);
+    let mut upper = u64::try_from(curve.start_time.checked_add(upper.min(duration))?).ok()?;
+    if lower > upper || !profitable_at(upper) {
+        return None;
+    }
+
+    while lower < upper {
+        let mid = lower + (upper - lower) / 2;
+        if profitable_at(mid) {
+            upper = mid;
+        } else {
+            lower = mid + 1;
+        }
+    }
+    Some(upper)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::order::tests::{cosigner_data, filler, v2_order};
+    use crate::order::{DutchOrder, ExclusiveDutchOrder};
+
+    fn params(timestamp: u64) -> ResolutionParams {
+        ResolutionParams {
+            block_number: 1,
+            timestamp,
+            priority_fee: Uint::ZERO,
+            filler: filler(),
+        }
+    }
+
+    // outputs decay from 2000 to 1000 between 10 and 20, the input is 1000
+    fn dutch_order() -> DutchOrder {
+        let order = v2_order(cosigner_data(vec![Uint::ZERO]));
+        DutchOrder {
+            info: order.info,
+            decayStartTime: Uint::from(10),
+            decayEndTime: Uint::from(20),
+            input: order.baseInput,
+            outputs: order.baseOutputs,
+        }
+    }
+
+    #[test]
+    fn test_earliest_profitable_time_output_decay() {
+        let order = Order::Dutch(dutch_order());
+
+        let time = earliest_profitable_time(
+            &order,
+            &params(5),
+            Uint::from(1000),
+            Uint::from(1500),
+            Uint::ZERO,
+        );
+        assert_eq!(time, Some(15));
+
+        // 1450 is reached half way between two seconds
+        let time = earliest_profitable_time(
+            &order,
+            &params(5),
+            Uint::from(1000),
+            Uint::from(1500),
+            Uint::from(50),
+        );
+        assert_eq!(time, Some(16));
+    }
+
+    #[test]
+    fn test_earliest_profitable_time_rounding() {
+        let mut order = dutch_order();
+        order.decayStartTime = Uint::from(0);
+        order.decayEndTime = Uint::from(3);
+        order.outputs[0].startAmount = Uint::from(1000);
+        order.outputs[0].endAmount = Uint::from(0);
+        let order = Order::Dutch(order);
+
+        // after 1 second the output is 1000 - 333 = 667, after 2 it is 1000 - 666 = 334
+        let at = |quote: u64| {
+            earliest_profitable_time(
+                &order,
+                &params(0),
+                Uint::from(1000),
+                Uint::from(quote),
+                Uint::ZERO,
+            )
+        };
+        assert_eq!(at(667), Some(1));
+        assert_eq!(at(666), Some(2));
+    }
+
+    #[test]
+    fn test_earliest_profitable_time_input_decay() {
+        let mut order = dutch_order();
+        order.input.endAmount = Uint::from(1100);
+        order.outputs[0].startAmount = Uint::from(1000);
+        order.outputs[0].endAmount = Uint::from(1000);
+        let order = Order::Dutch(order);
+
+        // the quote grows with the input, 1000 of input is only worth the outputs without gas
+        let time = earliest_profitable_time(
+            &order,
+            &params(5),
+            Uint::from(1000),
+            Uint::from(1000),
+            Uint::from(50),
+        );
+        assert_eq!(time, Some(15));
+    }
+
+    #[test]
+    fn test_earliest_profitable_time_already_profitable() {
+        let order = Order::Dutch(dutch_order());
+
+        let time = earliest_profitable_time(
+            &order,
+            &params(12),
+            Uint::from(1000),
+            Uint::from(2500),
+            Uint::ZERO,
+        );
+        assert_eq!(time, Some(12));
+    }
+
+    #[test]
+    fn test_earliest_profitable_time_never_profitable() {
+        let order = Order::Dutch(dutch_order());
+
+        let time = earliest_profitable_time(
+            &order,
+            &params(5),
+            Uint::from(1000),
+            Uint::from(999),
+            Uint::ZERO,
+        );
+        assert_eq!(time, None);
+    }
+
+    #[test]
+    fn test_earliest_profitable_time_exclusivity() {
+        let order = dutch_order();
+        let order = Order::ExclusiveDutch(ExclusiveDutchOrder {
+            info: order.info,
+            decayStartTime: order.decayStartTime,
+            decayEndTime: order.decayEndTime,
+            exclusiveFiller: Address::from([6u8; 20]),
+            exclusivityOverrideBps: Uint::ZERO,
+            input: order.input,
+            outputs: order.outputs,
+        });
+
+        // strictly exclusive to another filler until the decay starts
+        let time = earliest_profitable_time(
+            &order,
+            &params(5),
+            Uint::from(1000),
+            Uint::from(2000),
+            Uint::ZERO,
+        );
+        assert_eq!(time, Some(11));
+    }
+
+    #[test]
+    fn test_earliest_profitable_time_v2_overrides() {
+        let order = Order::V2Dutch(v2_order(cosigner_data(vec![Uint::from(3000)])));
+
+        // the override starts the output at 3000, decaying to 1000 between 10 and 20
+        let time = earliest_profitable_time(
+            &order,
+            &params(5),
+            Uint::from(1000),
+            Uint::from(2000),
+            Uint::ZERO,
+        );
+        assert_eq!(time, Some(15));
+    }
+}
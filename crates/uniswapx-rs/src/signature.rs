@@ -0,0 +1,345 @@
+use crate::order::{Order, PriorityOrder, V2DutchOrder};
+use alloy_primitives::{Address, B256};
+use anyhow::{anyhow, Result};
+use ethers::{
+    abi::{self, Token},
+    providers::{Middleware, MiddlewareError},
+    types::{
+        transaction::eip2718::TypedTransaction, Bytes, Signature, TransactionRequest, H160, H256,
+        U256,
+    },
+};
+
+/// `bytes4(keccak256("isValidSignature(bytes32,bytes)"))`, also the EIP-1271 magic value.
+const EIP1271_MAGIC_VALUE: [u8; 4] = [0x16, 0x26, 0xba, 0x7e];
+
+/// Recovers the signer of an ECDSA signature over `digest`. Accepts both 65 byte signatures
+/// and 64 byte EIP-2098 compact signatures, like Permit2 does.
+pub fn recover_signer(digest: B256, signature: &[u8]) -> Result<Address> {
+    let signature = match signature.len() {
+        65 => Signature::try_from(signature)?,
+        64 => {
+            // compact signatures pack the parity bit into the top bit of s
+            let mut s = [0u8; 32];
+            s.copy_from_slice(&signature[32..]);
+            let v = 27 + u64::from(s[0] >> 7);
+            s[0] &= 0x7f;
+            Signature {
+                r: U256::from_big_endian(&signature[..32]),
+                s: U256::from_big_endian(&s),
+                v,
+            }
+        }
+        length => return Err(anyhow!("invalid signature length {}", length)),
+    };
+    let signer = signature.recover(H256::from_slice(digest.as_slice()))?;
+    Ok(Address::from_slice(signer.as_bytes()))
+}
+
+/// Checks that `signer` signed `digest` the way Permit2 does: ECDSA if the signer has no code,
+/// EIP-1271 `isValidSignature` otherwise, even if the signature also recovers to the signer.
+/// Errors other than the wallet reverting, e.g. transport errors, are returned.
+pub async fn verify_signature<M>(
+    client: &M,
+    signer: Address,
+    digest: B256,
+    signature: &[u8],
+) -> Result<bool>
+where
+    M: Middleware,
+    M::Error: 'static,
+{
+    let address = H160::from_slice(signer.as_slice());
+    let code = client.get_code(address, None).await?;
+    if code.is_empty() {
+        return Ok(match recover_signer(digest, signature) {
+            Ok(recovered) => !recovered.is_zero() && recovered == signer,
+            Err(_) => false,
+        });
+    }
+
+    let mut data = EIP1271_MAGIC_VALUE.to_vec();
+    data.extend(abi::encode(&[
+        Token::FixedBytes(digest.to_vec()),
+        Token::Bytes(signature.to_vec()),
+    ]));
+    let tx: TypedTransaction = TransactionRequest::new()
+        .to(address)
+        .data(Bytes::from(data))
+        .into();
+
+    // reverting wallets reject the signature, which nodes report as an error response
+    match client.call(&tx, None).await {
+        Ok(result) => Ok(result.len() >= 4 && result[..4] == EIP1271_MAGIC_VALUE),
+        Err(e) if e.as_error_response().is_some() => Ok(false),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Checks a cosignature the way CosignerLib does, which only accepts 65 byte ECDSA signatures.
+fn verify_cosignature(cosigner: Address, digest: B256, cosignature: &[u8]) -> bool {
+    if cosignature.len() != 65 {
+        return false;
+    }
+    match recover_signer(digest, cosignature) {
+        Ok(signer) => !signer.is_zero() && signer == cosigner,
+        Err(_) => false,
+    }
+}
+
+impl V2DutchOrder {
+    pub fn verify_cosignature(&self, chain_id: u64) -> bool {
+        verify_cosignature(
+            self.cosigner,
+            self.cosigner_digest(chain_id),
+            &self.cosignature,
+        )
+    }
+}
+
+impl PriorityOrder {
+    /// The reactor only checks the cosignature if the cosigner moved the auction start,
+    /// so orders without a cosigner or target block are always valid.
+    pub fn verify_cosignature(&self, chain_id: u64) -> bool {
+        if !self.has_cosigner_override() {
+            return true;
+        }
+        verify_cosignature(
+            self.cosigner,
+            self.cosigner_digest(chain_id),
+            &self.cosignature,
+        )
+    }
+}
+
+impl Order {
+    /// Checks that the order's cosigner data is signed by its cosigner, for order types that
+    /// have one.
+    pub fn verify_cosignature(&self, chain_id: u64) -> bool {
+        match self {
+            Order::V2Dutch(order) => order.verify_cosignature(chain_id),
+            Order::Priority(order) => order.verify_cosignature(chain_id),
+            _ => true,
+        }
+    }
+
+    /// Checks that the order's swapper signed the Permit2 witness transfer for it.
+    pub async fn verify_swapper_signature<M>(
+        &self,
+        client: &M,
+        signature: &[u8],
+        chain_id: u64,
+        permit2: Address,
+    ) -> Result<bool>
+    where
+        M: Middleware,
+        M::Error: 'static,
+    {
+        verify_signature(
+            client,
+            self.info().swapper,
+            self.permit2_digest(chain_id, permit2),
+            signature,
+        )
+        .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::order::tests::{cosigner_data, priority_order, v2_order};
+    use alloy_primitives::{keccak256, Uint};
+    use ethers::{
+        providers::{JsonRpcError, MockResponse, Provider},
+        signers::{LocalWallet, Signer},
+    };
+
+    const PRIVATE_KEY: &str = "0x4c0883a69102937d6231471b5dbb6204fe5129617082792ae468d01a3f362318";
+
+    fn sign(digest: B256) -> (Address, Vec<u8>) {
+        let wallet = PRIVATE_KEY.parse::<LocalWallet>().unwrap();
+        let signature = wallet
+            .sign_hash(H256::from_slice(digest.as_slice()))
+            .unwrap();
+        (
+            Address::from_slice(wallet.address().as_bytes()),
+            signature.to_vec(),
+        )
+    }
+
+    #[test]
+    fn test_recover_signer() {
+        let digest = keccak256("order");
+        let (signer, signature) = sign(digest);
+
+        assert_eq!(recover_signer(digest, &signature).unwrap(), signer);
+        assert_ne!(
+            recover_signer(keccak256("other order"), &signature).unwrap(),
+            signer
+        );
+    }
+
+    #[test]
+    fn test_recover_signer_compact() {
+        let digest = keccak256("order");
+        let (signer, signature) = sign(digest);
+
+        // pack v into the top bit of s
+        let mut compact = signature[..64].to_vec();
+        if signature[64] == 28 {
+            compact[32] |= 0x80;
+        }
+
+        assert_eq!(recover_signer(digest, &compact).unwrap(), signer);
+    }
+
+    #[test]
+    fn test_recover_signer_invalid_length() {
+        assert!(recover_signer(keccak256("order"), &[0u8; 10]).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_verify_signature_eoa() {
+        let (provider, mock) = Provider::mocked();
+        let digest = keccak256("order");
+        let (signer, signature) = sign(digest);
+
+        // the signer has no code
+        mock.push::<Bytes, _>(Bytes::default()).unwrap();
+
+        assert!(verify_signature(&provider, signer, digest, &signature)
+            .await
+            .unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_verify_signature_eip1271() {
+        let (provider, mock) = Provider::mocked();
+        let digest = keccak256("order");
+        let (_, signature) = sign(digest);
+        let wallet_contract = Address::from([7u8; 20]);
+
+        // mocked responses are returned last in first out
+        let mut magic_value = EIP1271_MAGIC_VALUE.to_vec();
+        magic_value.resize(32, 0);
+        mock.push::<Bytes, _>(Bytes::from(magic_value)).unwrap();
+        mock.push::<Bytes, _>(Bytes::from(vec![0x60, 0x80]))
+            .unwrap();
+
+        assert!(
+            verify_signature(&provider, wallet_contract, digest, &signature)
+                .await
+                .unwrap()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_verify_signature_contract_ignores_ecdsa() {
+        let (provider, mock) = Provider::mocked();
+        let digest = keccak256("order");
+        let (signer, signature) = sign(digest);
+
+        // the signer has code, which rejects the signature
+        mock.push::<Bytes, _>(Bytes::from(vec![0u8; 32])).unwrap();
+        mock.push::<Bytes, _>(Bytes::from(vec![0x60, 0x80]))
+            .unwrap();
+
+        assert!(!verify_signature(&provider, signer, digest, &signature)
+            .await
+            .unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_verify_signature_eip1271_revert() {
+        let (provider, mock) = Provider::mocked();
+        let digest = keccak256("order");
+        let (_, signature) = sign(digest);
+
+        mock.push_response(MockResponse::Error(JsonRpcError {
+            code: 3,
+            message: "execution reverted".to_string(),
+            data: None,
+        }));
+        mock.push::<Bytes, _>(Bytes::from(vec![0x60, 0x80]))
+            .unwrap();
+
+        assert!(
+            !verify_signature(&provider, Address::from([7u8; 20]), digest, &signature)
+                .await
+                .unwrap()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_verify_signature_eip1271_transport_error() {
+        let (provider, mock) = Provider::mocked();
+        let digest = keccak256("order");
+        let (_, signature) = sign(digest);
+
+        // there's no response to the isValidSignature call, so it fails without reaching the
+        // wallet
+        mock.push::<Bytes, _>(Bytes::from(vec![0x60, 0x80]))
+            .unwrap();
+
+        assert!(
+            verify_signature(&provider, Address::from([7u8; 20]), digest, &signature)
+                .await
+                .is_err()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_verify_signature_wrong_eoa() {
+        let (provider, mock) = Provider::mocked();
+        let digest = keccak256("order");
+        let (_, signature) = sign(digest);
+
+        // the claimed signer has no code
+        mock.push::<Bytes, _>(Bytes::default()).unwrap();
+
+        assert!(
+            !verify_signature(&provider, Address::from([7u8; 20]), digest, &signature)
+                .await
+                .unwrap()
+        );
+    }
+
+    #[test]
+    fn test_v2_verify_cosignature() {
+        let mut order = v2_order(cosigner_data(vec![Uint::from(2500)]));
+        // the cosigner is part of the order hash, so set it before cosigning
+        let (cosigner, _) = sign(keccak256("order"));
+        order.cosigner = cosigner;
+        let (_, cosignature) = sign(order.cosigner_digest(1));
+        order.cosignature = cosignature;
+
+        assert!(order.verify_cosignature(1));
+        // the cosignature is bound to the chain
+        assert!(!order.verify_cosignature(137));
+
+        order.cosignerData.inputOverride = Uint::from(900);
+        assert!(!order.verify_cosignature(1));
+    }
+
+    #[test]
+    fn test_priority_verify_cosignature() {
+        let mut order = priority_order(0, 1);
+
+        // no target block, the cosignature is never checked
+        assert!(order.verify_cosignature(8453));
+
+        order.cosignerData.auctionTargetBlock = Uint::from(8);
+        assert!(!order.verify_cosignature(8453));
+
+        // orders without a cosigner ignore the target block
+        order.cosigner = Address::ZERO;
+        assert!(order.verify_cosignature(8453));
+
+        let (cosigner, _) = sign(keccak256("order"));
+        order.cosigner = cosigner;
+        let (_, cosignature) = sign(order.cosigner_digest(8453));
+        order.cosignature = cosignature;
+        assert!(Order::Priority(order).verify_cosignature(8453));
+    }
+}
@@ -0,0 +1,310 @@
+use crate::order::{
+    DutchInput, DutchOrder, DutchOutput, ExclusiveDutchOrder, LimitOrder, Order, OrderType,
+    PriorityOrder, V2DutchOrder,
+};
+use alloy_primitives::{Address, Uint};
+use std::fmt;
+
+/// Reasons an order can never be filled, named after the reactor errors they would revert with.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum OrderError {
+    DeadlineBeforeEndTime,
+    EndTimeBeforeStartTime,
+    IncorrectAmounts,
+    InputAndOutputDecay,
+    InputAndOutputFees,
+    InputOutputScaling,
+    InvalidCosignerInput,
+    InvalidCosignerOutput,
+    InvalidReactor,
+    DuplicateFeeOutput {
+        token: Address,
+    },
+    FeeTooLarge {
+        token: Address,
+        amount: Uint<256, 4>,
+        recipient: Address,
+    },
+    InvalidFeeToken {
+        token: Address,
+    },
+}
+
+impl fmt::Display for OrderError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+
+impl std::error::Error for OrderError {}
+
+fn validate_decay_window(
+    deadline: Uint<256, 4>,
+    decay_start_time: Uint<256, 4>,
+    decay_end_time: Uint<256, 4>,
+) -> Result<(), OrderError> {
+    if deadline.lt(&decay_end_time) {
+        return Err(OrderError::DeadlineBeforeEndTime);
+    }
+    if decay_end_time.lt(&decay_start_time) {
+        return Err(OrderError::EndTimeBeforeStartTime);
+    }
+    Ok(())
+}
+
+// DutchDecayLib only lets outputs decay downward
+fn validate_output_decay(outputs: &[DutchOutput]) -> Result<(), OrderError> {
+    if outputs
+        .iter()
+        .any(|output| output.startAmount.lt(&output.endAmount))
+    {
+        return Err(OrderError::IncorrectAmounts);
+    }
+    Ok(())
+}
+
+// either the input or the outputs may decay, not both
+fn validate_input_and_output_decay(
+    input: &DutchInput,
+    outputs: &[DutchOutput],
+) -> Result<(), OrderError> {
+    if input.startAmount != input.endAmount
+        && outputs
+            .iter()
+            .any(|output| output.startAmount != output.endAmount)
+    {
+        return Err(OrderError::InputAndOutputDecay);
+    }
+    Ok(())
+}
+
+impl ExclusiveDutchOrder {
+    /// Checks the order for the conditions the ExclusiveDutchOrderReactor reverts on regardless
+    /// of when or by whom it is filled.
+    pub fn validate(&self) -> Result<(), OrderError> {
+        validate_decay_window(self.info.deadline, self.decayStartTime, self.decayEndTime)?;
+        validate_input_and_output_decay(&self.input, &self.outputs)?;
+        validate_output_decay(&self.outputs)
+    }
+}
+
+impl DutchOrder {
+    /// Checks the order for the conditions the DutchOrderReactor reverts on regardless of when
+    /// or by whom it is filled.
+    pub fn validate(&self) -> Result<(), OrderError> {
+        validate_decay_window(self.info.deadline, self.decayStartTime, self.decayEndTime)?;
+        validate_input_and_output_decay(&self.input, &self.outputs)?;
+        validate_output_decay(&self.outputs)
+    }
+}
+
+impl V2DutchOrder {
+    /// Checks the order for the conditions the V2DutchOrderReactor reverts on regardless of
+    /// when or by whom it is filled. The cosignature itself is checked by `verify_cosignature`.
+    pub fn validate(&self) -> Result<(), OrderError> {
+        let cosigner_data = &self.cosignerData;
+        validate_decay_window(
+            self.info.deadline,
+            cosigner_data.decayStartTime,
+            cosigner_data.decayEndTime,
+        )?;
+
+        if !cosigner_data.inputOverride.is_zero()
+            && cosigner_data.inputOverride.gt(&self.baseInput.startAmount)
+        {
+            return Err(OrderError::InvalidCosignerInput);
+        }
+
+        // an empty array is a mismatch too, the reactor needs a zero override to keep an output
+        if cosigner_data.outputOverrides.len() != self.baseOutputs.len() {
+            return Err(OrderError::InvalidCosignerOutput);
+        }
+        if cosigner_data
+            .outputOverrides
+            .iter()
+            .zip(self.baseOutputs.iter())
+            .any(|(output_override, output)| {
+                !output_override.is_zero() && output_override.lt(&output.startAmount)
+            })
+        {
+            return Err(OrderError::InvalidCosignerOutput);
+        }
+
+        // overrides only raise the start amount, so checking the base outputs is enough
+        validate_output_decay(&self.baseOutputs)
+    }
+}
+
+impl PriorityOrder {
+    /// Checks the order for the conditions the PriorityOrderReactor reverts on regardless of
+    /// when or by whom it is filled.
+    pub fn validate(&self) -> Result<(), OrderError> {
+        if !self.input.mpsPerPriorityFeeWei.is_zero()
+            && self
+                .outputs
+                .iter()
+                .any(|output| !output.mpsPerPriorityFeeWei.is_zero())
+        {
+            return Err(OrderError::InputOutputScaling);
+        }
+        Ok(())
+    }
+}
+
+impl LimitOrder {
+    /// Limit orders have no amounts to validate, the reactor only checks the reactor address.
+    pub fn validate(&self) -> Result<(), OrderError> {
+        Ok(())
+    }
+}
+
+impl Order {
+    /// Checks the order for the conditions its reactor would always revert on, so orders that
+    /// can never be filled can be dropped. Orders for reactors other than `reactors` can't be
+    /// filled through them.
+    pub fn validate(&self, reactors: &[Address]) -> Result<(), OrderError> {
+        let reactor = self.info().reactor;
+        if !reactors.contains(&reactor) {
+            return Err(OrderError::InvalidReactor);
+        }
+        // an order for a known reactor of another order type can't be decoded by that reactor
+        if let Some(order_type) = OrderType::from_reactor(reactor) {
+            if order_type != self.order_type() {
+                return Err(OrderError::InvalidReactor);
+            }
+        }
+
+        match self {
+            Order::ExclusiveDutch(order) => order.validate(),
+            Order::Dutch(order) => order.validate(),
+            Order::V2Dutch(order) => order.validate(),
+            Order::Priority(order) => order.validate(),
+            Order::Limit(order) => order.validate(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::order::tests::{cosigner_data, priority_order, v2_order};
+
+    #[test]
+    fn test_v2_validate() {
+        let order = v2_order(cosigner_data(vec![Uint::from(2500)]));
+
+        assert_eq!(order.validate(), Ok(()));
+    }
+
+    #[test]
+    fn test_v2_validate_deadline_before_end_time() {
+        let mut data = cosigner_data(vec![Uint::ZERO]);
+        data.decayEndTime = Uint::from(101);
+
+        assert_eq!(
+            v2_order(data).validate(),
+            Err(OrderError::DeadlineBeforeEndTime)
+        );
+    }
+
+    #[test]
+    fn test_v2_validate_end_time_before_start_time() {
+        let mut data = cosigner_data(vec![Uint::ZERO]);
+        data.decayStartTime = Uint::from(30);
+
+        assert_eq!(
+            v2_order(data).validate(),
+            Err(OrderError::EndTimeBeforeStartTime)
+        );
+    }
+
+    #[test]
+    fn test_v2_validate_incorrect_amounts() {
+        let mut order = v2_order(cosigner_data(vec![Uint::ZERO]));
+        order.baseOutputs[0].endAmount = Uint::from(3000);
+
+        assert_eq!(order.validate(), Err(OrderError::IncorrectAmounts));
+    }
+
+    #[test]
+    fn test_v2_validate_invalid_cosigner_overrides() {
+        let mut data = cosigner_data(vec![Uint::ZERO]);
+        data.inputOverride = Uint::from(1001);
+        assert_eq!(
+            v2_order(data).validate(),
+            Err(OrderError::InvalidCosignerInput)
+        );
+
+        let data = cosigner_data(vec![Uint::from(1500)]);
+        assert_eq!(
+            v2_order(data).validate(),
+            Err(OrderError::InvalidCosignerOutput)
+        );
+
+        let data = cosigner_data(vec![Uint::from(2500), Uint::from(2500)]);
+        assert_eq!(
+            v2_order(data).validate(),
+            Err(OrderError::InvalidCosignerOutput)
+        );
+
+        let data = cosigner_data(vec![]);
+        assert_eq!(
+            v2_order(data).validate(),
+            Err(OrderError::InvalidCosignerOutput)
+        );
+    }
+
+    #[test]
+    fn test_dutch_validate_input_and_output_decay() {
+        let order = v2_order(cosigner_data(vec![Uint::ZERO]));
+        let mut order = DutchOrder {
+            info: order.info,
+            decayStartTime: Uint::from(10),
+            decayEndTime: Uint::from(20),
+            input: order.baseInput,
+            outputs: order.baseOutputs,
+        };
+        assert_eq!(order.validate(), Ok(()));
+
+        order.input.endAmount = Uint::from(1100);
+        assert_eq!(order.validate(), Err(OrderError::InputAndOutputDecay));
+    }
+
+    #[test]
+    fn test_priority_validate_input_output_scaling() {
+        assert_eq!(priority_order(0, 1).validate(), Ok(()));
+        assert_eq!(
+            priority_order(1, 1).validate(),
+            Err(OrderError::InputOutputScaling)
+        );
+    }
+
+    #[test]
+    fn test_order_validate_reactor() {
+        let order = Order::V2Dutch(v2_order(cosigner_data(vec![Uint::ZERO])));
+        let reactor = order.info().reactor;
+
+        assert_eq!(order.validate(&[reactor]), Ok(()));
+        // orders for reactors that aren't configured can't be filled
+        assert_eq!(
+            order.validate(&[Address::from([9u8; 20])]),
+            Err(OrderError::InvalidReactor)
+        );
+        assert_eq!(order.validate(&[]), Err(OrderError::InvalidReactor));
+    }
+
+    #[test]
+    fn test_order_validate_invalid_reactor() {
+        let mut order = v2_order(cosigner_data(vec![Uint::ZERO]));
+        // a priority order reactor
+        order.info.reactor = "0x000000001Ec5656dcdB24D90DFa42742738De729"
+            .parse()
+            .unwrap();
+        let reactor = order.info.reactor;
+
+        assert_eq!(
+            Order::V2Dutch(order).validate(&[reactor]),
+            Err(OrderError::InvalidReactor)
+        );
+    }
+}
@@ -0,0 +1,345 @@
+//! Differential tests for order resolution against the reactor contracts and libraries, run on a
+//! local anvil. These need `anvil` on the path: `cargo test -- --ignored`.
+
+use std::sync::Arc;
+
+use alloy_primitives::{Address, Uint};
+use bindings_uniswapx::{
+    mock_dutch_order_reactor::MockDutchOrderReactor,
+    mock_exclusivity_lib::MockExclusivityLib,
+    shared_types::{self, SignedOrder},
+};
+use ethers::{
+    middleware::SignerMiddleware,
+    providers::{Http, Middleware, Provider},
+    signers::{LocalWallet, Signer},
+    types::{BlockNumber, Bytes, H160, U256},
+    utils::Anvil,
+};
+use uniswapx_rs::order::{
+    DutchInput, DutchOrder, DutchOutput, ExclusiveDutchOrder, Order, OrderInfo, OrderResolution,
+    ResolutionParams,
+};
+
+/// A decay curve, with the decay window given relative to the timestamp the order is
+/// resolved at.
+struct DecayCase {
+    start_offset: i64,
+    end_offset: i64,
+    input: (Uint<256, 4>, Uint<256, 4>),
+    output: (Uint<256, 4>, Uint<256, 4>),
+}
+
+fn decay_cases() -> Vec<DecayCase> {
+    let amount = |value: u64| Uint::from(value);
+    let flat = (amount(1000), amount(1000));
+    let mut cases = vec![
+        // before, at and after the decay window
+        (10, 20, flat, (amount(2000), amount(1000))),
+        (0, 20, flat, (amount(2000), amount(1000))),
+        (-20, 0, flat, (amount(2000), amount(1000))),
+        (-30, -10, flat, (amount(2000), amount(1000))),
+        // rounding in both directions
+        (-1, 2, flat, (amount(100), amount(0))),
+        (
+            -1,
+            2,
+            (amount(0), amount(100)),
+            (amount(1000), amount(1000)),
+        ),
+        (-7, 6, flat, (amount(1_000_003), amount(999_989))),
+        (-5, 7, (amount(999_989), amount(1_000_003)), flat),
+        // no decay window
+        (0, 0, flat, (amount(2000), amount(1000))),
+        // end time before start time reverts
+        (5, -5, flat, (amount(2000), amount(1000))),
+        // (start - end) * elapsed overflows
+        (-2, 1, flat, (Uint::MAX, Uint::ZERO)),
+        (-2, 1, (Uint::ZERO, Uint::MAX), flat),
+        // large amounts that don't overflow
+        (-1, 1, flat, (Uint::MAX, Uint::MAX - amount(2))),
+    ];
+
+    // pseudo random curves from a fixed seed
+    let mut seed: u64 = 0x5eed;
+    let mut next = || {
+        seed = seed
+            .wrapping_mul(6364136223846793005)
+            .wrapping_add(1442695040888963407);
+        seed >> 33
+    };
+    for _ in 0..20 {
+        let start_offset = -((next() % 100) as i64);
+        let end_offset = start_offset + (next() % 200) as i64;
+        let end_amount = next();
+        let start_amount = end_amount + next() % 1_000_000;
+        cases.push((
+            start_offset,
+            end_offset,
+            flat,
+            (amount(start_amount), amount(end_amount)),
+        ));
+    }
+
+    cases
+        .into_iter()
+        .map(|(start_offset, end_offset, input, output)| DecayCase {
+            start_offset,
+            end_offset,
+            input,
+            output,
+        })
+        .collect()
+}
+
+fn dutch_order(case: &DecayCase, timestamp: u64) -> DutchOrder {
+    let offset = |offset: i64| Uint::from(timestamp.checked_add_signed(offset).unwrap());
+    DutchOrder {
+        info: OrderInfo {
+            reactor: Address::default(),
+            swapper: Address::from([2u8; 20]),
+            nonce: Uint::from(1),
+            deadline: Uint::from(timestamp + 1000),
+            additionalValidationContract: Address::default(),
+            additionalValidationData: vec![],
+        },
+        decayStartTime: offset(case.start_offset),
+        decayEndTime: offset(case.end_offset),
+        input: DutchInput {
+            token: Address::from([4u8; 20]),
+            startAmount: case.input.0,
+            endAmount: case.input.1,
+        },
+        outputs: vec![DutchOutput {
+            token: Address::from([5u8; 20]),
+            startAmount: case.output.0,
+            endAmount: case.output.1,
+            recipient: Address::from([2u8; 20]),
+        }],
+    }
+}
+
+#[tokio::test]
+#[ignore = "requires anvil"]
+async fn test_dutch_decay_matches_reactor() {
+    let anvil = Anvil::new().spawn();
+    let provider = Provider::<Http>::try_from(anvil.endpoint()).unwrap();
+    let wallet = LocalWallet::from(anvil.keys()[0].clone()).with_chain_id(anvil.chain_id());
+    let client = Arc::new(SignerMiddleware::new(provider, wallet.clone()));
+
+    // permit2 is only used when executing orders, not resolving them
+    let reactor = MockDutchOrderReactor::deploy(client.clone(), (H160::zero(), wallet.address()))
+        .unwrap()
+        .send()
+        .await
+        .unwrap();
+
+    let block = client
+        .get_block(BlockNumber::Latest)
+        .await
+        .unwrap()
+        .unwrap();
+    let block_number = block.number.unwrap();
+    let timestamp = block.timestamp.as_u64();
+
+    for (i, case) in decay_cases().iter().enumerate() {
+        let mut order = dutch_order(case, timestamp);
+        order.info.reactor = Address::from_slice(reactor.address().as_bytes());
+        let order = Order::Dutch(order);
+
+        let expected = reactor
+            .resolve_order(SignedOrder {
+                order: Bytes::from(order.encode()),
+                sig: Bytes::default(),
+            })
+            .block(block_number)
+            .call()
+            .await;
+        let resolution = order.resolve(&ResolutionParams {
+            block_number: block_number.as_u64(),
+            timestamp,
+            priority_fee: Uint::ZERO,
+            filler: Address::default(),
+        });
+
+        match (expected, resolution) {
+            (Ok(expected), OrderResolution::Resolved(resolved)) => {
+                assert_eq!(
+                    resolved.input.amount.to_string(),
+                    expected.input.amount.to_string(),
+                    "input amount of case {}",
+                    i
+                );
+                assert_eq!(resolved.outputs.len(), expected.outputs.len());
+                for (resolved, expected) in resolved.outputs.iter().zip(expected.outputs.iter()) {
+                    assert_eq!(
+                        resolved.amount.to_string(),
+                        expected.amount.to_string(),
+                        "output amount of case {}",
+                        i
+                    );
+                }
+            }
+            (Err(_), OrderResolution::Invalid) => {}
+            (expected, resolution) => panic!(
+                "case {} resolved to {:?}, reactor returned {:?}",
+                i, resolution, expected
+            ),
+        }
+    }
+}
+
+/// Output amounts and exclusivity override bps, checked against `ExclusivityLib`'s `mulDivUp`.
+fn exclusivity_override_cases() -> Vec<(Uint<256, 4>, Uint<256, 4>)> {
+    let amount = |value: u64| Uint::from(value);
+    // the largest amount that doesn't overflow when multiplied by BPS + 1
+    let max_amount = Uint::MAX / amount(10_001);
+    let mut cases = vec![
+        // rounding up
+        (amount(1), amount(1)),
+        (amount(9_999), amount(1)),
+        (amount(10_000), amount(1)),
+        (amount(10_001), amount(1)),
+        (amount(1_000_003), amount(37)),
+        (amount(0), amount(100)),
+        // overrides of 100% and more
+        (amount(1000), amount(10_000)),
+        (amount(1000), amount(25_000)),
+        // no override, which strictly excludes other fillers
+        (amount(1000), amount(0)),
+        // amount * (BPS + bps) overflows
+        (max_amount, amount(1)),
+        (max_amount + amount(1), amount(1)),
+        (Uint::MAX, amount(1)),
+        // BPS + bps overflows
+        (amount(1), Uint::MAX),
+    ];
+
+    // pseudo random amounts and overrides from a fixed seed
+    let mut seed: u64 = 0xb95;
+    let mut next = || {
+        seed = seed
+            .wrapping_mul(6364136223846793005)
+            .wrapping_add(1442695040888963407);
+        seed >> 33
+    };
+    for _ in 0..20 {
+        cases.push((amount(next()), amount(next() % 1000 + 1)));
+    }
+    cases
+}
+
+fn to_u256(value: Uint<256, 4>) -> U256 {
+    U256::from_big_endian(&value.to_be_bytes::<32>())
+}
+
+#[tokio::test]
+#[ignore = "requires anvil"]
+async fn test_exclusivity_override_matches_exclusivity_lib() {
+    let anvil = Anvil::new().spawn();
+    let provider = Provider::<Http>::try_from(anvil.endpoint()).unwrap();
+    let wallet = LocalWallet::from(anvil.keys()[0].clone()).with_chain_id(anvil.chain_id());
+    let client = Arc::new(SignerMiddleware::new(provider, wallet.clone()));
+
+    let exclusivity_lib = MockExclusivityLib::deploy(client.clone(), ())
+        .unwrap()
+        .send()
+        .await
+        .unwrap();
+
+    let block = client
+        .get_block(BlockNumber::Latest)
+        .await
+        .unwrap()
+        .unwrap();
+    let block_number = block.number.unwrap();
+    let timestamp = block.timestamp.as_u64();
+
+    // exclusive to another filler than the caller until after the block, and flat over the
+    // decay curve, so the outputs are only scaled by the override
+    let exclusive_filler = Address::from([7u8; 20]);
+    let exclusivity_end_time = Uint::from(timestamp + 100);
+
+    for (i, (amount, override_bps)) in exclusivity_override_cases().into_iter().enumerate() {
+        let order = Order::ExclusiveDutch(ExclusiveDutchOrder {
+            info: OrderInfo {
+                reactor: Address::default(),
+                swapper: Address::from([2u8; 20]),
+                nonce: Uint::from(1),
+                deadline: Uint::from(timestamp + 1000),
+                additionalValidationContract: Address::default(),
+                additionalValidationData: vec![],
+            },
+            decayStartTime: exclusivity_end_time,
+            decayEndTime: exclusivity_end_time + Uint::from(100),
+            exclusiveFiller: exclusive_filler,
+            exclusivityOverrideBps: override_bps,
+            input: DutchInput {
+                token: Address::from([4u8; 20]),
+                startAmount: Uint::from(1000),
+                endAmount: Uint::from(1000),
+            },
+            outputs: vec![DutchOutput {
+                token: Address::from([5u8; 20]),
+                startAmount: amount,
+                endAmount: amount,
+                recipient: Address::from([2u8; 20]),
+            }],
+        });
+        let resolution = order.resolve(&ResolutionParams {
+            block_number: block_number.as_u64(),
+            timestamp,
+            priority_fee: Uint::ZERO,
+            filler: Address::default(),
+        });
+
+        let resolved_order = shared_types::ResolvedOrder {
+            info: shared_types::OrderInfo {
+                reactor: H160::zero(),
+                swapper: H160::repeat_byte(2),
+                nonce: U256::one(),
+                deadline: U256::from(timestamp + 1000),
+                additional_validation_contract: H160::zero(),
+                additional_validation_data: Bytes::default(),
+            },
+            input: shared_types::InputToken {
+                token: H160::repeat_byte(4),
+                amount: U256::from(1000),
+                max_amount: U256::from(1000),
+            },
+            outputs: vec![shared_types::OutputToken {
+                token: H160::repeat_byte(5),
+                amount: to_u256(amount),
+                recipient: H160::repeat_byte(2),
+            }],
+            sig: Bytes::default(),
+            hash: [0u8; 32],
+        };
+        let expected = exclusivity_lib
+            .handle_exclusive_override(
+                resolved_order,
+                H160::from_slice(exclusive_filler.as_slice()),
+                to_u256(exclusivity_end_time),
+                to_u256(override_bps),
+            )
+            .block(block_number)
+            .call()
+            .await;
+
+        match (expected, resolution) {
+            (Ok(expected), OrderResolution::Resolved(resolved)) => {
+                assert_eq!(
+                    resolved.outputs[0].amount.to_string(),
+                    expected.outputs[0].amount.to_string(),
+                    "output amount of case {}",
+                    i
+                );
+            }
+            (Err(_), OrderResolution::Invalid | OrderResolution::ExclusiveToOtherFiller) => {}
+            (expected, resolution) => panic!(
+                "case {} resolved to {:?}, exclusivity lib returned {:?}",
+                i, resolution, expected
+            ),
+        }
+    }
+}
@@ -1,53 +1,348 @@
-use anyhow::Result;
+use crate::providers::resilient_ws_provider::ResilientWsProvider;
+use anyhow::{anyhow, Result};
 use artemis_core::types::{Collector, CollectorStream};
 use async_trait::async_trait;
 use ethers::{
     prelude::Middleware,
-    providers::PubsubClient,
-    types::{H256, U256, U64},
+    types::{Block, H160, H256, U256, U64},
 };
+use std::collections::BTreeMap;
 use std::sync::Arc;
 use tokio_stream::StreamExt;
+use tracing::{error, info};
+
+/// How many recent blocks are remembered to detect reorgs.
+const MAX_REORG_DEPTH: usize = 64;
+
+/// EIP-1559 bounds how fast the base fee changes between blocks.
+const BASE_FEE_MAX_CHANGE_DENOMINATOR: u64 = 8;
+/// EIP-1559 targets blocks half full.
+const ELASTICITY_MULTIPLIER: u64 = 2;
 
 /// A collector that listens for new blocks, and generates a stream of
-/// [events](NewBlock) which contain the block number and hash.
-pub struct BlockCollector<M> {
-    provider: Arc<M>,
+/// [events](BlockEvent) which contain the block number and hash. When a new block doesn't extend
+/// the previous head, a [Reorg](Reorg) listing the orphaned blocks is emitted before it.
+pub struct BlockCollector {
+    provider: Arc<ResilientWsProvider>,
 }
 
-/// A new block event, containing the block number and hash.
+/// A new block event, containing the block number and hash, and the gas data fees are priced
+/// from.
 #[derive(Debug, Clone)]
 pub struct NewBlock {
     pub hash: H256,
+    pub parent_hash: H256,
     pub number: U64,
     pub timestamp: U256,
+    /// `None` before EIP-1559.
+    pub base_fee_per_gas: Option<U256>,
+    pub gas_used: U256,
+    pub gas_limit: U256,
+    /// The fee recipient of the block.
+    pub miner: H160,
+    /// The base fee of the next block, derived from this block's base fee and gas usage.
+    pub next_base_fee: Option<U256>,
+}
+
+impl NewBlock {
+    /// Returns `None` for pending blocks, which have no hash or number yet.
+    pub fn from_block(block: &Block<H256>) -> Option<Self> {
+        Some(Self {
+            hash: block.hash?,
+            parent_hash: block.parent_hash,
+            number: block.number?,
+            timestamp: block.timestamp,
+            base_fee_per_gas: block.base_fee_per_gas,
+            gas_used: block.gas_used,
+            gas_limit: block.gas_limit,
+            miner: block.author.unwrap_or_default(),
+            next_base_fee: block
+                .base_fee_per_gas
+                .map(|base_fee| next_base_fee(base_fee, block.gas_used, block.gas_limit)),
+        })
+    }
+}
+
+/// The base fee of the block after one with `base_fee` that used `gas_used` of `gas_limit`, per
+/// EIP-1559. Chains that tune the EIP-1559 parameters, like OP stack chains, will differ.
+pub fn next_base_fee(base_fee: U256, gas_used: U256, gas_limit: U256) -> U256 {
+    let gas_target = gas_limit / ELASTICITY_MULTIPLIER;
+    if gas_target.is_zero() || gas_used == gas_target {
+        return base_fee;
+    }
+    if gas_used > gas_target {
+        let delta =
+            base_fee * (gas_used - gas_target) / gas_target / BASE_FEE_MAX_CHANGE_DENOMINATOR;
+        base_fee + delta.max(U256::one())
+    } else {
+        let delta =
+            base_fee * (gas_target - gas_used) / gas_target / BASE_FEE_MAX_CHANGE_DENOMINATOR;
+        base_fee.saturating_sub(delta)
+    }
+}
+
+/// A block that is no longer part of the canonical chain.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DroppedBlock {
+    pub hash: H256,
+    pub number: U64,
+}
+
+/// A reorg event, containing the blocks the new head orphaned.
+#[derive(Debug, Clone)]
+pub struct Reorg {
+    pub depth: u64,
+    pub dropped_blocks: Vec<DroppedBlock>,
+}
+
+#[derive(Debug, Clone)]
+pub enum BlockEvent {
+    NewBlock(NewBlock),
+    Reorg(Reorg),
+}
+
+/// The hashes of the most recent canonical blocks, by block number.
+#[derive(Debug, Default)]
+pub struct CanonicalBlocks {
+    blocks: BTreeMap<U64, H256>,
+}
+
+impl CanonicalBlocks {
+    /// Makes `block` the head, returning the blocks it orphans. Ancestors of `block` that
+    /// haven't been seen yet are fetched until one links to a known block.
+    pub async fn insert<M>(&mut self, provider: &M, block: &NewBlock) -> Result<Vec<DroppedBlock>>
+    where
+        M: Middleware,
+        M::Error: 'static,
+    {
+        // the new chain from its lowest unknown block up to the head
+        let mut new_chain = vec![(block.number, block.hash)];
+        let mut parent_hash = block.parent_hash;
+        while new_chain.len() <= MAX_REORG_DEPTH {
+            let (number, _) = new_chain[new_chain.len() - 1];
+            if number.is_zero() {
+                break;
+            }
+            match self.blocks.get(&(number - 1)) {
+                // an unknown parent is older than what we remember, or a block we missed
+                None => break,
+                Some(hash) if *hash == parent_hash => break,
+                Some(_) => {
+                    let parent = provider
+                        .get_block(parent_hash)
+                        .await?
+                        .ok_or_else(|| anyhow!("missing block {:?}", parent_hash))?;
+                    let hash = parent.hash.ok_or_else(|| anyhow!("pending parent block"))?;
+                    new_chain.push((number - 1, hash));
+                    parent_hash = parent.parent_hash;
+                }
+            }
+        }
+        new_chain.reverse();
+
+        let (lowest, _) = new_chain[0];
+        let mut dropped = Vec::new();
+        for (number, hash) in self.blocks.split_off(&lowest) {
+            let canonical =
+                number <= block.number && new_chain[(number - lowest).as_usize()] == (number, hash);
+            if !canonical {
+                dropped.push(DroppedBlock { hash, number });
+            }
+        }
+        self.blocks.extend(new_chain);
+        while self.blocks.len() > MAX_REORG_DEPTH {
+            self.blocks.pop_first();
+        }
+        Ok(dropped)
+    }
 }
 
-impl<M> BlockCollector<M> {
-    pub fn new(provider: Arc<M>) -> Self {
+impl BlockCollector {
+    pub fn new(provider: Arc<ResilientWsProvider>) -> Self {
         Self { provider }
     }
 }
 
 /// Implementation of the [Collector](Collector) trait for the [BlockCollector](BlockCollector).
-/// This implementation uses the [PubsubClient](PubsubClient) to subscribe to new blocks.
+/// This implementation uses the [ResilientWsProvider](ResilientWsProvider) to subscribe to new
+/// blocks across reconnects.
 #[async_trait]
-impl<M> Collector<NewBlock> for BlockCollector<M>
-where
-    M: Middleware,
-    M::Provider: PubsubClient,
-    M::Error: 'static,
-{
-    async fn get_event_stream(&self) -> Result<CollectorStream<'_, NewBlock>> {
-        let stream = self.provider.subscribe_blocks().await?;
-        let stream = stream.filter_map(|block| match block.hash {
-            Some(hash) => block.number.map(|number| NewBlock {
-                hash,
-                number,
-                timestamp: block.timestamp,
-            }),
-            None => None,
-        });
+impl Collector<BlockEvent> for BlockCollector {
+    async fn get_event_stream(&self) -> Result<CollectorStream<'_, BlockEvent>> {
+        let blocks = self.provider.subscribe_blocks();
+        let stream = async_stream::stream! {
+            futures::pin_mut!(blocks);
+            let mut canonical = CanonicalBlocks::default();
+            while let Some(block) = blocks.next().await {
+                let new_block = match NewBlock::from_block(&block) {
+                    Some(new_block) => new_block,
+                    None => continue,
+                };
+                let provider = self.provider.provider().await;
+                match canonical.insert(provider.as_ref(), &new_block).await {
+                    Ok(dropped_blocks) if !dropped_blocks.is_empty() => {
+                        info!(
+                            "Reorg of depth {} at block {}",
+                            dropped_blocks.len(),
+                            new_block.number
+                        );
+                        yield BlockEvent::Reorg(Reorg {
+                            depth: dropped_blocks.len() as u64,
+                            dropped_blocks,
+                        });
+                    }
+                    Ok(_) => {}
+                    Err(e) => {
+                        error!("failed to check block {} for reorgs: {}", new_block.number, e)
+                    }
+                }
+                yield BlockEvent::NewBlock(new_block);
+            }
+        };
         Ok(Box::pin(stream))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{next_base_fee, CanonicalBlocks, DroppedBlock, NewBlock};
+    use ethers::{
+        providers::Provider,
+        types::{Block, H160, H256, U256, U64},
+    };
+
+    fn new_block(number: u64, hash: u8, parent_hash: u8) -> NewBlock {
+        NewBlock {
+            hash: H256::repeat_byte(hash),
+            parent_hash: H256::repeat_byte(parent_hash),
+            number: U64::from(number),
+            timestamp: U256::from(number * 12),
+            base_fee_per_gas: None,
+            gas_used: U256::zero(),
+            gas_limit: U256::zero(),
+            miner: H160::zero(),
+            next_base_fee: None,
+        }
+    }
+
+    fn dropped_block(number: u64, hash: u8) -> DroppedBlock {
+        DroppedBlock {
+            hash: H256::repeat_byte(hash),
+            number: U64::from(number),
+        }
+    }
+
+    #[tokio::test]
+    async fn extends_chain_without_reorg() {
+        let (provider, _mock) = Provider::mocked();
+        let mut canonical = CanonicalBlocks::default();
+
+        for block in [new_block(1, 1, 0), new_block(2, 2, 1), new_block(3, 3, 2)] {
+            assert!(canonical
+                .insert(&provider, &block)
+                .await
+                .unwrap()
+                .is_empty());
+        }
+        // a head seen again isn't a reorg
+        let dropped = canonical.insert(&provider, &new_block(3, 3, 2)).await;
+        assert!(dropped.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn detects_replaced_head() {
+        let (provider, _mock) = Provider::mocked();
+        let mut canonical = CanonicalBlocks::default();
+        for block in [new_block(1, 1, 0), new_block(2, 2, 1), new_block(3, 3, 2)] {
+            canonical.insert(&provider, &block).await.unwrap();
+        }
+
+        let dropped = canonical
+            .insert(&provider, &new_block(3, 0x33, 2))
+            .await
+            .unwrap();
+        assert_eq!(dropped, vec![dropped_block(3, 3)]);
+    }
+
+    #[tokio::test]
+    async fn fetches_unknown_ancestors() {
+        let (provider, mock) = Provider::mocked();
+        let mut canonical = CanonicalBlocks::default();
+        for block in [new_block(1, 1, 0), new_block(2, 2, 1), new_block(3, 3, 2)] {
+            canonical.insert(&provider, &block).await.unwrap();
+        }
+        // the new head 4' builds on 3' and 2', which weren't announced
+        mock.push(Block::<H256> {
+            hash: Some(H256::repeat_byte(0x22)),
+            parent_hash: H256::repeat_byte(1),
+            number: Some(U64::from(2)),
+            ..Default::default()
+        })
+        .unwrap();
+        mock.push(Block::<H256> {
+            hash: Some(H256::repeat_byte(0x33)),
+            parent_hash: H256::repeat_byte(0x22),
+            number: Some(U64::from(3)),
+            ..Default::default()
+        })
+        .unwrap();
+
+        let dropped = canonical
+            .insert(&provider, &new_block(4, 0x44, 0x33))
+            .await
+            .unwrap();
+        assert_eq!(dropped, vec![dropped_block(2, 2), dropped_block(3, 3)]);
+
+        // the fetched ancestors are now canonical
+        let dropped = canonical.insert(&provider, &new_block(5, 0x55, 0x44)).await;
+        assert!(dropped.unwrap().is_empty());
+    }
+
+    #[test]
+    fn predicts_next_base_fee() {
+        let gwei = U256::from(1_000_000_000u64);
+        let gas_limit = U256::from(30_000_000u64);
+
+        // at the target the base fee stays the same
+        let base_fee = next_base_fee(gwei * 10, U256::from(15_000_000u64), gas_limit);
+        assert_eq!(base_fee, gwei * 10);
+        // full blocks raise it by an eighth
+        let base_fee = next_base_fee(gwei * 10, gas_limit, gas_limit);
+        assert_eq!(base_fee, U256::from(11_250_000_000u64));
+        // empty blocks lower it by an eighth
+        let base_fee = next_base_fee(gwei * 10, U256::zero(), gas_limit);
+        assert_eq!(base_fee, U256::from(8_750_000_000u64));
+        // any usage above the target raises it by at least 1 wei
+        let base_fee = next_base_fee(U256::from(7), U256::from(15_000_001u64), gas_limit);
+        assert_eq!(base_fee, U256::from(8));
+    }
+
+    #[test]
+    fn builds_new_block_with_gas_data() {
+        let block = Block::<H256> {
+            hash: Some(H256::repeat_byte(2)),
+            parent_hash: H256::repeat_byte(1),
+            number: Some(U64::from(2)),
+            timestamp: U256::from(24),
+            base_fee_per_gas: Some(U256::from(80)),
+            gas_used: U256::from(30_000_000u64),
+            gas_limit: U256::from(30_000_000u64),
+            author: Some(H160::repeat_byte(3)),
+            ..Default::default()
+        };
+
+        let new_block = NewBlock::from_block(&block).unwrap();
+        assert_eq!(new_block.hash, H256::repeat_byte(2));
+        assert_eq!(new_block.base_fee_per_gas, Some(U256::from(80)));
+        assert_eq!(new_block.gas_used, U256::from(30_000_000u64));
+        assert_eq!(new_block.gas_limit, U256::from(30_000_000u64));
+        assert_eq!(new_block.miner, H160::repeat_byte(3));
+        assert_eq!(new_block.next_base_fee, Some(U256::from(90)));
+
+        let pending = Block::<H256> {
+            hash: None,
+            ..block
+        };
+        assert!(NewBlock::from_block(&pending).is_none());
+    }
+}
@@ -1,3 +1,6 @@
 pub mod block_collector;
+pub mod permit2_collector;
+pub mod uniswapx_fill_collector;
 pub mod uniswapx_order_collector;
 pub mod uniswapx_route_collector;
+pub mod uniswapx_webhook_collector;
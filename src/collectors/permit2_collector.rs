@@ -0,0 +1,168 @@
+use crate::providers::resilient_ws_provider::ResilientWsProvider;
+use anyhow::Result;
+use artemis_core::types::{Collector, CollectorStream};
+use async_trait::async_trait;
+use bindings_uniswapx::i_permit_2::{
+    IPermit2Events, LockdownFilter, UnorderedNonceInvalidationFilter,
+};
+use ethers::{
+    contract::{parse_log, EthEvent},
+    types::{Address, Filter, Log},
+};
+use std::sync::Arc;
+use tokio_stream::StreamExt;
+use tracing::error;
+
+/// A Permit2 event that can make open orders unfillable.
+#[derive(Debug, Clone)]
+pub enum Permit2Event {
+    /// The owner invalidated the unordered nonces set in `mask` of bitmap word `word`.
+    UnorderedNonceInvalidation(UnorderedNonceInvalidationFilter),
+    /// The owner revoked the spender's allowance over a token.
+    Lockdown(LockdownFilter),
+}
+
+/// A collector that subscribes to Permit2 `UnorderedNonceInvalidation` and `Lockdown` logs, and
+/// generates a stream of [events](Permit2Event) for swappers cancelling orders. Logs missed while
+/// the subscription was down are backfilled after it reconnects.
+pub struct Permit2Collector {
+    provider: Arc<ResilientWsProvider>,
+    permit2: Address,
+}
+
+impl Permit2Collector {
+    pub fn new(provider: Arc<ResilientWsProvider>, permit2: Address) -> Self {
+        Self { provider, permit2 }
+    }
+
+    fn filter(&self) -> Filter {
+        Filter::new().address(self.permit2).topic0(vec![
+            UnorderedNonceInvalidationFilter::signature(),
+            LockdownFilter::signature(),
+        ])
+    }
+}
+
+// logs removed by a reorg didn't cancel anything
+fn parse_permit2_event(log: Log) -> Option<Permit2Event> {
+    if log.removed == Some(true) {
+        return None;
+    }
+    match parse_log::<IPermit2Events>(log) {
+        Ok(IPermit2Events::UnorderedNonceInvalidationFilter(event)) => {
+            Some(Permit2Event::UnorderedNonceInvalidation(event))
+        }
+        Ok(IPermit2Events::LockdownFilter(event)) => Some(Permit2Event::Lockdown(event)),
+        Ok(_) => None,
+        Err(e) => {
+            error!("failed to decode permit2 log: {}", e);
+            None
+        }
+    }
+}
+
+/// Implementation of the [Collector](Collector) trait for the
+/// [Permit2Collector](Permit2Collector).
+#[async_trait]
+impl Collector<Permit2Event> for Permit2Collector {
+    async fn get_event_stream(&self) -> Result<CollectorStream<'_, Permit2Event>> {
+        let logs = self.provider.subscribe_logs(self.filter());
+        Ok(Box::pin(logs.filter_map(parse_permit2_event)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_permit2_event, Permit2Event};
+    use bindings_uniswapx::i_permit_2::{
+        LockdownFilter, PermitFilter, UnorderedNonceInvalidationFilter,
+    };
+    use ethers::{
+        abi::{encode, Token},
+        contract::EthEvent,
+        types::{Address, Log, H256, U256},
+    };
+
+    fn permit2_log(topics: Vec<H256>, data: Vec<Token>) -> Log {
+        Log {
+            address: Address::repeat_byte(1),
+            topics,
+            data: encode(&data).into(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn parses_unordered_nonce_invalidation_logs() {
+        let log = permit2_log(
+            vec![
+                UnorderedNonceInvalidationFilter::signature(),
+                H256::from(Address::repeat_byte(2)),
+            ],
+            vec![Token::Uint(U256::from(3)), Token::Uint(U256::from(0b101))],
+        );
+
+        match parse_permit2_event(log) {
+            Some(Permit2Event::UnorderedNonceInvalidation(event)) => {
+                assert_eq!(event.owner, Address::repeat_byte(2));
+                assert_eq!(event.word, U256::from(3));
+                assert_eq!(event.mask, U256::from(0b101));
+            }
+            event => panic!("unexpected event {:?}", event),
+        }
+    }
+
+    #[test]
+    fn parses_lockdown_logs() {
+        let log = permit2_log(
+            vec![
+                LockdownFilter::signature(),
+                H256::from(Address::repeat_byte(2)),
+            ],
+            vec![
+                Token::Address(Address::repeat_byte(3)),
+                Token::Address(Address::repeat_byte(4)),
+            ],
+        );
+
+        match parse_permit2_event(log) {
+            Some(Permit2Event::Lockdown(event)) => {
+                assert_eq!(event.owner, Address::repeat_byte(2));
+                assert_eq!(event.token, Address::repeat_byte(3));
+                assert_eq!(event.spender, Address::repeat_byte(4));
+            }
+            event => panic!("unexpected event {:?}", event),
+        }
+    }
+
+    #[test]
+    fn skips_other_and_removed_logs() {
+        let mut log = permit2_log(
+            vec![
+                LockdownFilter::signature(),
+                H256::from(Address::repeat_byte(2)),
+            ],
+            vec![
+                Token::Address(Address::repeat_byte(3)),
+                Token::Address(Address::repeat_byte(4)),
+            ],
+        );
+        log.removed = Some(true);
+        assert!(parse_permit2_event(log).is_none());
+
+        let log = permit2_log(
+            vec![
+                PermitFilter::signature(),
+                H256::from(Address::repeat_byte(2)),
+                H256::from(Address::repeat_byte(3)),
+                H256::from(Address::repeat_byte(4)),
+            ],
+            vec![
+                Token::Uint(U256::from(1000)),
+                Token::Uint(U256::from(1_700_000_000)),
+                Token::Uint(U256::from(1)),
+            ],
+        );
+        assert!(parse_permit2_event(log).is_none());
+    }
+}
@@ -0,0 +1,121 @@
+use crate::providers::resilient_ws_provider::ResilientWsProvider;
+use anyhow::Result;
+use artemis_core::types::{Collector, CollectorStream};
+use async_trait::async_trait;
+use bindings_uniswapx::reactor_events::FillFilter;
+use ethers::{
+    contract::{parse_log, EthEvent},
+    types::{Address, Filter, Log, H256, U64},
+};
+use std::sync::Arc;
+use tokio_stream::StreamExt;
+use tracing::error;
+
+/// A fill and the block it was included in, which orphans it if reorged out.
+#[derive(Debug, Clone)]
+pub struct Fill {
+    pub event: FillFilter,
+    pub block_hash: H256,
+    pub block_number: U64,
+}
+
+/// A collector that subscribes to `Fill` logs of the given reactors, and generates a stream of
+/// [events](Fill) for the orders they fill. Fills missed while the subscription was down
+/// are backfilled after it reconnects.
+pub struct UniswapXFillCollector {
+    provider: Arc<ResilientWsProvider>,
+    reactors: Vec<Address>,
+}
+
+impl UniswapXFillCollector {
+    pub fn new(provider: Arc<ResilientWsProvider>, reactors: Vec<Address>) -> Self {
+        Self { provider, reactors }
+    }
+
+    fn filter(&self) -> Filter {
+        Filter::new()
+            .address(self.reactors.clone())
+            .topic0(FillFilter::signature())
+    }
+}
+
+// logs removed by a reorg didn't fill anything
+fn parse_fill(log: Log) -> Option<Fill> {
+    if log.removed == Some(true) {
+        return None;
+    }
+    let (block_hash, block_number) = (log.block_hash?, log.block_number?);
+    let event = parse_log::<FillFilter>(log)
+        .map_err(|e| error!("failed to decode fill log: {}", e))
+        .ok()?;
+    Some(Fill {
+        event,
+        block_hash,
+        block_number,
+    })
+}
+
+/// Implementation of the [Collector](Collector) trait for the
+/// [UniswapXFillCollector](UniswapXFillCollector).
+#[async_trait]
+impl Collector<Fill> for UniswapXFillCollector {
+    async fn get_event_stream(&self) -> Result<CollectorStream<'_, Fill>> {
+        let logs = self.provider.subscribe_logs(self.filter());
+        Ok(Box::pin(logs.filter_map(parse_fill)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::parse_fill;
+    use bindings_uniswapx::reactor_events::FillFilter;
+    use ethers::{
+        abi::{encode, Token},
+        contract::EthEvent,
+        types::{Address, Log, H256, U256, U64},
+    };
+
+    fn fill_log(order_hash: [u8; 32]) -> Log {
+        Log {
+            address: Address::repeat_byte(1),
+            topics: vec![
+                FillFilter::signature(),
+                H256::from(order_hash),
+                H256::from(Address::repeat_byte(2)),
+                H256::from(Address::repeat_byte(3)),
+            ],
+            data: encode(&[Token::Uint(U256::from(7))]).into(),
+            block_hash: Some(H256::repeat_byte(4)),
+            block_number: Some(U64::from(5)),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn parses_fill_logs() {
+        let fill = parse_fill(fill_log([9u8; 32])).unwrap();
+
+        assert_eq!(fill.event.order_hash, [9u8; 32]);
+        assert_eq!(fill.event.filler, Address::repeat_byte(2));
+        assert_eq!(fill.event.swapper, Address::repeat_byte(3));
+        assert_eq!(fill.event.nonce, U256::from(7));
+        assert_eq!(fill.block_hash, H256::repeat_byte(4));
+        assert_eq!(fill.block_number, U64::from(5));
+    }
+
+    #[test]
+    fn skips_removed_fill_logs() {
+        let mut log = fill_log([9u8; 32]);
+        log.removed = Some(true);
+
+        assert!(parse_fill(log).is_none());
+    }
+
+    #[test]
+    fn skips_pending_fill_logs() {
+        let mut log = fill_log([9u8; 32]);
+        log.block_hash = None;
+
+        assert!(parse_fill(log).is_none());
+    }
+}
@@ -1,15 +1,27 @@
 use anyhow::Result;
 use artemis_core::types::{Collector, CollectorStream};
 use async_trait::async_trait;
+use futures::lock::Mutex;
 use futures::{stream, StreamExt};
 use reqwest::Client;
 use serde::Deserialize;
-use tokio::time::Duration;
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+use tokio::time::{Duration, Instant};
 use tokio_stream::wrappers::IntervalStream;
 
 static UNISWAPX_API_URL: &str = "https://api.uniswap.org/v2";
 static POLL_INTERVAL_SECS: u64 = 5;
-pub const CHAIN_ID: u64 = 1;
+// how long an order that is no longer reported is remembered, so it isn't emitted again
+const SEEN_ORDER_TTL_SECS: u64 = 3600;
+const MAX_SEEN_ORDERS: usize = 100_000;
+static ORDERS_PAGE_LIMIT: u64 = 500;
+// stops following cursors that never end
+static MAX_PAGES: usize = 100;
+// hashes of orders that left the open set asked for per poll, and per request so urls stay short
+const MAX_STATUS_QUERIES: usize = 500;
+const STATUS_QUERY_CHUNK: usize = 50;
+pub const OPEN_ORDER_STATUS: &str = "open";
 
 #[derive(Debug, Clone, Deserialize)]
 pub struct UniswapXOrder {
@@ -24,54 +36,271 @@ pub struct UniswapXOrder {
     pub chain_id: u64,
     #[serde(rename = "orderHash")]
     pub order_hash: String,
+    #[serde(rename = "type", default)]
+    pub order_type: Option<String>,
+    #[serde(default)]
+    pub input: Option<UniswapXOrderToken>,
+    #[serde(default)]
+    pub outputs: Vec<UniswapXOrderToken>,
+}
+
+/// The token of an order input or output, as reported by the API.
+#[derive(Debug, Clone, Deserialize)]
+pub struct UniswapXOrderToken {
+    pub token: String,
 }
 
 /// A new order event, containing the internal order.
 #[derive(Debug, Clone, Deserialize)]
 pub struct UniswapXOrderResponse {
     pub orders: Vec<UniswapXOrder>,
+    // set when there are more orders to fetch
+    #[serde(default)]
+    pub cursor: Option<String>,
+}
+
+/// Restricts the orders the collector emits. The order type and swapper are filtered by the
+/// API, token pairs by the collector.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct OrderFilter {
+    pub order_type: Option<String>,
+    pub swapper: Option<String>,
+    // (input token, output token) pairs, any pair if empty
+    pub token_pairs: Vec<(String, String)>,
+}
+
+impl OrderFilter {
+    fn query(&self) -> Vec<(&'static str, String)> {
+        let mut query = Vec::new();
+        if let Some(order_type) = &self.order_type {
+            query.push(("orderType", order_type.clone()));
+        }
+        if let Some(swapper) = &self.swapper {
+            query.push(("swapper", swapper.clone()));
+        }
+        query
+    }
+
+    fn matches(&self, order: &UniswapXOrder) -> bool {
+        if self.token_pairs.is_empty() {
+            return true;
+        }
+        let input = match &order.input {
+            Some(input) => &input.token,
+            None => return false,
+        };
+        self.token_pairs.iter().any(|(token_in, token_out)| {
+            token_in.eq_ignore_ascii_case(input)
+                && order
+                    .outputs
+                    .iter()
+                    .any(|output| output.token.eq_ignore_ascii_case(token_out))
+        })
+    }
+}
+
+/// Order hashes the collector has emitted, with the last status it emitted for each. Entries
+/// expire once an order hasn't been reported for the TTL, and the oldest are evicted past
+/// `capacity`. Collectors that share it don't emit the same order twice, whatever case each
+/// reports its hash in.
+pub struct SeenOrders {
+    orders: HashMap<String, (String, Instant)>,
+    ttl: Duration,
+    capacity: usize,
+}
+
+impl SeenOrders {
+    fn new(ttl: Duration, capacity: usize) -> Self {
+        Self {
+            orders: HashMap::new(),
+            ttl,
+            capacity,
+        }
+    }
+
+    // hashes are hex, which the API and webhooks don't case the same
+    fn key(order_hash: &str) -> String {
+        order_hash.to_lowercase()
+    }
+
+    /// Records `order` as reported at `now`, returning whether it is new or its status changed.
+    pub(crate) fn update(&mut self, order: &UniswapXOrder, now: Instant) -> bool {
+        let key = Self::key(&order.order_hash);
+        let changed = match self.orders.get(&key) {
+            Some((status, _)) => *status != order.order_status,
+            None => true,
+        };
+        self.orders
+            .insert(key, (order.order_status.clone(), now + self.ttl));
+        changed
+    }
+
+    /// Hashes of at most `limit` orders last seen open that are missing from `open_orders`, the
+    /// longest unreported first.
+    fn closed_orders(&self, open_orders: &[UniswapXOrder], limit: usize) -> Vec<String> {
+        let open: HashSet<String> = open_orders
+            .iter()
+            .map(|order| Self::key(&order.order_hash))
+            .collect();
+        let mut closed: Vec<(&String, Instant)> = self
+            .orders
+            .iter()
+            .filter(|(order_hash, (status, _))| {
+                status == OPEN_ORDER_STATUS && !open.contains(*order_hash)
+            })
+            .map(|(order_hash, (_, expires_at))| (order_hash, *expires_at))
+            .collect();
+        closed.sort_unstable_by_key(|(_, expires_at)| *expires_at);
+        closed
+            .into_iter()
+            .take(limit)
+            .map(|(order_hash, _)| order_hash.clone())
+            .collect()
+    }
+
+    /// Forgets the orders of `order_hashes` that are still recorded open, so orders the API has
+    /// no final status for aren't asked for again every poll.
+    fn forget_open(&mut self, order_hashes: &[String]) {
+        for order_hash in order_hashes {
+            let key = Self::key(order_hash);
+            if let Some((status, _)) = self.orders.get(&key) {
+                if status == OPEN_ORDER_STATUS {
+                    self.orders.remove(&key);
+                }
+            }
+        }
+    }
+
+    pub(crate) fn prune(&mut self, now: Instant) {
+        self.orders.retain(|_, (_, expires_at)| *expires_at > now);
+        if self.orders.len() > self.capacity {
+            let mut expiries: Vec<Instant> = self
+                .orders
+                .values()
+                .map(|(_, expires_at)| *expires_at)
+                .collect();
+            expiries.sort_unstable();
+            let cutoff = expiries[self.orders.len() - self.capacity];
+            self.orders
+                .retain(|_, (_, expires_at)| *expires_at >= cutoff);
+        }
+    }
+}
+
+impl Default for SeenOrders {
+    fn default() -> Self {
+        Self::new(Duration::from_secs(SEEN_ORDER_TTL_SECS), MAX_SEEN_ORDERS)
+    }
 }
 
 /// A collector that listens for new orders on UniswapX, and generates a stream of
-/// [events](UniswapXOrder) which contain the order.
+/// [events](UniswapXOrder) which contain the order. Each order is emitted when it is first
+/// seen open, and again with its new status once the API reports it filled, cancelled or
+/// expired.
 #[derive(Default)]
 pub struct UniswapXOrderCollector {
     pub client: Client,
     pub base_url: String,
+    pub chain_id: u64,
+    pub filter: OrderFilter,
+    pub seen: Arc<Mutex<SeenOrders>>,
 }
 
 impl UniswapXOrderCollector {
-    pub fn new() -> Self {
+    pub fn new(chain_id: u64) -> Self {
         Self {
             client: Client::new(),
             base_url: UNISWAPX_API_URL.to_string(),
+            chain_id,
+            filter: OrderFilter::default(),
+            seen: Arc::new(Mutex::new(SeenOrders::default())),
+        }
+    }
+
+    // fetches every page of orders matching `query`
+    async fn get_orders(&self, query: Vec<(&str, String)>) -> Result<Vec<UniswapXOrder>> {
+        let url = format!("{}/orders", self.base_url);
+        let mut query = query;
+        query.push(("chainId", self.chain_id.to_string()));
+        query.push(("limit", ORDERS_PAGE_LIMIT.to_string()));
+
+        let mut orders = Vec::new();
+        let mut cursor: Option<String> = None;
+        for _ in 0..MAX_PAGES {
+            let mut request = self.client.get(&url).query(&query);
+            if let Some(cursor) = &cursor {
+                request = request.query(&[("cursor", cursor)]);
+            }
+            let data = request
+                .send()
+                .await?
+                .json::<UniswapXOrderResponse>()
+                .await?;
+            orders.extend(data.orders);
+
+            cursor = data.cursor.filter(|cursor| !cursor.is_empty());
+            if cursor.is_none() {
+                break;
+            }
         }
+        Ok(orders)
+    }
+
+    // returns the orders that are new or changed status since the last poll
+    async fn poll(&self, seen: &Mutex<SeenOrders>) -> Result<Vec<UniswapXOrder>> {
+        let mut query = vec![("orderStatus", OPEN_ORDER_STATUS.to_string())];
+        query.extend(self.filter.query());
+        let open_orders: Vec<UniswapXOrder> = self
+            .get_orders(query)
+            .await?
+            .into_iter()
+            .filter(|order| self.filter.matches(order))
+            .collect();
+        let closed_orders = seen
+            .lock()
+            .await
+            .closed_orders(&open_orders, MAX_STATUS_QUERIES);
+
+        // ask for the status of orders that left the open set
+        let mut orders = open_orders;
+        for order_hashes in closed_orders.chunks(STATUS_QUERY_CHUNK) {
+            orders.extend(
+                self.get_orders(vec![("orderHashes", order_hashes.join(","))])
+                    .await?
+                    .into_iter()
+                    .filter(|order| order.order_status != OPEN_ORDER_STATUS),
+            );
+        }
+
+        let now = Instant::now();
+        let mut seen = seen.lock().await;
+        let orders = orders
+            .into_iter()
+            .filter(|order| seen.update(order, now))
+            .collect();
+        // orders reported filled, cancelled or expired are done; the rest are emitted again if
+        // they reopen
+        seen.forget_open(&closed_orders);
+        seen.prune(now);
+        Ok(orders)
     }
 }
 
 /// Implementation of the [Collector](Collector) trait for the
 /// [UniswapXOrderCollector](UniswapXOrderCollector).
-// TODO: implement order deduplication
 #[async_trait]
 impl Collector<UniswapXOrder> for UniswapXOrderCollector {
     async fn get_event_stream(&self) -> Result<CollectorStream<'_, UniswapXOrder>> {
-        let url = format!(
-            "{}/orders?orderStatus=open&chainId={}",
-            self.base_url, CHAIN_ID
-        );
+        let seen = self.seen.clone();
 
         // stream that polls the UniswapX API every 5 seconds
         let stream = IntervalStream::new(tokio::time::interval(Duration::from_secs(
             POLL_INTERVAL_SECS,
         )))
         .then(move |_| {
-            let url = url.clone();
-            let client = self.client.clone();
-            async move {
-                let response = client.get(url).send().await?;
-                let data = response.json::<UniswapXOrderResponse>().await?;
-                Ok(data.orders)
-            }
+            let seen = seen.clone();
+            async move { self.poll(&seen).await }
         })
         .flat_map(
             |values_result: Result<Vec<UniswapXOrder>>| match values_result {
@@ -92,10 +321,16 @@ impl Collector<UniswapXOrder> for UniswapXOrderCollector {
 
 #[cfg(test)]
 mod tests {
-    use crate::collectors::uniswapx_order_collector::UniswapXOrderCollector;
+    use crate::collectors::uniswapx_order_collector::{
+        OrderFilter, SeenOrders, UniswapXOrder, UniswapXOrderCollector,
+    };
     use artemis_core::types::Collector;
+    use futures::lock::Mutex;
     use futures::StreamExt;
     use mockito::{Mock, Server, ServerGuard};
+    use tokio::time::{Duration, Instant};
+    use uniswapx_rs::builder::{OrderSigner, V2DutchOrderBuilder};
+    use uniswapx_rs::order::Order;
 
     async fn get_collector(mock_response: &str) -> (UniswapXOrderCollector, ServerGuard, Mock) {
         let mut server = Server::new_async().await;
@@ -112,6 +347,9 @@ mod tests {
         let res = UniswapXOrderCollector {
             client: reqwest::Client::new(),
             base_url: url.clone(),
+            chain_id: 1,
+            filter: OrderFilter::default(),
+            seen: Default::default(),
         };
 
         (res, server, mock)
@@ -140,4 +378,276 @@ mod tests {
         );
         mock.assert_async().await;
     }
+
+    #[tokio::test]
+    async fn creates_order_stream_from_generated_orders() {
+        let swapper = OrderSigner::random(1);
+        let cosigner = OrderSigner::random(1);
+        let mut order = Order::V2Dutch(
+            V2DutchOrderBuilder::new()
+                .swapper(swapper.address())
+                .build(),
+        );
+        cosigner.cosign(&mut order).unwrap();
+        let signed = swapper.sign(order).unwrap();
+
+        let response = format!(
+            r#"{{"orders":[{{"encodedOrder":"{}","signature":"{}","orderStatus":"open","createdAt":1685895015,"chainId":1,"orderHash":"{}","type":"Dutch_V2"}}]}}"#,
+            signed.encoded_order(),
+            signed.signature(),
+            signed.order_hash()
+        );
+        let (collector, _server, mock) = get_collector(&response).await;
+        let stream = collector.get_event_stream().await.unwrap();
+        let (order, _) = stream.into_future().await;
+
+        let order = order.unwrap();
+        assert_eq!(order.order_hash, signed.order_hash());
+        let decoded = Order::decode(&order.encoded_order, None).unwrap();
+        assert_eq!(decoded.hash(), signed.order.hash());
+        assert!(decoded.verify_cosignature(1));
+        mock.assert_async().await;
+    }
+
+    fn api_order(order_hash: &str, order_status: &str) -> UniswapXOrder {
+        UniswapXOrder {
+            encoded_order: "0x".to_string(),
+            signature: "0x".to_string(),
+            order_status: order_status.to_string(),
+            created_at: 1685895015,
+            chain_id: 1,
+            order_hash: order_hash.to_string(),
+            order_type: None,
+            input: None,
+            outputs: vec![],
+        }
+    }
+
+    fn api_response(orders: &[(&str, &str)]) -> String {
+        let orders: Vec<String> = orders
+            .iter()
+            .map(|(order_hash, order_status)| {
+                format!(
+                    r#"{{"encodedOrder":"0x","signature":"0x","orderStatus":"{}","createdAt":1685895015,"chainId":1,"orderHash":"{}"}}"#,
+                    order_status, order_hash
+                )
+            })
+            .collect();
+        format!(r#"{{"orders":[{}]}}"#, orders.join(","))
+    }
+
+    #[test]
+    fn seen_orders_only_reports_new_orders_and_status_changes() {
+        let mut seen = SeenOrders::new(Duration::from_secs(60), 10);
+        let now = Instant::now();
+
+        assert!(seen.update(&api_order("0x01", "open"), now));
+        assert!(!seen.update(&api_order("0x01", "open"), now));
+        assert_eq!(seen.closed_orders(&[], 10), vec!["0x01".to_string()]);
+        assert!(seen
+            .closed_orders(&[api_order("0x01", "open")], 10)
+            .is_empty());
+
+        assert!(seen.update(&api_order("0x01", "filled"), now));
+        assert!(!seen.update(&api_order("0x01", "filled"), now));
+        assert!(seen.closed_orders(&[], 10).is_empty());
+
+        // the same hash cased differently
+        assert!(seen.update(&api_order("0xAB", "open"), now));
+        assert!(!seen.update(&api_order("0xab", "open"), now));
+        assert!(seen
+            .closed_orders(&[api_order("0xAb", "open")], 10)
+            .is_empty());
+    }
+
+    #[test]
+    fn seen_orders_limits_and_forgets_closed_orders() {
+        let mut seen = SeenOrders::new(Duration::from_secs(60), 10);
+        let now = Instant::now();
+
+        seen.update(&api_order("0x01", "open"), now + Duration::from_secs(2));
+        seen.update(&api_order("0x02", "open"), now);
+        seen.update(&api_order("0x03", "open"), now + Duration::from_secs(1));
+        // the longest unreported first
+        let closed = seen.closed_orders(&[], 2);
+        assert_eq!(closed, vec!["0x02".to_string(), "0x03".to_string()]);
+
+        // 0x02 is reported filled, 0x03 isn't reported at all
+        seen.update(&api_order("0x02", "filled"), now);
+        seen.forget_open(&closed);
+        assert!(!seen.update(&api_order("0x02", "filled"), now));
+        assert_eq!(seen.closed_orders(&[], 2), vec!["0x01".to_string()]);
+        assert!(seen.update(&api_order("0x03", "open"), now));
+    }
+
+    #[test]
+    fn seen_orders_expire_and_are_bounded() {
+        let mut seen = SeenOrders::new(Duration::from_secs(60), 2);
+        let now = Instant::now();
+
+        seen.update(&api_order("0x01", "open"), now);
+        seen.update(&api_order("0x02", "open"), now + Duration::from_secs(1));
+        seen.update(&api_order("0x03", "open"), now + Duration::from_secs(2));
+        seen.prune(now);
+        // the oldest order is evicted past the capacity
+        assert!(seen.update(&api_order("0x01", "open"), now));
+
+        seen.prune(now + Duration::from_secs(120));
+        assert!(seen.orders.is_empty());
+    }
+
+    #[tokio::test]
+    async fn polls_only_emit_new_orders_and_status_changes() {
+        let mut server = Server::new_async().await;
+        let open_mock = server
+            .mock("GET", "/orders")
+            .match_query(mockito::Matcher::UrlEncoded(
+                "orderStatus".into(),
+                "open".into(),
+            ))
+            .with_body(api_response(&[("0x01", "open"), ("0x02", "open")]))
+            .create_async()
+            .await;
+        let collector = UniswapXOrderCollector {
+            client: reqwest::Client::new(),
+            base_url: server.url(),
+            chain_id: 1,
+            filter: OrderFilter::default(),
+            seen: Default::default(),
+        };
+        let seen = Mutex::new(SeenOrders::new(Duration::from_secs(60), 10));
+
+        let orders = collector.poll(&seen).await.unwrap();
+        assert_eq!(orders.len(), 2);
+        let orders = collector.poll(&seen).await.unwrap();
+        assert!(orders.is_empty());
+
+        // 0x02 leaves the open set and is reported filled
+        open_mock.remove_async().await;
+        server
+            .mock("GET", "/orders")
+            .match_query(mockito::Matcher::UrlEncoded(
+                "orderStatus".into(),
+                "open".into(),
+            ))
+            .with_body(api_response(&[("0x01", "open"), ("0x03", "open")]))
+            .create_async()
+            .await;
+        let status_mock = server
+            .mock("GET", "/orders")
+            .match_query(mockito::Matcher::UrlEncoded(
+                "orderHashes".into(),
+                "0x02".into(),
+            ))
+            .with_body(api_response(&[("0x02", "filled")]))
+            .create_async()
+            .await;
+
+        let orders = collector.poll(&seen).await.unwrap();
+        let mut orders: Vec<(String, String)> = orders
+            .into_iter()
+            .map(|order| (order.order_hash, order.order_status))
+            .collect();
+        orders.sort();
+        assert_eq!(
+            orders,
+            vec![
+                ("0x02".to_string(), "filled".to_string()),
+                ("0x03".to_string(), "open".to_string()),
+            ]
+        );
+        status_mock.assert_async().await;
+    }
+
+    #[tokio::test]
+    async fn follows_cursor_pagination() {
+        let mut server = Server::new_async().await;
+        let first_page = server
+            .mock("GET", "/orders")
+            .match_query(mockito::Matcher::Exact(
+                "orderStatus=open&chainId=1&limit=500".into(),
+            ))
+            .with_body(r#"{"orders":[{"encodedOrder":"0x","signature":"0x","orderStatus":"open","createdAt":1685895015,"chainId":1,"orderHash":"0x01"}],"cursor":"next"}"#)
+            .create_async()
+            .await;
+        let second_page = server
+            .mock("GET", "/orders")
+            .match_query(mockito::Matcher::UrlEncoded("cursor".into(), "next".into()))
+            .with_body(api_response(&[("0x02", "open")]))
+            .create_async()
+            .await;
+        let collector = UniswapXOrderCollector {
+            client: reqwest::Client::new(),
+            base_url: server.url(),
+            chain_id: 1,
+            filter: OrderFilter::default(),
+            seen: Default::default(),
+        };
+        let seen = Mutex::new(SeenOrders::new(Duration::from_secs(60), 10));
+
+        let orders = collector.poll(&seen).await.unwrap();
+        let order_hashes: Vec<&str> = orders
+            .iter()
+            .map(|order| order.order_hash.as_str())
+            .collect();
+        assert_eq!(order_hashes, vec!["0x01", "0x02"]);
+        first_page.assert_async().await;
+        second_page.assert_async().await;
+    }
+
+    #[tokio::test]
+    async fn filters_orders() {
+        let mut server = Server::new_async().await;
+        let order = |order_hash: &str, token_in: &str, token_out: &str| {
+            format!(
+                r#"{{"encodedOrder":"0x","signature":"0x","orderStatus":"open","createdAt":1685895015,"chainId":1,"orderHash":"{}","type":"Dutch_V2","input":{{"token":"{}","startAmount":"1","endAmount":"1"}},"outputs":[{{"token":"{}","startAmount":"2","endAmount":"1","recipient":"0x0000000000000000000000000000000000000002"}}]}}"#,
+                order_hash, token_in, token_out
+            )
+        };
+        let mock = server
+            .mock("GET", "/orders")
+            .match_query(mockito::Matcher::AllOf(vec![
+                mockito::Matcher::UrlEncoded("orderStatus".into(), "open".into()),
+                mockito::Matcher::UrlEncoded("orderType".into(), "Dutch_V2".into()),
+                mockito::Matcher::UrlEncoded(
+                    "swapper".into(),
+                    "0x0000000000000000000000000000000000000001".into(),
+                ),
+            ]))
+            .with_body(format!(
+                r#"{{"orders":[{},{}]}}"#,
+                order(
+                    "0x01",
+                    "0xc02aaa39b223fe8d0a0e5c4f27ead9083c756cc2",
+                    "0xa0b86991c6218b36c1d19d4a2e9eb0ce3606eb48"
+                ),
+                order(
+                    "0x02",
+                    "0xc02aaa39b223fe8d0a0e5c4f27ead9083c756cc2",
+                    "0xdac17f958d2ee523a2206206994597c13d831ec7"
+                )
+            ))
+            .create_async()
+            .await;
+        let collector = UniswapXOrderCollector {
+            client: reqwest::Client::new(),
+            base_url: server.url(),
+            chain_id: 1,
+            filter: OrderFilter {
+                order_type: Some("Dutch_V2".to_string()),
+                swapper: Some("0x0000000000000000000000000000000000000001".to_string()),
+                token_pairs: vec![(
+                    "0xC02aaA39b223FE8D0A0e5C4F27eAD9083C756Cc2".to_string(),
+                    "0xA0b86991c6218b36c1d19D4a2e9Eb0cE3606eB48".to_string(),
+                )],
+            },
+            seen: Default::default(),
+        };
+        let seen = Mutex::new(SeenOrders::new(Duration::from_secs(60), 10));
+
+        let orders = collector.poll(&seen).await.unwrap();
+        assert_eq!(orders.len(), 1);
+        assert_eq!(orders[0].order_hash, "0x01");
+        mock.assert_async().await;
+    }
 }
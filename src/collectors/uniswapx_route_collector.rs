@@ -1,26 +1,27 @@
-use crate::collectors::uniswapx_order_collector::CHAIN_ID;
 use alloy_primitives::Uint;
 use anyhow::Result;
 use reqwest::header::ORIGIN;
 use serde::{Deserialize, Serialize};
 use tokio::sync::mpsc::{Receiver, Sender};
 use tracing::info;
-use uniswapx_rs::order::{ExclusiveDutchOrder, ResolvedOrder};
+use uniswapx_rs::order::{Order, ResolvedOrder};
 
-use crate::strategies::uniswapx_strategy::EXECUTOR_ADDRESS;
+use crate::strategies::types::ChainConfig;
 use artemis_core::types::{Collector, CollectorStream};
 use async_trait::async_trait;
 use futures::lock::Mutex;
 use futures::stream::{FuturesUnordered, StreamExt};
 use reqwest::Client;
+use std::sync::Arc;
 
 const ROUTING_API: &str = "https://api.uniswap.org/v1/quote";
 const SLIPPAGE_TOLERANCE: &str = "0.5";
-const DEADLINE: u64 = 1000;
+/// Seconds a routed swap stays valid for.
+pub const DEADLINE: u64 = 1000;
 
 #[derive(Debug, Clone)]
 pub struct OrderData {
-    pub order: ExclusiveDutchOrder,
+    pub order: Order,
     pub hash: String,
     pub signature: String,
     pub resolved: ResolvedOrder,
@@ -31,6 +32,7 @@ pub struct OrderBatchData {
     pub orders: Vec<OrderData>,
     pub amount_in: Uint<256, 4>,
     pub amount_out_required: Uint<256, 4>,
+    pub reactor: String,
     pub token_in: String,
     pub token_out: String,
 }
@@ -63,29 +65,29 @@ struct RoutingApiQuery {
 #[serde(rename_all = "camelCase")]
 #[allow(dead_code)]
 pub struct TokenInRoute {
-    address: String,
-    chain_id: u64,
-    symbol: String,
-    decimals: String,
+    pub address: String,
+    pub chain_id: u64,
+    pub symbol: String,
+    pub decimals: String,
 }
 
 #[derive(Clone, Debug, Deserialize)]
 #[serde(rename_all = "camelCase")]
 #[allow(dead_code)]
 pub struct V3Route {
-    address: String,
-    token_in: TokenInRoute,
-    token_out: TokenInRoute,
-    fee: String,
+    pub address: String,
+    pub token_in: TokenInRoute,
+    pub token_out: TokenInRoute,
+    pub fee: String,
 }
 
 #[derive(Clone, Debug, Deserialize)]
 #[serde(rename_all = "camelCase")]
 #[allow(dead_code)]
 pub struct V2Route {
-    address: String,
-    token_in: TokenInRoute,
-    token_out: TokenInRoute,
+    pub address: String,
+    pub token_in: TokenInRoute,
+    pub token_out: TokenInRoute,
 }
 
 #[derive(Clone, Debug, Deserialize)]
@@ -117,6 +119,9 @@ pub struct OrderRoute {
 }
 
 pub struct RouteOrderParams {
+    pub chain_id: u64,
+    // the executor the swap is routed to
+    pub recipient: String,
     pub token_in: String,
     pub token_out: String,
     pub amount: String,
@@ -128,6 +133,23 @@ pub struct RoutedOrder {
     pub request: OrderBatchData,
 }
 
+/// Finds a route to swap the input of an order batch into its output.
+#[async_trait]
+pub trait Router: Send + Sync {
+    async fn route(&self, params: RouteOrderParams) -> Result<OrderRoute>;
+}
+
+/// A [Router](Router) backed by the Uniswap routing API.
+#[derive(Debug, Default)]
+pub struct RoutingApi;
+
+#[async_trait]
+impl Router for RoutingApi {
+    async fn route(&self, params: RouteOrderParams) -> Result<OrderRoute> {
+        route_order(params).await
+    }
+}
+
 /// A new order event, containing the internal order.
 #[derive(Debug, Clone, Deserialize)]
 pub struct RouteResponse {
@@ -138,17 +160,23 @@ pub struct RouteResponse {
 /// [events](Route) which contain the order.
 pub struct UniswapXRouteCollector {
     pub client: Client,
+    pub chain: ChainConfig,
+    pub router: Arc<dyn Router>,
     pub route_request_receiver: Mutex<Receiver<Vec<OrderBatchData>>>,
     pub route_sender: Sender<RoutedOrder>,
 }
 
 impl UniswapXRouteCollector {
     pub fn new(
+        chain: ChainConfig,
+        router: Arc<dyn Router>,
         route_request_receiver: Receiver<Vec<OrderBatchData>>,
         route_sender: Sender<RoutedOrder>,
     ) -> Self {
         Self {
             client: Client::new(),
+            chain,
+            router,
             route_request_receiver: Mutex::new(route_request_receiver),
             route_sender,
         }
@@ -173,7 +201,9 @@ impl Collector<RoutedOrder> for UniswapXRouteCollector {
                         );
 
                         async move {
-                            (batch, route_order(RouteOrderParams {
+                            (batch, self.router.route(RouteOrderParams {
+                                chain_id: self.chain.chain_id,
+                                recipient: self.chain.executor.clone(),
                                 token_in: token_in.clone(),
                                 token_out: token_out.clone(),
                                 amount: amount_in.to_string(),
@@ -202,11 +232,11 @@ pub async fn route_order(params: RouteOrderParams) -> Result<OrderRoute> {
     let query = RoutingApiQuery {
         token_in_address: params.token_in,
         token_out_address: params.token_out,
-        token_in_chain_id: CHAIN_ID,
-        token_out_chain_id: CHAIN_ID,
+        token_in_chain_id: params.chain_id,
+        token_out_chain_id: params.chain_id,
         trade_type: TradeType::ExactIn,
         amount: params.amount,
-        recipient: EXECUTOR_ADDRESS.to_string(),
+        recipient: params.recipient,
         slippage_tolerance: SLIPPAGE_TOLERANCE.to_string(),
         deadline: DEADLINE,
     };
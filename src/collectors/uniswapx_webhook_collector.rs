@@ -0,0 +1,304 @@
+use crate::collectors::uniswapx_order_collector::{SeenOrders, UniswapXOrder, OPEN_ORDER_STATUS};
+use anyhow::{anyhow, Result};
+use artemis_core::types::{Collector, CollectorStream};
+use async_trait::async_trait;
+use futures::lock::Mutex;
+use hyper::body::HttpBody;
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{Body, Method, Request, Response, Server, StatusCode};
+use serde::Deserialize;
+use std::convert::Infallible;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use tokio::sync::mpsc::{self, Sender};
+use tokio::time::Instant;
+use tokio_stream::wrappers::ReceiverStream;
+use tracing::{error, info};
+
+// orders are a few kilobytes at most
+const MAX_BODY_BYTES: usize = 64 * 1024;
+
+/// An order as posted to filler webhooks.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct WebhookOrder {
+    encoded_order: String,
+    signature: String,
+    order_hash: String,
+    created_at: u64,
+    chain_id: u64,
+    #[serde(default)]
+    order_status: Option<String>,
+    #[serde(rename = "type", default)]
+    order_type: Option<String>,
+}
+
+fn is_hex(value: &str, length: Option<usize>) -> bool {
+    match value.strip_prefix("0x") {
+        Some(hex) => {
+            length.map_or(true, |length| hex.len() == length)
+                && hex.chars().all(|c| c.is_ascii_hexdigit())
+        }
+        None => false,
+    }
+}
+
+impl WebhookOrder {
+    fn validate(self, chain_id: u64) -> Result<UniswapXOrder> {
+        if self.chain_id != chain_id {
+            return Err(anyhow!("unsupported chain id {}", self.chain_id));
+        }
+        if !is_hex(&self.order_hash, Some(64)) {
+            return Err(anyhow!("invalid order hash {}", self.order_hash));
+        }
+        if !is_hex(&self.encoded_order, None) || self.encoded_order.len() % 2 != 0 {
+            return Err(anyhow!("invalid encoded order"));
+        }
+        if !is_hex(&self.signature, None) || self.signature.len() % 2 != 0 {
+            return Err(anyhow!("invalid signature"));
+        }
+        // webhooks are only sent for new orders
+        let order_status = self
+            .order_status
+            .unwrap_or_else(|| OPEN_ORDER_STATUS.to_string());
+        if order_status != OPEN_ORDER_STATUS {
+            return Err(anyhow!("unexpected order status {}", order_status));
+        }
+
+        Ok(UniswapXOrder {
+            encoded_order: self.encoded_order,
+            signature: self.signature,
+            order_status,
+            created_at: self.created_at,
+            chain_id: self.chain_id,
+            order_hash: self.order_hash,
+            order_type: self.order_type,
+            input: None,
+            outputs: vec![],
+        })
+    }
+}
+
+fn response(status: StatusCode) -> Response<Body> {
+    let mut response = Response::new(Body::empty());
+    *response.status_mut() = status;
+    response
+}
+
+async fn handle_request(
+    request: Request<Body>,
+    chain_id: u64,
+    seen: Arc<Mutex<SeenOrders>>,
+    sender: Sender<UniswapXOrder>,
+) -> Result<Response<Body>, Infallible> {
+    if request.method() != Method::POST {
+        return Ok(response(StatusCode::METHOD_NOT_ALLOWED));
+    }
+
+    if request.body().size_hint().lower() > MAX_BODY_BYTES as u64 {
+        return Ok(response(StatusCode::PAYLOAD_TOO_LARGE));
+    }
+    let body = match hyper::body::to_bytes(request.into_body()).await {
+        Ok(body) if body.len() <= MAX_BODY_BYTES => body,
+        Ok(_) => return Ok(response(StatusCode::PAYLOAD_TOO_LARGE)),
+        Err(_) => return Ok(response(StatusCode::BAD_REQUEST)),
+    };
+    let order = match serde_json::from_slice::<WebhookOrder>(&body)
+        .map_err(|e| anyhow!(e))
+        .and_then(|order| order.validate(chain_id))
+    {
+        Ok(order) => order,
+        Err(e) => {
+            info!("Rejecting webhook order: {}", e);
+            return Ok(response(StatusCode::BAD_REQUEST));
+        }
+    };
+
+    // the polling collector may have emitted the order already
+    let now = Instant::now();
+    let is_new = {
+        let mut seen = seen.lock().await;
+        let is_new = seen.update(&order, now);
+        seen.prune(now);
+        is_new
+    };
+    if is_new && sender.send(order).await.is_err() {
+        return Ok(response(StatusCode::SERVICE_UNAVAILABLE));
+    }
+    Ok(response(StatusCode::OK))
+}
+
+/// A collector that runs an HTTP server for UniswapX filler webhooks, and generates a stream of
+/// [events](UniswapXOrder) for the orders posted to it. Sharing `seen` with a
+/// [UniswapXOrderCollector](crate::collectors::uniswapx_order_collector::UniswapXOrderCollector)
+/// lets both run without emitting an order twice.
+pub struct UniswapXWebhookCollector {
+    pub address: SocketAddr,
+    pub chain_id: u64,
+    pub seen: Arc<Mutex<SeenOrders>>,
+}
+
+impl UniswapXWebhookCollector {
+    pub fn new(address: SocketAddr, chain_id: u64, seen: Arc<Mutex<SeenOrders>>) -> Self {
+        Self {
+            address,
+            chain_id,
+            seen,
+        }
+    }
+}
+
+/// Implementation of the [Collector](Collector) trait for the
+/// [UniswapXWebhookCollector](UniswapXWebhookCollector).
+#[async_trait]
+impl Collector<UniswapXOrder> for UniswapXWebhookCollector {
+    async fn get_event_stream(&self) -> Result<CollectorStream<'_, UniswapXOrder>> {
+        let (sender, receiver) = mpsc::channel(512);
+        let chain_id = self.chain_id;
+        let seen = self.seen.clone();
+        let make_service = make_service_fn(move |_| {
+            let seen = seen.clone();
+            let sender = sender.clone();
+            async move {
+                Ok::<_, Infallible>(service_fn(move |request| {
+                    handle_request(request, chain_id, seen.clone(), sender.clone())
+                }))
+            }
+        });
+
+        let server = Server::try_bind(&self.address)?.serve(make_service);
+        info!("Listening for webhook orders on {}", self.address);
+        tokio::spawn(async move {
+            if let Err(e) = server.await {
+                error!("Webhook server error: {}", e);
+            }
+        });
+
+        Ok(Box::pin(ReceiverStream::new(receiver)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::collectors::uniswapx_order_collector::{SeenOrders, UniswapXOrder};
+    use crate::collectors::uniswapx_webhook_collector::UniswapXWebhookCollector;
+    use artemis_core::types::Collector;
+    use futures::lock::Mutex;
+    use futures::StreamExt;
+    use std::net::{SocketAddr, TcpListener};
+    use std::sync::Arc;
+    use tokio::time::Instant;
+
+    fn free_address() -> SocketAddr {
+        TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+    }
+
+    fn webhook_body(order_hash: &str) -> String {
+        format!(
+            r#"{{"encodedOrder":"0x00","signature":"0x01","orderHash":"{}","createdAt":1685895015,"chainId":1,"type":"Dutch_V2","filler":"0x0000000000000000000000000000000000000001"}}"#,
+            order_hash
+        )
+    }
+
+    fn order_hash(byte: u8) -> String {
+        format!("0x{}", format!("{:02x}", byte).repeat(32))
+    }
+
+    async fn post(address: SocketAddr, body: String) -> reqwest::StatusCode {
+        reqwest::Client::new()
+            .post(format!("http://{}", address))
+            .body(body)
+            .send()
+            .await
+            .unwrap()
+            .status()
+    }
+
+    #[tokio::test]
+    async fn emits_posted_orders_once() {
+        let address = free_address();
+        let collector = UniswapXWebhookCollector::new(address, 1, Default::default());
+        let mut stream = collector.get_event_stream().await.unwrap();
+
+        assert!(post(address, webhook_body(&order_hash(1)))
+            .await
+            .is_success());
+        assert!(post(address, webhook_body(&order_hash(1)))
+            .await
+            .is_success());
+        assert!(post(address, webhook_body(&order_hash(2)))
+            .await
+            .is_success());
+
+        let order = stream.next().await.unwrap();
+        assert_eq!(order.order_hash, order_hash(1));
+        assert_eq!(order.order_status, "open");
+        assert_eq!(order.order_type.as_deref(), Some("Dutch_V2"));
+        let order = stream.next().await.unwrap();
+        assert_eq!(order.order_hash, order_hash(2));
+    }
+
+    #[tokio::test]
+    async fn skips_orders_seen_by_the_polling_collector() {
+        let address = free_address();
+        let seen = Arc::new(Mutex::new(SeenOrders::default()));
+        seen.lock().await.update(
+            &UniswapXOrder {
+                encoded_order: "0x00".to_string(),
+                signature: "0x01".to_string(),
+                order_status: "open".to_string(),
+                created_at: 1685895015,
+                chain_id: 1,
+                // cased differently than the webhook
+                order_hash: order_hash(0xab).to_uppercase().replace("0X", "0x"),
+                order_type: None,
+                input: None,
+                outputs: vec![],
+            },
+            Instant::now(),
+        );
+        let collector = UniswapXWebhookCollector::new(address, 1, seen);
+        let mut stream = collector.get_event_stream().await.unwrap();
+
+        assert!(post(address, webhook_body(&order_hash(0xab)))
+            .await
+            .is_success());
+        assert!(post(address, webhook_body(&order_hash(2)))
+            .await
+            .is_success());
+
+        let order = stream.next().await.unwrap();
+        assert_eq!(order.order_hash, order_hash(2));
+    }
+
+    #[tokio::test]
+    async fn rejects_invalid_payloads() {
+        let address = free_address();
+        let collector = UniswapXWebhookCollector::new(address, 1, Default::default());
+        let _stream = collector.get_event_stream().await.unwrap();
+
+        let status = post(address, "not json".to_string()).await;
+        assert_eq!(status, reqwest::StatusCode::BAD_REQUEST);
+
+        let status = post(address, webhook_body("0x1234")).await;
+        assert_eq!(status, reqwest::StatusCode::BAD_REQUEST);
+
+        let status = post(
+            address,
+            webhook_body(&order_hash(1)).replace(r#""chainId":1"#, r#""chainId":137"#),
+        )
+        .await;
+        assert_eq!(status, reqwest::StatusCode::BAD_REQUEST);
+
+        let status = reqwest::Client::new()
+            .get(format!("http://{}", address))
+            .send()
+            .await
+            .unwrap()
+            .status();
+        assert_eq!(status, reqwest::StatusCode::METHOD_NOT_ALLOWED);
+    }
+}
@@ -5,10 +5,25 @@ use std::{
 use tracing::info;
 
 use anyhow::{Context, Result};
-use artemis_core::executors::mempool_executor::SubmitTxToMempool;
+use artemis_core::executors::mempool_executor::GasBidInfo;
 use artemis_core::types::Executor;
 use async_trait::async_trait;
-use ethers::providers::Middleware;
+use ethers::{
+    providers::Middleware,
+    types::{transaction::eip2718::TypedTransaction, U256},
+};
+
+/// A fill transaction to send, with the base fee of the block it targets.
+#[derive(Debug, Clone)]
+pub struct SubmitFill {
+    pub tx: TypedTransaction,
+    pub gas_bid_info: Option<GasBidInfo>,
+    /// Base fee predicted for the next block, none on chains without EIP-1559.
+    pub next_base_fee: Option<U256>,
+    /// Priority fee the fill's priority orders were resolved at. The fill has to pay exactly
+    /// this, as the reactor scales their amounts by the priority fee of the transaction.
+    pub priority_fee: Option<U256>,
+}
 
 /// An executor that sends transactions to the mempool.
 pub struct ProtectExecutor<M, N> {
@@ -26,7 +41,7 @@ impl<M: Middleware, N: Middleware> ProtectExecutor<M, N> {
 }
 
 #[async_trait]
-impl<M, N> Executor<SubmitTxToMempool> for ProtectExecutor<M, N>
+impl<M, N> Executor<SubmitFill> for ProtectExecutor<M, N>
 where
     M: Middleware,
     M::Error: 'static,
@@ -34,7 +49,7 @@ where
     N::Error: 'static,
 {
     /// Send a transaction to the mempool.
-    async fn execute(&self, mut action: SubmitTxToMempool) -> Result<()> {
+    async fn execute(&self, mut action: SubmitFill) -> Result<()> {
         info!("Executing tx {:?}", action.tx);
         let gas_usage_result = self
             .client
@@ -44,22 +59,63 @@ where
         info!("Gas Usage {:?}", gas_usage_result);
         let gas_usage = gas_usage_result?;
 
-        let bid_gas_price;
-        if let Some(gas_bid_info) = action.gas_bid_info {
+        let bid_gas_price = action.gas_bid_info.map(|gas_bid_info| {
             // gas price at which we'd break even, meaning 100% of profit goes to validator
             let breakeven_gas_price = gas_bid_info.total_profit / gas_usage;
             // gas price corresponding to bid percentage
-            bid_gas_price = breakeven_gas_price
+            breakeven_gas_price
                 .mul(gas_bid_info.bid_percentage)
-                .div(100);
-        } else {
-            bid_gas_price = self
-                .client
-                .get_gas_price()
-                .await
-                .context("Error getting gas price: {}")?;
+                .div(100)
+        });
+
+        match action.next_base_fee {
+            Some(base_fee) => {
+                let (max_fee_per_gas, max_priority_fee_per_gas) =
+                    match (action.priority_fee, bid_gas_price) {
+                        (Some(priority_fee), _) => (base_fee + priority_fee, priority_fee),
+                        // the bid caps what we pay per gas, and whatever isn't burnt is the tip
+                        (None, Some(bid_gas_price)) => {
+                            if bid_gas_price < base_fee {
+                                info!(
+                                    "Bid gas price {} is below the base fee {}, skipping",
+                                    bid_gas_price, base_fee
+                                );
+                                return Ok(());
+                            }
+                            (bid_gas_price, bid_gas_price - base_fee)
+                        }
+                        (None, None) => {
+                            let (max_fee_per_gas, max_priority_fee_per_gas) = self
+                                .client
+                                .estimate_eip1559_fees(None)
+                                .await
+                                .context("Error estimating fees: {}")?;
+                            (
+                                max_fee_per_gas.max(base_fee + max_priority_fee_per_gas),
+                                max_priority_fee_per_gas,
+                            )
+                        }
+                    };
+                let tx = action
+                    .tx
+                    .as_eip1559_mut()
+                    .context("Fills on EIP-1559 chains must be EIP-1559 transactions")?;
+                tx.max_fee_per_gas = Some(max_fee_per_gas);
+                tx.max_priority_fee_per_gas = Some(max_priority_fee_per_gas);
+            }
+            None => {
+                // without a base fee, the whole gas price is the priority fee
+                let gas_price = match action.priority_fee.or(bid_gas_price) {
+                    Some(gas_price) => gas_price,
+                    None => self
+                        .client
+                        .get_gas_price()
+                        .await
+                        .context("Error getting gas price: {}")?,
+                };
+                action.tx.set_gas_price(gas_price);
+            }
         }
-        action.tx.set_gas_price(bid_gas_price);
         self.sender_client.send_transaction(action.tx, None).await?;
         Ok(())
     }
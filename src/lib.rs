@@ -1,3 +1,5 @@
 pub mod collectors;
 pub mod executors;
+pub mod providers;
+pub mod routers;
 pub mod strategies;
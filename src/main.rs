@@ -4,37 +4,59 @@ use clap::Parser;
 use artemis_core::engine::Engine;
 use artemis_core::types::{CollectorMap, ExecutorMap};
 use collectors::{
-    block_collector::BlockCollector,
-    uniswapx_order_collector::{UniswapXOrderCollector, CHAIN_ID},
-    uniswapx_route_collector::UniswapXRouteCollector,
+    block_collector::{BlockCollector, BlockEvent},
+    permit2_collector::Permit2Collector,
+    uniswapx_fill_collector::UniswapXFillCollector,
+    uniswapx_order_collector::{OrderFilter, SeenOrders, UniswapXOrderCollector},
+    uniswapx_route_collector::{Router, RoutingApi, UniswapXRouteCollector},
+    uniswapx_webhook_collector::UniswapXWebhookCollector,
 };
 use ethers::{
     prelude::MiddlewareBuilder,
-    providers::{Http, Provider, Ws},
+    providers::{Http, Provider},
     signers::{LocalWallet, Signer},
+    types::H160,
 };
 use executors::protect_executor::ProtectExecutor;
+use futures::lock::Mutex;
+use providers::resilient_ws_provider::{connect_first, ResilientWsProvider};
+use routers::{
+    uniswap_router::UniswapRouter, uniswap_v2_router::UniswapV2RouterConfig,
+    uniswap_v3_router::UniswapV3RouterConfig,
+};
+use serde::Deserialize;
+use std::net::SocketAddr;
+use std::path::PathBuf;
 use std::sync::Arc;
+use std::time::Duration;
 use strategies::{
-    types::{Action, Config, Event},
+    types::{Action, ChainConfig, Config, Event},
     uniswapx_strategy::UniswapXUniswapFill,
 };
 use tokio::sync::mpsc::channel;
-use tracing::{info, Level};
+use tracing::{error, info, Level};
 use tracing_subscriber::{filter, prelude::*};
+use uniswapx_rs::hash::PERMIT2_ADDRESS;
 
 pub mod collectors;
 pub mod executors;
+pub mod providers;
+pub mod routers;
 pub mod strategies;
 
-const MEV_BLOCKER: &str = "https://rpc.mevblocker.io/noreverts";
+/// Times the strategy and executor connection is reconnected to its url before failing.
+const WS_RECONNECTS: usize = 10;
+
+/// Block times without a new block after which the block subscription reconnects.
+const STALL_BLOCKS: u64 = 10;
+const MIN_STALL_SECS: u64 = 30;
 
 /// CLI Options.
 #[derive(Parser, Debug)]
 pub struct Args {
-    /// Ethereum node WS endpoint.
+    /// JSON file listing the chains to fill orders on, see chains.example.json.
     #[arg(long)]
-    pub wss: String,
+    pub chains: PathBuf,
 
     /// Private key for sending txs.
     #[arg(long)]
@@ -45,66 +67,134 @@ pub struct Args {
     pub bid_percentage: u64,
 }
 
-#[tokio::main]
-async fn main() -> Result<()> {
-    // Set up tracing and parse args.
-    let filter = filter::Targets::new()
-        .with_target("artemis_core", Level::INFO)
-        .with_target("uniswapx_artemis", Level::INFO);
-
-    tracing_subscriber::registry()
-        .with(tracing_subscriber::fmt::layer())
-        .with(filter)
-        .init();
+/// A chain to fill orders on, with the providers and order sources of its pipeline.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PipelineConfig {
+    #[serde(flatten)]
+    pub chain: ChainConfig,
+    /// Node WS endpoints, failed over between in order.
+    pub wss: Vec<String>,
+    /// HTTP endpoint fills are sent through, e.g. MEV Blocker on mainnet.
+    pub tx_rpc: String,
+    /// Restricts the orders collected.
+    #[serde(default)]
+    pub filter: OrderFilter,
+    /// Address to receive UniswapX webhook orders on, in addition to polling the API.
+    #[serde(default)]
+    pub webhook_address: Option<SocketAddr>,
+    /// Routes orders through these Uniswap V2 pairs locally instead of the routing API.
+    #[serde(default)]
+    pub uniswap_v2_router: Option<UniswapV2RouterConfig>,
+    /// Routes orders through these Uniswap V3 pools locally instead of the routing API.
+    #[serde(default)]
+    pub uniswap_v3_router: Option<UniswapV3RouterConfig>,
+}
 
-    let args = Args::parse();
+// sets up the collectors, strategy and executor filling orders on one chain
+async fn build_engine(
+    pipeline: PipelineConfig,
+    private_key: &str,
+    bid_percentage: u64,
+) -> Result<Engine<Event, Action>> {
+    let chain_id = pipeline.chain.chain_id;
 
-    // Set up ethers provider.
-    let ws = Ws::connect(args.wss).await?;
+    // Set up ethers providers. Collectors subscribe through the resilient provider, which
+    // reconnects and fills gaps on its own.
+    let stall_timeout = Duration::from_millis(pipeline.chain.block_time_ms * STALL_BLOCKS)
+        .max(Duration::from_secs(MIN_STALL_SECS));
+    let subscriptions =
+        Arc::new(ResilientWsProvider::new(pipeline.wss.clone())?.with_stall_timeout(stall_timeout));
+    let ws = connect_first(&pipeline.wss, WS_RECONNECTS).await?;
     let provider = Provider::new(ws);
+    let tx_provider = Provider::<Http>::try_from(pipeline.tx_rpc)?;
 
-    let mevblocker_provider =
-        Provider::<Http>::try_from(MEV_BLOCKER).expect("could not instantiate HTTP Provider");
-
-    let wallet: LocalWallet = args
-        .private_key
-        .parse::<LocalWallet>()
-        .unwrap()
-        .with_chain_id(CHAIN_ID);
+    let wallet: LocalWallet = private_key.parse::<LocalWallet>()?.with_chain_id(chain_id);
     let address = wallet.address();
 
     let provider = Arc::new(provider.nonce_manager(address).with_signer(wallet.clone()));
-    let mevblocker_provider = Arc::new(
-        mevblocker_provider
-            .nonce_manager(address)
-            .with_signer(wallet),
-    );
+    let tx_provider = Arc::new(tx_provider.nonce_manager(address).with_signer(wallet));
 
-    // Set up engine.
     let mut engine = Engine::default();
 
     // Set up block collector.
-    let block_collector = Box::new(BlockCollector::new(provider.clone()));
-    let block_collector = CollectorMap::new(block_collector, Event::NewBlock);
+    let block_collector = Box::new(BlockCollector::new(subscriptions.clone()));
+    let block_collector = CollectorMap::new(block_collector, |e| match e {
+        BlockEvent::NewBlock(block) => Event::NewBlock(block),
+        BlockEvent::Reorg(reorg) => Event::Reorg(reorg),
+    });
     engine.add_collector(Box::new(block_collector));
 
+    // Set up fill collector.
+    let reactors = pipeline
+        .chain
+        .reactors
+        .iter()
+        .map(|reactor| reactor.parse::<H160>())
+        .collect::<Result<Vec<_>, _>>()?;
+    let fill_collector = Box::new(UniswapXFillCollector::new(subscriptions.clone(), reactors));
+    let fill_collector = CollectorMap::new(fill_collector, Event::Fill);
+    engine.add_collector(Box::new(fill_collector));
+
+    // Set up permit2 collector.
+    let permit2_collector = Box::new(Permit2Collector::new(
+        subscriptions.clone(),
+        PERMIT2_ADDRESS.parse::<H160>()?,
+    ));
+    let permit2_collector = CollectorMap::new(permit2_collector, Event::Permit2);
+    engine.add_collector(Box::new(permit2_collector));
+
     let (batch_sender, batch_receiver) = channel(512);
     let (route_sender, route_receiver) = channel(512);
 
-    let uniswapx_collector = Box::new(UniswapXOrderCollector::new());
+    // polled and webhook orders share the seen orders, so each order is only emitted once
+    let seen_orders = Arc::new(Mutex::new(SeenOrders::default()));
+    let uniswapx_collector = Box::new(UniswapXOrderCollector {
+        filter: pipeline.filter,
+        seen: seen_orders.clone(),
+        ..UniswapXOrderCollector::new(chain_id)
+    });
     let uniswapx_collector =
         CollectorMap::new(uniswapx_collector, |e| Event::UniswapXOrder(Box::new(e)));
     engine.add_collector(Box::new(uniswapx_collector));
 
-    let uniswapx_route_collector =
-        Box::new(UniswapXRouteCollector::new(batch_receiver, route_sender));
+    if let Some(webhook_address) = pipeline.webhook_address {
+        let webhook_collector = Box::new(UniswapXWebhookCollector::new(
+            webhook_address,
+            chain_id,
+            seen_orders.clone(),
+        ));
+        let webhook_collector =
+            CollectorMap::new(webhook_collector, |e| Event::UniswapXOrder(Box::new(e)));
+        engine.add_collector(Box::new(webhook_collector));
+    }
+
+    let v2 = pipeline.uniswap_v2_router.as_ref();
+    let v3 = pipeline.uniswap_v3_router.as_ref();
+    let router: Arc<dyn Router> = if v2.is_some() || v3.is_some() {
+        let router = Arc::new(
+            UniswapRouter::new(subscriptions.clone(), pipeline.chain.clone(), v2, v3).await?,
+        );
+        let tracker = router.clone();
+        tokio::spawn(async move { tracker.track_pools().await });
+        router
+    } else {
+        Arc::new(RoutingApi)
+    };
+    let uniswapx_route_collector = Box::new(UniswapXRouteCollector::new(
+        pipeline.chain.clone(),
+        router,
+        batch_receiver,
+        route_sender,
+    ));
     let uniswapx_route_collector = CollectorMap::new(uniswapx_route_collector, |e| {
         Event::UniswapXRoute(Box::new(e))
     });
     engine.add_collector(Box::new(uniswapx_route_collector));
 
     let config = Config {
-        bid_percentage: args.bid_percentage,
+        bid_percentage,
+        chain: pipeline.chain,
     };
 
     let strategy = UniswapXUniswapFill::new(
@@ -115,18 +205,53 @@ async fn main() -> Result<()> {
     );
     engine.add_strategy(Box::new(strategy));
 
-    let executor = Box::new(ProtectExecutor::new(
-        provider.clone(),
-        mevblocker_provider.clone(),
-    ));
+    let executor = Box::new(ProtectExecutor::new(provider.clone(), tx_provider.clone()));
 
     let executor = ExecutorMap::new(executor, |action| match action {
         Action::SubmitTx(tx) => Some(tx),
     });
 
     engine.add_executor(Box::new(executor));
-    // Start engine.
-    if let Ok(mut set) = engine.run().await {
+    Ok(engine)
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    // Set up tracing and parse args.
+    let filter = filter::Targets::new()
+        .with_target("artemis_core", Level::INFO)
+        .with_target("uniswapx_artemis", Level::INFO);
+
+    tracing_subscriber::registry()
+        .with(tracing_subscriber::fmt::layer())
+        .with(filter)
+        .init();
+
+    let args = Args::parse();
+    let pipelines: Vec<PipelineConfig> =
+        serde_json::from_str(&std::fs::read_to_string(&args.chains)?)?;
+
+    // Start an independent engine per chain.
+    let mut sets = Vec::new();
+    for pipeline in pipelines {
+        let chain_id = pipeline.chain.chain_id;
+        let engine = match build_engine(pipeline, &args.private_key, args.bid_percentage).await {
+            Ok(engine) => engine,
+            Err(e) => {
+                error!("Failed to build pipeline for chain {}: {}", chain_id, e);
+                continue;
+            }
+        };
+        match engine.run().await {
+            Ok(set) => {
+                info!("Started pipeline for chain {}", chain_id);
+                sets.push(set);
+            }
+            Err(e) => error!("Failed to start pipeline for chain {}: {}", chain_id, e),
+        }
+    }
+
+    for mut set in sets {
         while let Some(res) = set.join_next().await {
             info!("res: {:?}", res);
         }
@@ -0,0 +1,689 @@
+use anyhow::{anyhow, Result};
+use ethers::{
+    prelude::Middleware,
+    providers::{Provider, Ws},
+    types::{Block, Filter, Log, H256, U64},
+};
+use futures::{lock::Mutex, Stream};
+use std::sync::Arc;
+use tokio::time::{sleep, timeout, Duration};
+use tokio_stream::StreamExt;
+use tracing::{error, info};
+
+static CONNECT_TIMEOUT_SECS: u64 = 10;
+
+/// Most blocks polled to fill a gap after reconnecting, older missed blocks are skipped.
+const MAX_GAP_BLOCKS: u64 = 128;
+
+/// Largest block range backfilled with one `get_logs` request, as nodes limit the range.
+const LOG_RANGE_BLOCKS: u64 = 1000;
+
+/// How long to wait between reconnect attempts, doubling after each failed attempt.
+#[derive(Debug, Clone, Copy)]
+pub struct Backoff {
+    pub initial: Duration,
+    pub max: Duration,
+}
+
+impl Backoff {
+    pub fn delay(&self, attempt: u32) -> Duration {
+        self.initial
+            .saturating_mul(2u32.saturating_pow(attempt))
+            .min(self.max)
+    }
+}
+
+impl Default for Backoff {
+    fn default() -> Self {
+        Self {
+            initial: Duration::from_millis(500),
+            max: Duration::from_secs(30),
+        }
+    }
+}
+
+/// Connects to the first of `urls` that accepts a websocket connection. ethers reconnects the
+/// connection to the same url up to `reconnects` times if it drops.
+pub async fn connect_first(urls: &[String], reconnects: usize) -> Result<Ws> {
+    for url in urls {
+        match connect(url, reconnects).await {
+            Ok(ws) => return Ok(ws),
+            Err(e) => error!("failed to connect to {}: {}", url, e),
+        }
+    }
+    Err(anyhow!("failed to connect to any of {} urls", urls.len()))
+}
+
+async fn connect(url: &str, reconnects: usize) -> Result<Ws> {
+    timeout(
+        Duration::from_secs(CONNECT_TIMEOUT_SECS),
+        Ws::connect_with_reconnects(url, reconnects),
+    )
+    .await
+    .map_err(|_| anyhow!("timed out"))?
+    .map_err(|e| anyhow!(e))
+}
+
+#[derive(Debug, Default)]
+struct Connection {
+    // index of the url to connect to next
+    url: usize,
+    provider: Option<Arc<Provider<Ws>>>,
+}
+
+/// A websocket provider for subscriptions that outlive connections. Subscriptions resubscribe
+/// after their connection drops, reconnecting with backoff and failing over to the next url, and
+/// fill the gap left by polling for the blocks and logs they missed.
+#[derive(Debug)]
+pub struct ResilientWsProvider {
+    urls: Vec<String>,
+    backoff: Backoff,
+    /// Longest wait for a new block before the connection is considered stalled, and for a new
+    /// log before the connection is checked.
+    stall_timeout: Duration,
+    connection: Mutex<Connection>,
+}
+
+impl ResilientWsProvider {
+    pub fn new(urls: Vec<String>) -> Result<Self> {
+        if urls.is_empty() {
+            return Err(anyhow!("no websocket urls"));
+        }
+        Ok(Self {
+            urls,
+            backoff: Backoff::default(),
+            stall_timeout: Duration::from_secs(60),
+            connection: Mutex::new(Connection::default()),
+        })
+    }
+
+    pub fn with_stall_timeout(self, stall_timeout: Duration) -> Self {
+        Self {
+            stall_timeout,
+            ..self
+        }
+    }
+
+    /// Returns the live connection, connecting to the urls in turn until one accepts.
+    pub async fn provider(&self) -> Arc<Provider<Ws>> {
+        let mut connection = self.connection.lock().await;
+        if let Some(provider) = &connection.provider {
+            return provider.clone();
+        }
+
+        let mut attempt = 0;
+        loop {
+            let url = &self.urls[connection.url];
+            // reconnecting is left to us, so subscriptions see the connection drop
+            match connect(url, 0).await {
+                Ok(ws) => {
+                    info!("Connected to {}", url);
+                    let provider = Arc::new(Provider::new(ws));
+                    connection.provider = Some(provider.clone());
+                    return provider;
+                }
+                Err(e) => {
+                    error!("failed to connect to {}: {}", url, e);
+                    connection.url = (connection.url + 1) % self.urls.len();
+                    sleep(self.backoff.delay(attempt)).await;
+                    attempt += 1;
+                }
+            }
+        }
+    }
+
+    // drops a failed connection, so the next one is made to the next url
+    async fn disconnect(&self, provider: &Arc<Provider<Ws>>) {
+        let mut connection = self.connection.lock().await;
+        if let Some(current) = &connection.provider {
+            if Arc::ptr_eq(current, provider) {
+                connection.provider = None;
+                connection.url = (connection.url + 1) % self.urls.len();
+            }
+        }
+    }
+
+    // whether a subscription on `provider` is quiet rather than stalled, which logs can be for
+    // much longer than blocks: the connection must still be the live one and answer requests
+    async fn is_healthy(&self, provider: &Arc<Provider<Ws>>) -> bool {
+        let is_current = match &self.connection.lock().await.provider {
+            Some(current) => Arc::ptr_eq(current, provider),
+            None => false,
+        };
+        is_current
+            && matches!(
+                timeout(self.stall_timeout, provider.get_block_number()).await,
+                Ok(Ok(_))
+            )
+    }
+
+    /// Subscribes to new blocks. After a reconnect, the blocks missed since the last one are
+    /// polled with `eth_getBlockByNumber` before new blocks are emitted.
+    pub fn subscribe_blocks(&self) -> impl Stream<Item = Block<H256>> + Send + '_ {
+        async_stream::stream! {
+            // the highest block emitted so far
+            let mut last_block: Option<U64> = None;
+            loop {
+                let provider = self.provider().await;
+                let mut blocks = match provider.subscribe_blocks().await {
+                    Ok(blocks) => blocks,
+                    Err(e) => {
+                        error!("failed to subscribe to blocks: {}", e);
+                        self.disconnect(&provider).await;
+                        continue;
+                    }
+                };
+
+                // the subscription buffers new blocks while the gap is filled
+                let mut filled_to = None;
+                if let Some(from_block) = last_block {
+                    match missed_blocks(provider.as_ref(), from_block).await {
+                        Ok(missed) => {
+                            if let Some(to_block) = missed.last().and_then(|block| block.number) {
+                                info!("Polled blocks {} to {}", from_block + 1, to_block);
+                                filled_to = Some(to_block);
+                                last_block = Some(to_block);
+                            }
+                            for block in missed {
+                                yield block;
+                            }
+                        }
+                        Err(e) => {
+                            error!("failed to poll missed blocks: {}", e);
+                            self.disconnect(&provider).await;
+                            continue;
+                        }
+                    }
+                }
+
+                loop {
+                    match timeout(self.stall_timeout, blocks.next()).await {
+                        Ok(Some(block)) => {
+                            if block.number.is_some() && block.number <= filled_to {
+                                continue;
+                            }
+                            if block.number > last_block {
+                                last_block = block.number;
+                            }
+                            yield block;
+                        }
+                        Ok(None) => {
+                            info!("Block subscription ended, reconnecting");
+                            break;
+                        }
+                        Err(_) => {
+                            error!("No new block in {:?}, reconnecting", self.stall_timeout);
+                            break;
+                        }
+                    }
+                }
+                self.disconnect(&provider).await;
+            }
+        }
+    }
+
+    /// Subscribes to logs matching `filter`. After a reconnect, the blocks missed since the last
+    /// log, or since subscribing if there was none, are backfilled with `get_logs` before new
+    /// logs are emitted, so no log is skipped.
+    /// Logs removed by reorgs are passed through. When no log arrives within the stall timeout,
+    /// it resubscribes if the connection was replaced or stopped responding.
+    pub fn subscribe_logs(&self, filter: Filter) -> impl Stream<Item = Log> + Send + '_ {
+        async_stream::stream! {
+            // the last block we have emitted all logs up to
+            let mut last_block: Option<U64> = None;
+            loop {
+                let provider = self.provider().await;
+                // the head before the first subscription, so a reconnect before the first log
+                // still backfills
+                let backfill_from = last_block;
+                if last_block.is_none() {
+                    match provider.get_block_number().await {
+                        Ok(head) => last_block = Some(head),
+                        Err(e) => {
+                            error!("failed to get the block number: {}", e);
+                            self.disconnect(&provider).await;
+                            continue;
+                        }
+                    }
+                }
+                let mut logs = match provider.subscribe_logs(&filter).await {
+                    Ok(logs) => logs,
+                    Err(e) => {
+                        error!("failed to subscribe to logs: {}", e);
+                        self.disconnect(&provider).await;
+                        continue;
+                    }
+                };
+
+                // the subscription buffers new logs while the gap is backfilled
+                let mut backfilled_to = None;
+                if let Some(from_block) = backfill_from {
+                    let backfill = match provider.get_block_number().await {
+                        Ok(head) if head > from_block => {
+                            missed_logs(provider.as_ref(), &filter, from_block, head)
+                                .await
+                                .map(|logs| Some((head, logs)))
+                        }
+                        Ok(_) => Ok(None),
+                        Err(e) => Err(e.into()),
+                    };
+                    match backfill {
+                        Ok(Some((head, backfilled_logs))) => {
+                            info!("Backfilled logs from block {} to {}", from_block + 1, head);
+                            for log in backfilled_logs {
+                                yield log;
+                            }
+                            last_block = Some(head);
+                            backfilled_to = Some(head);
+                        }
+                        Ok(None) => {}
+                        Err(e) => {
+                            // reconnect rather than leave a gap
+                            error!("failed to backfill logs: {}", e);
+                            self.disconnect(&provider).await;
+                            continue;
+                        }
+                    }
+                }
+
+                loop {
+                    match timeout(self.stall_timeout, logs.next()).await {
+                        Ok(Some(log)) => {
+                            let block_number = log.block_number;
+                            if block_number.is_some() && block_number <= backfilled_to {
+                                continue;
+                            }
+                            yield log;
+                            if block_number > last_block {
+                                last_block = block_number;
+                            }
+                        }
+                        Ok(None) => {
+                            info!("Log subscription ended, reconnecting");
+                            break;
+                        }
+                        Err(_) => {
+                            if !self.is_healthy(&provider).await {
+                                error!(
+                                    "No new log in {:?} and the connection stalled, resubscribing",
+                                    self.stall_timeout
+                                );
+                                break;
+                            }
+                        }
+                    }
+                }
+                self.disconnect(&provider).await;
+            }
+        }
+    }
+}
+
+// polls the blocks after `from_block` up to the head, at most the last MAX_GAP_BLOCKS of them
+async fn missed_blocks(provider: &Provider<Ws>, from_block: U64) -> Result<Vec<Block<H256>>> {
+    let head = provider.get_block_number().await?.as_u64();
+    let from_block = (from_block.as_u64() + 1).max(head.saturating_sub(MAX_GAP_BLOCKS - 1));
+    let mut blocks = Vec::new();
+    for number in from_block..=head {
+        match provider.get_block(number).await? {
+            Some(block) => blocks.push(block),
+            None => break,
+        }
+    }
+    Ok(blocks)
+}
+
+// gets the logs of the blocks after `from_block` up to `to_block`, LOG_RANGE_BLOCKS at a time
+async fn missed_logs(
+    provider: &Provider<Ws>,
+    filter: &Filter,
+    from_block: U64,
+    to_block: U64,
+) -> Result<Vec<Log>> {
+    let mut logs = Vec::new();
+    let mut start = from_block + 1;
+    while start <= to_block {
+        let end = (start + LOG_RANGE_BLOCKS - 1).min(to_block);
+        let range = filter.clone().from_block(start).to_block(end);
+        logs.extend(provider.get_logs(&range).await?);
+        start = end + 1;
+    }
+    Ok(logs)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Backoff, ResilientWsProvider, LOG_RANGE_BLOCKS};
+    use ethers::types::{Block, Filter, Log, H256, U256, U64};
+    use futures::{SinkExt, StreamExt};
+    use serde_json::{json, Value};
+    use std::net::SocketAddr;
+    use tokio::net::TcpListener;
+    use tokio::time::{sleep, timeout, Duration};
+    use tokio_tungstenite::tungstenite::Message;
+
+    /// What the mock node does on one connection.
+    #[derive(Clone, Default)]
+    struct Session {
+        // the block number returned by eth_blockNumber
+        head: u64,
+        // blocks announced to newHeads subscribers
+        heads: Vec<u64>,
+        // blocks whose logs are announced to logs subscribers
+        log_blocks: Vec<u64>,
+        // whether to drop the connection after announcing
+        close: bool,
+        // whether to stop answering after announcing, leaving the connection open
+        stall: bool,
+    }
+
+    fn block(number: u64) -> Block<H256> {
+        Block {
+            hash: Some(H256::from_low_u64_be(number)),
+            parent_hash: H256::from_low_u64_be(number - 1),
+            number: Some(U64::from(number)),
+            timestamp: U256::from(number * 12),
+            ..Default::default()
+        }
+    }
+
+    fn log(block_number: u64) -> Log {
+        Log {
+            block_number: Some(U64::from(block_number)),
+            block_hash: Some(H256::from_low_u64_be(block_number)),
+            ..Default::default()
+        }
+    }
+
+    fn quantity(value: &Value) -> u64 {
+        u64::from_str_radix(value.as_str().unwrap().trim_start_matches("0x"), 16).unwrap()
+    }
+
+    fn result(request: &Value, session: &Session) -> Value {
+        let params = &request["params"];
+        match request["method"].as_str().unwrap() {
+            "eth_subscribe" => json!("0x1"),
+            "eth_unsubscribe" => json!(true),
+            "eth_blockNumber" => json!(format!("0x{:x}", session.head)),
+            "eth_getBlockByNumber" => {
+                let number = quantity(&params[0]);
+                if number <= session.head {
+                    serde_json::to_value(block(number)).unwrap()
+                } else {
+                    Value::Null
+                }
+            }
+            "eth_getLogs" => {
+                let (from, to) = (
+                    quantity(&params[0]["fromBlock"]),
+                    quantity(&params[0]["toBlock"]),
+                );
+                // nodes reject larger ranges
+                assert!(to - from < LOG_RANGE_BLOCKS, "log range too large");
+                serde_json::to_value((from..=to).map(log).collect::<Vec<_>>()).unwrap()
+            }
+            method => panic!("unexpected method {}", method),
+        }
+    }
+
+    /// Serves a mock JSON-RPC node, following one session per connection. The last session is
+    /// repeated for any further connections.
+    async fn mock_node(sessions: Vec<Session>) -> SocketAddr {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            let mut sessions = sessions.into_iter();
+            let mut session = Session::default();
+            loop {
+                let (stream, _) = listener.accept().await.unwrap();
+                session = sessions.next().unwrap_or(session);
+                let session = session.clone();
+                tokio::spawn(async move {
+                    let mut ws = tokio_tungstenite::accept_async(stream).await.unwrap();
+                    while let Some(Ok(Message::Text(text))) = ws.next().await {
+                        let request: Value = serde_json::from_str(&text).unwrap();
+                        let response = json!({
+                            "jsonrpc": "2.0",
+                            "id": request["id"],
+                            "result": result(&request, &session),
+                        });
+                        ws.send(Message::Text(response.to_string())).await.unwrap();
+                        if request["method"] != "eth_subscribe" {
+                            continue;
+                        }
+
+                        sleep(Duration::from_millis(50)).await;
+                        let notifications: Vec<Value> = if request["params"][0] == "newHeads" {
+                            session
+                                .heads
+                                .iter()
+                                .map(|number| serde_json::to_value(block(*number)).unwrap())
+                                .collect()
+                        } else {
+                            session
+                                .log_blocks
+                                .iter()
+                                .map(|number| serde_json::to_value(log(*number)).unwrap())
+                                .collect()
+                        };
+                        for notification in notifications {
+                            let notification = json!({
+                                "jsonrpc": "2.0",
+                                "method": "eth_subscription",
+                                "params": {"subscription": "0x1", "result": notification},
+                            });
+                            ws.send(Message::Text(notification.to_string()))
+                                .await
+                                .unwrap();
+                        }
+                        if session.close {
+                            ws.close(None).await.ok();
+                            return;
+                        }
+                        if session.stall {
+                            futures::future::pending::<()>().await;
+                        }
+                    }
+                });
+            }
+        });
+        address
+    }
+
+    // a port nothing listens on
+    async fn closed_address() -> SocketAddr {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        listener.local_addr().unwrap()
+    }
+
+    fn resilient_provider(addresses: &[SocketAddr]) -> ResilientWsProvider {
+        let urls = addresses
+            .iter()
+            .map(|address| format!("ws://{}", address))
+            .collect();
+        ResilientWsProvider {
+            backoff: Backoff {
+                initial: Duration::from_millis(10),
+                max: Duration::from_millis(100),
+            },
+            ..ResilientWsProvider::new(urls).unwrap()
+        }
+    }
+
+    #[test]
+    fn backoff_doubles_up_to_max() {
+        let backoff = Backoff {
+            initial: Duration::from_millis(100),
+            max: Duration::from_secs(1),
+        };
+        assert_eq!(backoff.delay(0), Duration::from_millis(100));
+        assert_eq!(backoff.delay(1), Duration::from_millis(200));
+        assert_eq!(backoff.delay(3), Duration::from_millis(800));
+        assert_eq!(backoff.delay(4), Duration::from_secs(1));
+        assert_eq!(backoff.delay(100), Duration::from_secs(1));
+    }
+
+    #[tokio::test]
+    async fn reconnects_and_polls_missed_blocks() {
+        let address = mock_node(vec![
+            Session {
+                head: 2,
+                heads: vec![1, 2],
+                close: true,
+                ..Default::default()
+            },
+            // blocks 3 and 4 were produced while disconnected
+            Session {
+                head: 4,
+                heads: vec![4, 5],
+                ..Default::default()
+            },
+        ])
+        .await;
+        let provider = resilient_provider(&[address]);
+        let blocks = provider.subscribe_blocks();
+        futures::pin_mut!(blocks);
+
+        let mut numbers = Vec::new();
+        while numbers.len() < 5 {
+            let block = timeout(Duration::from_secs(5), blocks.next())
+                .await
+                .unwrap();
+            numbers.push(block.unwrap().number.unwrap().as_u64());
+        }
+        assert_eq!(numbers, vec![1, 2, 3, 4, 5]);
+    }
+
+    #[tokio::test]
+    async fn fails_over_to_the_next_url() {
+        let address = mock_node(vec![Session {
+            head: 1,
+            heads: vec![1],
+            ..Default::default()
+        }])
+        .await;
+        let provider = resilient_provider(&[closed_address().await, address]);
+        let blocks = provider.subscribe_blocks();
+        futures::pin_mut!(blocks);
+
+        let block = timeout(Duration::from_secs(5), blocks.next())
+            .await
+            .unwrap();
+        assert_eq!(block.unwrap().number, Some(U64::from(1)));
+    }
+
+    #[tokio::test]
+    async fn reconnects_and_backfills_missed_logs() {
+        let address = mock_node(vec![
+            Session {
+                head: 1,
+                log_blocks: vec![1],
+                close: true,
+                ..Default::default()
+            },
+            // logs in blocks 2 and 3 were emitted while disconnected
+            Session {
+                head: 3,
+                log_blocks: vec![3, 4],
+                ..Default::default()
+            },
+        ])
+        .await;
+        let provider = resilient_provider(&[address]);
+        let logs = provider.subscribe_logs(Filter::new());
+        futures::pin_mut!(logs);
+
+        let mut numbers = Vec::new();
+        while numbers.len() < 4 {
+            let log = timeout(Duration::from_secs(5), logs.next()).await.unwrap();
+            numbers.push(log.unwrap().block_number.unwrap().as_u64());
+        }
+        assert_eq!(numbers, vec![1, 2, 3, 4]);
+    }
+
+    #[tokio::test]
+    async fn backfills_logs_missed_before_the_first_log() {
+        let address = mock_node(vec![
+            Session {
+                head: 1,
+                close: true,
+                ..Default::default()
+            },
+            // logs in blocks 2 and 3 were emitted while disconnected
+            Session {
+                head: 3,
+                log_blocks: vec![4],
+                ..Default::default()
+            },
+        ])
+        .await;
+        let provider = resilient_provider(&[address]);
+        let logs = provider.subscribe_logs(Filter::new());
+        futures::pin_mut!(logs);
+
+        let mut numbers = Vec::new();
+        while numbers.len() < 3 {
+            let log = timeout(Duration::from_secs(5), logs.next()).await.unwrap();
+            numbers.push(log.unwrap().block_number.unwrap().as_u64());
+        }
+        assert_eq!(numbers, vec![2, 3, 4]);
+    }
+
+    #[tokio::test]
+    async fn backfills_long_gaps_in_ranges() {
+        let address = mock_node(vec![
+            Session {
+                head: 1,
+                log_blocks: vec![1],
+                close: true,
+                ..Default::default()
+            },
+            Session {
+                head: 2500,
+                log_blocks: vec![2501],
+                ..Default::default()
+            },
+        ])
+        .await;
+        let provider = resilient_provider(&[address]);
+        let logs = provider.subscribe_logs(Filter::new());
+        futures::pin_mut!(logs);
+
+        let mut numbers = Vec::new();
+        while numbers.len() < 2501 {
+            let log = timeout(Duration::from_secs(5), logs.next()).await.unwrap();
+            numbers.push(log.unwrap().block_number.unwrap().as_u64());
+        }
+        assert_eq!(numbers, (1..=2501).collect::<Vec<_>>());
+    }
+
+    #[tokio::test]
+    async fn resubscribes_to_logs_on_a_stalled_connection() {
+        let address = mock_node(vec![
+            Session {
+                head: 1,
+                log_blocks: vec![1],
+                stall: true,
+                ..Default::default()
+            },
+            // the log in block 2 was emitted while the connection was stalled
+            Session {
+                head: 2,
+                log_blocks: vec![2, 3],
+                ..Default::default()
+            },
+        ])
+        .await;
+        let provider =
+            resilient_provider(&[address]).with_stall_timeout(Duration::from_millis(200));
+        let logs = provider.subscribe_logs(Filter::new());
+        futures::pin_mut!(logs);
+
+        let mut numbers = Vec::new();
+        while numbers.len() < 3 {
+            let log = timeout(Duration::from_secs(5), logs.next()).await.unwrap();
+            numbers.push(log.unwrap().block_number.unwrap().as_u64());
+        }
+        assert_eq!(numbers, vec![1, 2, 3]);
+    }
+}
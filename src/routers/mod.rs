@@ -0,0 +1,5 @@
+pub mod uniswap_router;
+pub mod uniswap_v2_router;
+pub mod uniswap_v3_math;
+pub mod uniswap_v3_pool;
+pub mod uniswap_v3_router;
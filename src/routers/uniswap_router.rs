@@ -0,0 +1,632 @@
+use super::uniswap_v2_router::{load_pair, sync_topic, UniswapV2RouterConfig};
+use super::uniswap_v3_pool::{burn_topic, mint_topic, swap_topic};
+use super::uniswap_v3_router::{encode_path, load_pool, UniswapV3RouterConfig};
+use crate::collectors::uniswapx_route_collector::{
+    MethodParameters, OrderRoute, Route, RouteOrderParams, Router, TokenInRoute, V2Route, V3Route,
+    DEADLINE,
+};
+use crate::providers::resilient_ws_provider::ResilientWsProvider;
+use crate::strategies::types::ChainConfig;
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use bindings_uniswapx::{
+    i_swap_router_02::{
+        ExactInputCall, ExactInputParams, MulticallCall, SwapExactTokensForTokensCall,
+    },
+    ierc20::IERC20,
+};
+use ethers::{
+    abi::AbiEncode,
+    prelude::Middleware,
+    types::{Bytes, Filter, Log, H160, U256, U64},
+};
+use futures::lock::Mutex;
+use std::collections::HashMap;
+use std::fmt::Debug;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+use tokio_stream::StreamExt;
+use tracing::{error, info};
+
+/// Longest path searched for routes, in pools.
+const MAX_HOPS: usize = 3;
+/// Gas used by a swap through SwapRouter02 besides the gas of its hops.
+const SWAP_GAS: u64 = 40_000;
+/// Swaps revert if they return less than the quote minus this, like routing API swaps.
+const SLIPPAGE_BPS: u64 = 50;
+
+/// SwapRouter02 `Constants.ADDRESS_THIS`: swaps to it leave their output in the router for the
+/// next swap of the multicall.
+fn address_this() -> H160 {
+    H160::from_low_u64_be(2)
+}
+
+/// The protocol of a pool, which decides how swaps through it are encoded and described.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Protocol {
+    V2,
+    /// V3 pools are identified in paths by their fee tier.
+    V3 {
+        fee: u32,
+    },
+}
+
+/// An exact input swap through a pool.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PoolSwap {
+    pub token_out: H160,
+    pub amount_out: U256,
+    /// The gas the swap adds to a route.
+    pub gas: u64,
+}
+
+/// A pool routes can swap through, kept up to date by its logs.
+pub trait Pool: Debug + Send + Sync {
+    fn address(&self) -> H160;
+
+    fn tokens(&self) -> [H160; 2];
+
+    fn protocol(&self) -> Protocol;
+
+    /// Swaps `amount_in` of `token_in` for the other token of the pool. None if the pool doesn't
+    /// have the token, or can't swap all of the input.
+    fn quote_exact_input(&self, token_in: H160, amount_in: U256) -> Option<PoolSwap>;
+
+    /// Applies a log of the pool, unless the state is already as of a later log. Returns whether
+    /// the state changed.
+    fn apply_log(&mut self, log: &Log) -> bool;
+}
+
+/// A path through pools, and the output of an exact input swap along it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Path {
+    pub pools: Vec<H160>,
+    pub protocols: Vec<Protocol>,
+    /// The tokens swapped through, from the input to the output token.
+    pub tokens: Vec<H160>,
+    pub amount_out: U256,
+    /// The gas of the hops of the path.
+    pub gas: u64,
+}
+
+/// The pools routes are found through, of any protocol.
+#[derive(Debug, Default)]
+pub struct Pools {
+    pools: HashMap<H160, Box<dyn Pool>>,
+}
+
+impl Pools {
+    pub fn insert(&mut self, pool: Box<dyn Pool>) {
+        self.pools.insert(pool.address(), pool);
+    }
+
+    pub fn get(&self, address: &H160) -> Option<&dyn Pool> {
+        self.pools.get(address).map(|pool| pool.as_ref())
+    }
+
+    pub fn addresses(&self) -> Vec<H160> {
+        self.pools.keys().copied().collect()
+    }
+
+    pub fn len(&self) -> usize {
+        self.pools.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pools.is_empty()
+    }
+
+    /// Applies a log to its pool. Returns whether the pool changed.
+    pub fn apply_log(&mut self, log: &Log) -> bool {
+        match self.pools.get_mut(&log.address) {
+            Some(pool) => pool.apply_log(log),
+            None => false,
+        }
+    }
+
+    /// The path of at most `MAX_HOPS` pools that swaps `amount_in` of `token_in` for the most
+    /// `token_out`.
+    pub fn best_path(&self, token_in: H160, token_out: H160, amount_in: U256) -> Option<Path> {
+        let mut path = Path {
+            pools: vec![],
+            protocols: vec![],
+            tokens: vec![token_in],
+            amount_out: amount_in,
+            gas: 0,
+        };
+        let mut best = None;
+        self.search(&mut path, token_out, &mut best);
+        best
+    }
+
+    // depth first search over paths that don't revisit a token
+    fn search(&self, path: &mut Path, token_out: H160, best: &mut Option<Path>) {
+        if path.pools.len() == MAX_HOPS {
+            return;
+        }
+        let token_in = path.tokens[path.tokens.len() - 1];
+        let amount_in = path.amount_out;
+        let gas = path.gas;
+        for pool in self.pools.values() {
+            let swap = match pool.quote_exact_input(token_in, amount_in) {
+                Some(swap) if !swap.amount_out.is_zero() => swap,
+                _ => continue,
+            };
+            if path.tokens.contains(&swap.token_out) {
+                continue;
+            }
+
+            path.pools.push(pool.address());
+            path.protocols.push(pool.protocol());
+            path.tokens.push(swap.token_out);
+            path.amount_out = swap.amount_out;
+            path.gas = gas + swap.gas;
+            if swap.token_out == token_out {
+                if best
+                    .as_ref()
+                    .map_or(true, |best| swap.amount_out > best.amount_out)
+                {
+                    *best = Some(path.clone());
+                }
+            } else {
+                self.search(path, token_out, best);
+            }
+            path.pools.pop();
+            path.protocols.pop();
+            path.tokens.pop();
+            path.amount_out = amount_in;
+            path.gas = gas;
+        }
+    }
+}
+
+/// SwapRouter02 `multicall(deadline, [...])` calldata for an exact input swap along `path`, in
+/// the same form as routing API swaps: a `swapExactTokensForTokens` or `exactInput` call per run
+/// of V2 or V3 pools, each after the first swapping the output the previous one left in the
+/// router.
+pub fn swap_calldata(
+    amount_in: U256,
+    amount_out_min: U256,
+    path: &Path,
+    recipient: H160,
+    deadline: u64,
+) -> Bytes {
+    let hops = path.pools.len();
+    let mut calls = vec![];
+    let mut start = 0;
+    while start < hops {
+        let v2 = path.protocols[start] == Protocol::V2;
+        let end = (start..hops)
+            .find(|hop| (path.protocols[*hop] == Protocol::V2) != v2)
+            .unwrap_or(hops);
+        // `Constants.CONTRACT_BALANCE` swaps the router's balance of the input token
+        let amount_in = if start == 0 { amount_in } else { U256::zero() };
+        let (amount_out_min, recipient) = if end == hops {
+            (amount_out_min, recipient)
+        } else {
+            (U256::zero(), address_this())
+        };
+        let tokens = &path.tokens[start..=end];
+
+        let call = if v2 {
+            SwapExactTokensForTokensCall {
+                amount_in,
+                amount_out_min,
+                path: tokens.to_vec(),
+                to: recipient,
+            }
+            .encode()
+        } else {
+            let fees: Vec<u32> = path.protocols[start..end]
+                .iter()
+                .filter_map(|protocol| match protocol {
+                    Protocol::V3 { fee } => Some(*fee),
+                    Protocol::V2 => None,
+                })
+                .collect();
+            ExactInputCall {
+                params: ExactInputParams {
+                    path: encode_path(tokens, &fees),
+                    recipient,
+                    amount_in,
+                    amount_out_minimum: amount_out_min,
+                },
+            }
+            .encode()
+        };
+        calls.push(call.into());
+        start = end;
+    }
+
+    let multicall = MulticallCall {
+        deadline: U256::from(deadline),
+        data: calls,
+    };
+    multicall.encode().into()
+}
+
+/// Describes a token in routes. Symbol and decimals are optional in ERC20, so are left empty if
+/// the token doesn't have them.
+pub async fn describe_token<M: Middleware>(
+    client: Arc<M>,
+    token: H160,
+    chain_id: u64,
+) -> TokenInRoute {
+    let erc20 = IERC20::new(token, client);
+    let symbol = erc20.symbol().call().await.unwrap_or_default();
+    let decimals = erc20
+        .decimals()
+        .call()
+        .await
+        .map(|decimals| decimals.to_string())
+        .unwrap_or_default();
+    TokenInRoute {
+        address: format!("{:?}", token),
+        chain_id,
+        symbol,
+        decimals,
+    }
+}
+
+/// A [Router](Router) that finds exact input routes locally through Uniswap V2 pairs and V3
+/// pools, from their state tracked with their logs, instead of asking the routing API. Routes can
+/// mix pairs and pools.
+pub struct UniswapRouter {
+    provider: Arc<ResilientWsProvider>,
+    chain: ChainConfig,
+    swap_router: H160,
+    pools: Mutex<Pools>,
+    // the block the pools were loaded at, which their logs are tracked from
+    loaded_at: U64,
+    // describes tokens in routes
+    tokens: HashMap<H160, TokenInRoute>,
+}
+
+impl UniswapRouter {
+    /// Loads the tokens and current state of the configured pairs and pools. Both have to swap
+    /// through the same SwapRouter02.
+    pub async fn new(
+        provider: Arc<ResilientWsProvider>,
+        chain: ChainConfig,
+        v2: Option<&UniswapV2RouterConfig>,
+        v3: Option<&UniswapV3RouterConfig>,
+    ) -> Result<Self> {
+        let swap_router = match (v2, v3) {
+            (Some(v2), Some(v3)) => {
+                let swap_router = H160::from_str(&v2.swap_router)?;
+                if swap_router != H160::from_str(&v3.swap_router)? {
+                    return Err(anyhow!(
+                        "chain {} configures different v2 and v3 swap routers",
+                        chain.chain_id
+                    ));
+                }
+                swap_router
+            }
+            (Some(v2), None) => H160::from_str(&v2.swap_router)?,
+            (None, Some(v3)) => H160::from_str(&v3.swap_router)?,
+            (None, None) => return Err(anyhow!("no uniswap pairs or pools to route through")),
+        };
+
+        let client = provider.provider().await;
+        let block = client.get_block_number().await?;
+
+        let mut pools = Pools::default();
+        for address in v2.map_or(&[][..], |v2| v2.pairs.as_slice()) {
+            let pair = load_pair(client.as_ref(), H160::from_str(address)?, block).await?;
+            pools.insert(Box::new(pair));
+        }
+        for address in v3.map_or(&[][..], |v3| v3.pools.as_slice()) {
+            let pool = load_pool(client.as_ref(), H160::from_str(address)?, block).await?;
+            pools.insert(Box::new(pool));
+        }
+
+        let mut tokens = HashMap::new();
+        for pool in pools.pools.values() {
+            for token in pool.tokens() {
+                if !tokens.contains_key(&token) {
+                    let description = describe_token(client.clone(), token, chain.chain_id).await;
+                    tokens.insert(token, description);
+                }
+            }
+        }
+        info!(
+            "Loaded {} uniswap pairs and pools at block {}",
+            pools.len(),
+            block
+        );
+
+        Ok(Self {
+            provider,
+            chain,
+            swap_router,
+            pools: Mutex::new(pools),
+            loaded_at: block,
+            tokens,
+        })
+    }
+
+    /// Applies V2 `Sync` logs and V3 `Swap`, `Mint` and `Burn` logs of the pools as they are
+    /// emitted. Runs until the subscription ends, which only happens when the provider is
+    /// dropped.
+    pub async fn track_pools(&self) {
+        let addresses = self.pools.lock().await.addresses();
+        let filter = Filter::new().address(addresses).topic0(vec![
+            sync_topic(),
+            swap_topic(),
+            mint_topic(),
+            burn_topic(),
+        ]);
+        let logs = self.provider.subscribe_logs(filter.clone());
+        futures::pin_mut!(logs);
+        let mut backfilled = false;
+        while let Some(log) = logs.next().await {
+            // v3 mints and burns are applied as deltas, so none emitted between loading the
+            // pools and subscribing can be missed
+            if !backfilled {
+                if let Some(block_number) = log.block_number {
+                    if let Err(e) = self.backfill(&filter, block_number).await {
+                        error!("failed to backfill pool logs: {}", e);
+                    }
+                    backfilled = true;
+                }
+            }
+            if log.removed == Some(true) {
+                // a reorged out log can't be undone from the log alone
+                if let Err(e) = self.reload_pool(log.address).await {
+                    error!("failed to reload pool {:?}: {}", log.address, e);
+                }
+                continue;
+            }
+            self.pools.lock().await.apply_log(&log);
+        }
+    }
+
+    async fn backfill(&self, filter: &Filter, to_block: U64) -> Result<()> {
+        if to_block <= self.loaded_at {
+            return Ok(());
+        }
+        let range = filter
+            .clone()
+            .from_block(self.loaded_at + 1)
+            .to_block(to_block);
+        let logs = self.provider.provider().await.get_logs(&range).await?;
+        let mut pools = self.pools.lock().await;
+        for log in logs.iter() {
+            pools.apply_log(log);
+        }
+        Ok(())
+    }
+
+    async fn reload_pool(&self, address: H160) -> Result<()> {
+        let protocol = match self.pools.lock().await.get(&address) {
+            Some(pool) => pool.protocol(),
+            None => return Ok(()),
+        };
+        let client = self.provider.provider().await;
+        let block = client.get_block_number().await?;
+        let pool: Box<dyn Pool> = match protocol {
+            Protocol::V2 => Box::new(load_pair(client.as_ref(), address, block).await?),
+            Protocol::V3 { .. } => Box::new(load_pool(client.as_ref(), address, block).await?),
+        };
+        self.pools.lock().await.insert(pool);
+        Ok(())
+    }
+
+    fn describe(&self, path: &Path) -> Vec<Route> {
+        path.pools
+            .iter()
+            .zip(path.protocols.iter())
+            .zip(path.tokens.windows(2))
+            .map(|((pool, protocol), tokens)| {
+                let address = format!("{:?}", pool);
+                let token_in = self.tokens[&tokens[0]].clone();
+                let token_out = self.tokens[&tokens[1]].clone();
+                match protocol {
+                    Protocol::V2 => Route::V2(V2Route {
+                        address,
+                        token_in,
+                        token_out,
+                    }),
+                    Protocol::V3 { fee } => Route::V3(V3Route {
+                        address,
+                        token_in,
+                        token_out,
+                        fee: fee.to_string(),
+                    }),
+                }
+            })
+            .collect()
+    }
+}
+
+#[async_trait]
+impl Router for UniswapRouter {
+    async fn route(&self, params: RouteOrderParams) -> Result<OrderRoute> {
+        let token_in = H160::from_str(&params.token_in)?;
+        let token_out = H160::from_str(&params.token_out)?;
+        let amount_in = U256::from_dec_str(&params.amount)?;
+        let recipient = H160::from_str(&params.recipient)?;
+        let wrapped_native_token = H160::from_str(&self.chain.wrapped_native_token)?;
+        let gas_price = self.provider.provider().await.get_gas_price().await?;
+
+        let (path, gas_use_estimate, gas_use_estimate_quote) = {
+            let pools = self.pools.lock().await;
+            let path = pools
+                .best_path(token_in, token_out, amount_in)
+                .ok_or_else(|| anyhow!("no route from {:?} to {:?}", token_in, token_out))?;
+            let gas_use_estimate = U256::from(SWAP_GAS + path.gas);
+            // the gas cost in the output token is what the strategy prices the output token in
+            // eth with
+            let gas_use_eth = gas_use_estimate * gas_price;
+            let gas_use_estimate_quote = if token_out == wrapped_native_token {
+                gas_use_eth
+            } else {
+                pools
+                    .best_path(wrapped_native_token, token_out, gas_use_eth)
+                    .ok_or_else(|| anyhow!("no route to price gas in {:?}", token_out))?
+                    .amount_out
+            };
+            (path, gas_use_estimate, gas_use_estimate_quote)
+        };
+
+        let amount_out_min = path.amount_out * (10_000 - SLIPPAGE_BPS) / 10_000;
+        let deadline = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs() + DEADLINE;
+        let calldata = swap_calldata(amount_in, amount_out_min, &path, recipient, deadline);
+
+        Ok(OrderRoute {
+            quote: path.amount_out.to_string(),
+            gas_price_wei: gas_price.to_string(),
+            gas_use_estimate_quote: gas_use_estimate_quote.to_string(),
+            gas_use_estimate: gas_use_estimate.to_string(),
+            route: vec![self.describe(&path)],
+            method_parameters: MethodParameters {
+                calldata: calldata.to_string(),
+                value: "0x00".to_string(),
+                to: format!("{:?}", self.swap_router),
+            },
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::routers::{uniswap_v2_router::tests::pair, uniswap_v3_router::tests::pool};
+    use ethers::abi::AbiDecode;
+
+    const E18: u128 = 1_000_000_000_000_000_000;
+
+    fn token(byte: u8) -> H160 {
+        H160::repeat_byte(byte)
+    }
+
+    #[test]
+    fn finds_best_path_across_protocols() {
+        let mut pools = Pools::default();
+        // a shallow v2 pair straight to B, or a deep v3 pool to C and a deep v2 pair on to B
+        pools.insert(Box::new(pair(0xab, 0xa, 0xb, 10, 10)));
+        pools.insert(Box::new(pool(0xac, 0xa, 0xc, 500, 1000 * E18)));
+        pools.insert(Box::new(pair(0xcb, 0xb, 0xc, 1000, 1000)));
+
+        let amount_in = U256::from(E18);
+        let path = pools.best_path(token(0xa), token(0xb), amount_in).unwrap();
+        assert_eq!(
+            path.pools,
+            vec![H160::repeat_byte(0xac), H160::repeat_byte(0xcb)]
+        );
+        assert_eq!(
+            path.protocols,
+            vec![Protocol::V3 { fee: 500 }, Protocol::V2]
+        );
+        assert_eq!(path.tokens, vec![token(0xa), token(0xc), token(0xb)]);
+
+        let v3 = pools
+            .get(&H160::repeat_byte(0xac))
+            .unwrap()
+            .quote_exact_input(token(0xa), amount_in)
+            .unwrap();
+        let v2 = pools
+            .get(&H160::repeat_byte(0xcb))
+            .unwrap()
+            .quote_exact_input(token(0xc), v3.amount_out)
+            .unwrap();
+        assert_eq!(path.amount_out, v2.amount_out);
+        assert_eq!(path.gas, v3.gas + v2.gas);
+
+        assert!(pools.best_path(token(0xa), token(0xd), amount_in).is_none());
+    }
+
+    #[test]
+    fn limits_path_length() {
+        let mut pools = Pools::default();
+        pools.insert(Box::new(pair(0x12, 0x1, 0x2, 1000, 1000)));
+        pools.insert(Box::new(pool(0x23, 0x2, 0x3, 500, 1000 * E18)));
+        pools.insert(Box::new(pair(0x34, 0x3, 0x4, 1000, 1000)));
+        pools.insert(Box::new(pool(0x45, 0x4, 0x5, 500, 1000 * E18)));
+
+        let amount_in = U256::from(E18);
+        assert!(pools.best_path(token(0x1), token(0x4), amount_in).is_some());
+        assert!(pools.best_path(token(0x1), token(0x5), amount_in).is_none());
+    }
+
+    fn path(protocols: Vec<Protocol>, tokens: Vec<u8>) -> Path {
+        Path {
+            pools: vec![H160::zero(); protocols.len()],
+            protocols,
+            tokens: tokens.into_iter().map(token).collect(),
+            amount_out: U256::zero(),
+            gas: 0,
+        }
+    }
+
+    #[test]
+    fn encodes_single_protocol_swaps() {
+        let v2 = path(vec![Protocol::V2, Protocol::V2], vec![0xa, 0xc, 0xb]);
+        let calldata = swap_calldata(
+            U256::from(1000),
+            U256::from(990),
+            &v2,
+            token(0xe),
+            1_700_000_000,
+        );
+        let multicall = MulticallCall::decode(&calldata).unwrap();
+        assert_eq!(multicall.deadline, U256::from(1_700_000_000u64));
+        assert_eq!(multicall.data.len(), 1);
+        let swap = SwapExactTokensForTokensCall::decode(&multicall.data[0]).unwrap();
+        assert_eq!(swap.amount_in, U256::from(1000));
+        assert_eq!(swap.amount_out_min, U256::from(990));
+        assert_eq!(swap.path, v2.tokens);
+        assert_eq!(swap.to, token(0xe));
+
+        let v3 = path(
+            vec![Protocol::V3 { fee: 500 }, Protocol::V3 { fee: 3000 }],
+            vec![0xa, 0xc, 0xb],
+        );
+        let calldata = swap_calldata(
+            U256::from(1000),
+            U256::from(990),
+            &v3,
+            token(0xe),
+            1_700_000_000,
+        );
+        let multicall = MulticallCall::decode(&calldata).unwrap();
+        assert_eq!(multicall.data.len(), 1);
+        let swap = ExactInputCall::decode(&multicall.data[0]).unwrap();
+        assert_eq!(swap.params.path, encode_path(&v3.tokens, &[500, 3000]));
+        assert_eq!(swap.params.recipient, token(0xe));
+        assert_eq!(swap.params.amount_in, U256::from(1000));
+        assert_eq!(swap.params.amount_out_minimum, U256::from(990));
+    }
+
+    #[test]
+    fn encodes_mixed_swaps() {
+        let mixed = path(
+            vec![Protocol::V3 { fee: 500 }, Protocol::V2, Protocol::V2],
+            vec![0xa, 0xc, 0xd, 0xb],
+        );
+        let calldata = swap_calldata(
+            U256::from(1000),
+            U256::from(990),
+            &mixed,
+            token(0xe),
+            1_700_000_000,
+        );
+        let multicall = MulticallCall::decode(&calldata).unwrap();
+        assert_eq!(multicall.data.len(), 2);
+
+        // the v3 hop leaves its output in the router
+        let v3 = ExactInputCall::decode(&multicall.data[0]).unwrap();
+        assert_eq!(v3.params.path, encode_path(&mixed.tokens[..2], &[500]));
+        assert_eq!(v3.params.recipient, address_this());
+        assert_eq!(v3.params.amount_in, U256::from(1000));
+        assert_eq!(v3.params.amount_out_minimum, U256::zero());
+
+        // and the v2 hops swap all of it, checking the output of the whole route
+        let v2 = SwapExactTokensForTokensCall::decode(&multicall.data[1]).unwrap();
+        assert_eq!(v2.amount_in, U256::zero());
+        assert_eq!(v2.amount_out_min, U256::from(990));
+        assert_eq!(v2.path, mixed.tokens[1..].to_vec());
+        assert_eq!(v2.to, token(0xe));
+    }
+}
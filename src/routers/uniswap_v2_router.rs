@@ -0,0 +1,308 @@
+use super::uniswap_router::{Pool, PoolSwap, Protocol};
+use anyhow::{anyhow, Result};
+use ethers::{
+    abi::{self, ParamType, Token},
+    prelude::Middleware,
+    types::{
+        transaction::eip2718::TypedTransaction, BlockId, Bytes, Log, TransactionRequest, H160,
+        H256, U256, U64,
+    },
+    utils::keccak256,
+};
+use serde::Deserialize;
+use tracing::error;
+
+/// `bytes4(keccak256("getReserves()"))`
+const GET_RESERVES_SELECTOR: [u8; 4] = [0x09, 0x02, 0xf1, 0xac];
+/// `bytes4(keccak256("token0()"))`
+const TOKEN0_SELECTOR: [u8; 4] = [0x0d, 0xfe, 0x16, 0x81];
+/// `bytes4(keccak256("token1()"))`
+const TOKEN1_SELECTOR: [u8; 4] = [0xd2, 0x12, 0x20, 0xa7];
+
+/// Gas used by each swap through a pair.
+const HOP_GAS: u64 = 60_000;
+
+pub fn sync_topic() -> H256 {
+    H256::from(keccak256("Sync(uint112,uint112)"))
+}
+
+/// The Uniswap V2 pairs to route through.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UniswapV2RouterConfig {
+    /// SwapRouter02 on the chain, which swaps are sent through.
+    pub swap_router: String,
+    pub pairs: Vec<String>,
+}
+
+/// A Uniswap V2 pair and its reserves.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct V2Pair {
+    pub address: H160,
+    pub token0: H160,
+    pub token1: H160,
+    pub reserve0: U256,
+    pub reserve1: U256,
+    // block number and log index the reserves are as of
+    synced_at: (U64, U256),
+}
+
+impl Pool for V2Pair {
+    fn address(&self) -> H160 {
+        self.address
+    }
+
+    fn tokens(&self) -> [H160; 2] {
+        [self.token0, self.token1]
+    }
+
+    fn protocol(&self) -> Protocol {
+        Protocol::V2
+    }
+
+    fn quote_exact_input(&self, token_in: H160, amount_in: U256) -> Option<PoolSwap> {
+        let (token_out, amount_out) = if token_in == self.token0 {
+            (
+                self.token1,
+                get_amount_out(amount_in, self.reserve0, self.reserve1)?,
+            )
+        } else if token_in == self.token1 {
+            (
+                self.token0,
+                get_amount_out(amount_in, self.reserve1, self.reserve0)?,
+            )
+        } else {
+            return None;
+        };
+        Some(PoolSwap {
+            token_out,
+            amount_out,
+            gas: HOP_GAS,
+        })
+    }
+
+    /// Applies the reserves of the pair's `Sync` log.
+    fn apply_log(&mut self, log: &Log) -> bool {
+        if log.address != self.address || log.topics.first() != Some(&sync_topic()) {
+            return false;
+        }
+        let synced_at = match (log.block_number, log.log_index) {
+            (Some(block_number), Some(log_index)) => (block_number, log_index),
+            _ => return false,
+        };
+        if synced_at <= self.synced_at {
+            return false;
+        }
+        let reserves = abi::decode(&[ParamType::Uint(112), ParamType::Uint(112)], &log.data);
+        match reserves.as_deref() {
+            Ok([Token::Uint(reserve0), Token::Uint(reserve1)]) => {
+                self.reserve0 = *reserve0;
+                self.reserve1 = *reserve1;
+                self.synced_at = synced_at;
+                true
+            }
+            _ => {
+                error!("invalid sync log for pair {:?}", self.address);
+                false
+            }
+        }
+    }
+}
+
+/// `UniswapV2Library.getAmountOut`: the output of swapping `amount_in` through a pair with the
+/// given reserves, after the 0.3% fee.
+pub fn get_amount_out(amount_in: U256, reserve_in: U256, reserve_out: U256) -> Option<U256> {
+    if amount_in.is_zero() || reserve_in.is_zero() || reserve_out.is_zero() {
+        return None;
+    }
+    let amount_in_with_fee = amount_in.checked_mul(U256::from(997))?;
+    let numerator = amount_in_with_fee.checked_mul(reserve_out)?;
+    let denominator = reserve_in
+        .checked_mul(U256::from(1000))?
+        .checked_add(amount_in_with_fee)?;
+    Some(numerator / denominator)
+}
+
+async fn call_pair<M>(
+    client: &M,
+    pair: H160,
+    selector: [u8; 4],
+    outputs: &[ParamType],
+    block: Option<BlockId>,
+) -> Result<Vec<Token>>
+where
+    M: Middleware,
+    M::Error: 'static,
+{
+    let tx: TypedTransaction = TransactionRequest::new()
+        .to(pair)
+        .data(Bytes::from(selector.to_vec()))
+        .into();
+    let result = client.call(&tx, block).await?;
+    Ok(abi::decode(outputs, &result)?)
+}
+
+// the reserves of a pair as of the end of `block`
+async fn get_reserves<M>(client: &M, pair: H160, block: U64) -> Result<(U256, U256)>
+where
+    M: Middleware,
+    M::Error: 'static,
+{
+    let outputs = [
+        ParamType::Uint(112),
+        ParamType::Uint(112),
+        ParamType::Uint(32),
+    ];
+    let block = Some(BlockId::from(block));
+    match call_pair(client, pair, GET_RESERVES_SELECTOR, &outputs, block)
+        .await?
+        .as_slice()
+    {
+        [Token::Uint(reserve0), Token::Uint(reserve1), _] => Ok((*reserve0, *reserve1)),
+        _ => Err(anyhow!("invalid reserves for pair {:?}", pair)),
+    }
+}
+
+async fn get_token<M>(client: &M, pair: H160, selector: [u8; 4], block: U64) -> Result<H160>
+where
+    M: Middleware,
+    M::Error: 'static,
+{
+    let block = Some(BlockId::from(block));
+    match call_pair(client, pair, selector, &[ParamType::Address], block)
+        .await?
+        .as_slice()
+    {
+        [Token::Address(token)] => Ok(*token),
+        _ => Err(anyhow!("invalid token for pair {:?}", pair)),
+    }
+}
+
+/// Loads the tokens and reserves of a pair as of the end of `block`.
+pub async fn load_pair<M>(client: &M, address: H160, block: U64) -> Result<V2Pair>
+where
+    M: Middleware,
+    M::Error: 'static,
+{
+    let token0 = get_token(client, address, TOKEN0_SELECTOR, block).await?;
+    let token1 = get_token(client, address, TOKEN1_SELECTOR, block).await?;
+    let (reserve0, reserve1) = get_reserves(client, address, block).await?;
+    Ok(V2Pair {
+        address,
+        token0,
+        token1,
+        reserve0,
+        reserve1,
+        // the reserves include every sync in the block
+        synced_at: (block, U256::MAX),
+    })
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::routers::uniswap_router::Pools;
+
+    const E18: u64 = 1_000_000_000_000_000_000;
+
+    fn token(byte: u8) -> H160 {
+        H160::repeat_byte(byte)
+    }
+
+    pub(crate) fn pair(
+        address: u8,
+        token0: u8,
+        token1: u8,
+        reserve0: u64,
+        reserve1: u64,
+    ) -> V2Pair {
+        V2Pair {
+            address: H160::repeat_byte(address),
+            token0: token(token0),
+            token1: token(token1),
+            reserve0: U256::from(reserve0) * E18,
+            reserve1: U256::from(reserve1) * E18,
+            synced_at: (U64::from(10), U256::MAX),
+        }
+    }
+
+    fn sync_log(pair: u8, block_number: u64, log_index: u64, reserves: (u64, u64)) -> Log {
+        Log {
+            address: H160::repeat_byte(pair),
+            topics: vec![sync_topic()],
+            data: abi::encode(&[
+                Token::Uint(U256::from(reserves.0)),
+                Token::Uint(U256::from(reserves.1)),
+            ])
+            .into(),
+            block_number: Some(U64::from(block_number)),
+            log_index: Some(U256::from(log_index)),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn computes_amount_out() {
+        let amount_out = get_amount_out(
+            U256::from(E18),
+            U256::from(100) * E18,
+            U256::from(200_000_000_000u64),
+        );
+        assert_eq!(amount_out, Some(U256::from(1_974_316_068u64)));
+
+        assert_eq!(get_amount_out(U256::zero(), U256::one(), U256::one()), None);
+        assert_eq!(get_amount_out(U256::one(), U256::zero(), U256::one()), None);
+    }
+
+    #[test]
+    fn finds_best_multi_hop_path() {
+        let mut pairs = Pools::default();
+        // A/B directly, or A/C and C/B with deeper liquidity
+        pairs.insert(Box::new(pair(0xab, 0xa, 0xb, 1000, 1000)));
+        pairs.insert(Box::new(pair(0xac, 0xa, 0xc, 1000, 2000)));
+        pairs.insert(Box::new(pair(0xcb, 0xb, 0xc, 2000, 2000)));
+
+        let path = pairs
+            .best_path(token(0xa), token(0xb), U256::from(10) * E18)
+            .unwrap();
+        assert_eq!(
+            path.pools,
+            vec![H160::repeat_byte(0xac), H160::repeat_byte(0xcb)]
+        );
+        assert_eq!(path.tokens, vec![token(0xa), token(0xc), token(0xb)]);
+        assert_eq!(
+            path.amount_out,
+            U256::from_dec_str("19492090719486852022").unwrap()
+        );
+        assert_eq!(path.gas, 2 * HOP_GAS);
+
+        // the other direction of the same pairs
+        let path = pairs
+            .best_path(token(0xb), token(0xa), U256::from(10) * E18)
+            .unwrap();
+        assert_eq!(path.tokens, vec![token(0xb), token(0xa)]);
+    }
+
+    #[test]
+    fn applies_newer_sync_logs() {
+        let mut synced = pair(0xab, 0xa, 0xb, 1000, 1000);
+
+        assert!(synced.apply_log(&sync_log(0xab, 11, 3, (5, 6))));
+        assert_eq!(
+            (synced.reserve0, synced.reserve1),
+            (U256::from(5), U256::from(6))
+        );
+
+        // already included in the reserves
+        assert!(!synced.apply_log(&sync_log(0xab, 11, 2, (7, 8))));
+        assert!(!synced.apply_log(&sync_log(0xab, 10, 9, (7, 8))));
+        // another pair's log
+        assert!(!synced.apply_log(&sync_log(0xcd, 12, 0, (7, 8))));
+
+        assert!(synced.apply_log(&sync_log(0xab, 12, 0, (7, 8))));
+        assert_eq!(
+            (synced.reserve0, synced.reserve1),
+            (U256::from(7), U256::from(8))
+        );
+    }
+}
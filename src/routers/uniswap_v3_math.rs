@@ -0,0 +1,513 @@
+//! Ports of the Uniswap V3 core math libraries (`TickMath`, `FullMath`, `SqrtPriceMath` and
+//! `SwapMath`), rounding exactly like the contracts so simulated swaps match on-chain swaps to
+//! the wei. Functions return `None` where the contracts revert.
+
+use ethers::types::{U256, U512};
+
+pub const MIN_TICK: i32 = -887272;
+pub const MAX_TICK: i32 = 887272;
+/// `getSqrtRatioAtTick(MIN_TICK)`
+pub const MIN_SQRT_RATIO: U256 = U256([4295128739, 0, 0, 0]);
+/// `getSqrtRatioAtTick(MAX_TICK)`
+pub const MAX_SQRT_RATIO: U256 = U256([0x5d951d5263988d26, 0xefd1fc6a50648849, 0xfffd8963, 0]);
+
+/// Swap fees are in hundredths of a bip.
+const FEE_DENOMINATOR: u32 = 1_000_000;
+
+fn q96() -> U256 {
+    U256::one() << 96
+}
+
+fn max_u160() -> U256 {
+    (U256::one() << 160) - 1
+}
+
+/// `TickMath.getSqrtRatioAtTick`: `sqrt(1.0001^tick) * 2^96`.
+pub fn get_sqrt_ratio_at_tick(tick: i32) -> Option<U256> {
+    let abs_tick = tick.unsigned_abs();
+    if abs_tick > MAX_TICK as u32 {
+        return None;
+    }
+
+    // sqrt(1.0001^-2^i) * 2^128 for each bit i of the tick
+    const RATIOS: [u128; 19] = [
+        0xfff97272373d413259a46990580e213a,
+        0xfff2e50f5f656932ef12357cf3c7fdcc,
+        0xffe5caca7e10e4e61c3624eaa0941cd0,
+        0xffcb9843d60f6159c9db58835c926644,
+        0xff973b41fa98c081472e6896dfb254c0,
+        0xff2ea16466c96a3843ec78b326b52861,
+        0xfe5dee046a99a2a811c461f1969c3053,
+        0xfcbe86c7900a88aedcffc83b479aa3a4,
+        0xf987a7253ac413176f2b074cf7815e54,
+        0xf3392b0822b70005940c7a398e4b70f3,
+        0xe7159475a2c29b7443b29c7fa6e889d9,
+        0xd097f3bdfd2022b8845ad8f792aa5825,
+        0xa9f746462d870fdf8a65dc1f90e061e5,
+        0x70d869a156d2a1b890bb3df62baf32f7,
+        0x31be135f97d08fd981231505542fcfa6,
+        0x9aa508b5b7a84e1c677de54f3e99bc9,
+        0x5d6af8dedb81196699c329225ee604,
+        0x2216e584f5fa1ea926041bedfe98,
+        0x48a170391f7dc42444e8fa2,
+    ];
+    let mut ratio = if abs_tick & 1 != 0 {
+        U256::from(0xfffcb933bd6fad37aa2d162d1a594001u128)
+    } else {
+        U256::one() << 128
+    };
+    for (i, bit_ratio) in RATIOS.iter().enumerate() {
+        if abs_tick & (2 << i) != 0 {
+            ratio = (ratio * U256::from(*bit_ratio)) >> 128;
+        }
+    }
+    if tick > 0 {
+        ratio = U256::MAX / ratio;
+    }
+
+    // round up from Q128.128 to Q64.96, so getTickAtSqrtRatio of the result is the tick
+    let rounding = if (ratio & U256::from(u32::MAX)).is_zero() {
+        0
+    } else {
+        1
+    };
+    Some((ratio >> 32) + rounding)
+}
+
+/// `TickMath.getTickAtSqrtRatio`: the greatest tick whose sqrt ratio is at most `sqrt_price_x96`.
+pub fn get_tick_at_sqrt_ratio(sqrt_price_x96: U256) -> Option<i32> {
+    if sqrt_price_x96 < MIN_SQRT_RATIO || sqrt_price_x96 >= MAX_SQRT_RATIO {
+        return None;
+    }
+    // the sqrt ratio increases with the tick, so binary search for it
+    let (mut low, mut high) = (MIN_TICK, MAX_TICK);
+    while low < high {
+        let mid = low + (high - low + 1) / 2;
+        if get_sqrt_ratio_at_tick(mid)? <= sqrt_price_x96 {
+            low = mid;
+        } else {
+            high = mid - 1;
+        }
+    }
+    Some(low)
+}
+
+/// `FullMath.mulDiv`: `a * b / denominator` with a 512 bit intermediate product.
+pub fn mul_div(a: U256, b: U256, denominator: U256) -> Option<U256> {
+    if denominator.is_zero() {
+        return None;
+    }
+    U256::try_from(a.full_mul(b) / U512::from(denominator)).ok()
+}
+
+/// `FullMath.mulDivRoundingUp`
+pub fn mul_div_rounding_up(a: U256, b: U256, denominator: U256) -> Option<U256> {
+    if denominator.is_zero() {
+        return None;
+    }
+    let product = a.full_mul(b);
+    let denominator = U512::from(denominator);
+    let mut result = product / denominator;
+    if !(product % denominator).is_zero() {
+        result += U512::one();
+    }
+    U256::try_from(result).ok()
+}
+
+// `UnsafeMath.divRoundingUp`
+fn div_rounding_up(x: U256, y: U256) -> U256 {
+    let rounding = if (x % y).is_zero() { 0 } else { 1 };
+    x / y + rounding
+}
+
+/// `SqrtPriceMath.getNextSqrtPriceFromAmount0RoundingUp`
+fn get_next_sqrt_price_from_amount0_rounding_up(
+    sqrt_price_x96: U256,
+    liquidity: u128,
+    amount: U256,
+    add: bool,
+) -> Option<U256> {
+    if amount.is_zero() {
+        return Some(sqrt_price_x96);
+    }
+    let numerator1 = U256::from(liquidity) << 96;
+
+    if add {
+        if let Some(product) = amount.checked_mul(sqrt_price_x96) {
+            if let Some(denominator) = numerator1.checked_add(product) {
+                return mul_div_rounding_up(numerator1, sqrt_price_x96, denominator);
+            }
+        }
+        // the price can't go below zero, so only the less precise form is needed on overflow
+        let denominator = (numerator1 / sqrt_price_x96).checked_add(amount)?;
+        Some(div_rounding_up(numerator1, denominator))
+    } else {
+        let product = amount.checked_mul(sqrt_price_x96)?;
+        if numerator1 <= product {
+            return None;
+        }
+        let next = mul_div_rounding_up(numerator1, sqrt_price_x96, numerator1 - product)?;
+        (next <= max_u160()).then_some(next)
+    }
+}
+
+/// `SqrtPriceMath.getNextSqrtPriceFromAmount1RoundingDown`
+fn get_next_sqrt_price_from_amount1_rounding_down(
+    sqrt_price_x96: U256,
+    liquidity: u128,
+    amount: U256,
+    add: bool,
+) -> Option<U256> {
+    let liquidity = U256::from(liquidity);
+    if add {
+        let quotient = if amount <= max_u160() {
+            (amount << 96) / liquidity
+        } else {
+            mul_div(amount, q96(), liquidity)?
+        };
+        let next = sqrt_price_x96.checked_add(quotient)?;
+        (next <= max_u160()).then_some(next)
+    } else {
+        let quotient = if amount <= max_u160() {
+            div_rounding_up(amount << 96, liquidity)
+        } else {
+            mul_div_rounding_up(amount, q96(), liquidity)?
+        };
+        (sqrt_price_x96 > quotient).then(|| sqrt_price_x96 - quotient)
+    }
+}
+
+/// `SqrtPriceMath.getNextSqrtPriceFromInput`: the price after swapping `amount_in` of token0 if
+/// `zero_for_one`, or of token1 otherwise, rounded so the price moves at least as far.
+pub fn get_next_sqrt_price_from_input(
+    sqrt_price_x96: U256,
+    liquidity: u128,
+    amount_in: U256,
+    zero_for_one: bool,
+) -> Option<U256> {
+    if sqrt_price_x96.is_zero() || liquidity == 0 {
+        return None;
+    }
+    if zero_for_one {
+        get_next_sqrt_price_from_amount0_rounding_up(sqrt_price_x96, liquidity, amount_in, true)
+    } else {
+        get_next_sqrt_price_from_amount1_rounding_down(sqrt_price_x96, liquidity, amount_in, true)
+    }
+}
+
+/// `SqrtPriceMath.getNextSqrtPriceFromOutput`: the price after swapping for `amount_out` of
+/// token1 if `zero_for_one`, or of token0 otherwise.
+pub fn get_next_sqrt_price_from_output(
+    sqrt_price_x96: U256,
+    liquidity: u128,
+    amount_out: U256,
+    zero_for_one: bool,
+) -> Option<U256> {
+    if sqrt_price_x96.is_zero() || liquidity == 0 {
+        return None;
+    }
+    if zero_for_one {
+        get_next_sqrt_price_from_amount1_rounding_down(sqrt_price_x96, liquidity, amount_out, false)
+    } else {
+        get_next_sqrt_price_from_amount0_rounding_up(sqrt_price_x96, liquidity, amount_out, false)
+    }
+}
+
+/// `SqrtPriceMath.getAmount0Delta`: the token0 between two prices for `liquidity`.
+pub fn get_amount0_delta(
+    sqrt_ratio_a_x96: U256,
+    sqrt_ratio_b_x96: U256,
+    liquidity: u128,
+    round_up: bool,
+) -> Option<U256> {
+    let (lower, upper) = if sqrt_ratio_a_x96 > sqrt_ratio_b_x96 {
+        (sqrt_ratio_b_x96, sqrt_ratio_a_x96)
+    } else {
+        (sqrt_ratio_a_x96, sqrt_ratio_b_x96)
+    };
+    if lower.is_zero() {
+        return None;
+    }
+    let numerator1 = U256::from(liquidity) << 96;
+    let numerator2 = upper - lower;
+    if round_up {
+        let amount = mul_div_rounding_up(numerator1, numerator2, upper)?;
+        Some(div_rounding_up(amount, lower))
+    } else {
+        Some(mul_div(numerator1, numerator2, upper)? / lower)
+    }
+}
+
+/// `SqrtPriceMath.getAmount1Delta`: the token1 between two prices for `liquidity`.
+pub fn get_amount1_delta(
+    sqrt_ratio_a_x96: U256,
+    sqrt_ratio_b_x96: U256,
+    liquidity: u128,
+    round_up: bool,
+) -> Option<U256> {
+    let (lower, upper) = if sqrt_ratio_a_x96 > sqrt_ratio_b_x96 {
+        (sqrt_ratio_b_x96, sqrt_ratio_a_x96)
+    } else {
+        (sqrt_ratio_a_x96, sqrt_ratio_b_x96)
+    };
+    if round_up {
+        mul_div_rounding_up(U256::from(liquidity), upper - lower, q96())
+    } else {
+        mul_div(U256::from(liquidity), upper - lower, q96())
+    }
+}
+
+/// The result of swapping within a single tick range.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SwapStep {
+    pub sqrt_price_next_x96: U256,
+    pub amount_in: U256,
+    pub amount_out: U256,
+    pub fee_amount: U256,
+}
+
+/// `SwapMath.computeSwapStep`: swaps `amount_remaining` of input if `exact_input`, or for
+/// `amount_remaining` of output otherwise, without moving the price past `sqrt_price_target_x96`.
+pub fn compute_swap_step(
+    sqrt_price_current_x96: U256,
+    sqrt_price_target_x96: U256,
+    liquidity: u128,
+    amount_remaining: U256,
+    exact_input: bool,
+    fee_pips: u32,
+) -> Option<SwapStep> {
+    let zero_for_one = sqrt_price_current_x96 >= sqrt_price_target_x96;
+    let mut amount_in = U256::zero();
+    let mut amount_out = U256::zero();
+
+    let sqrt_price_next_x96 = if exact_input {
+        let amount_remaining_less_fee = mul_div(
+            amount_remaining,
+            U256::from(FEE_DENOMINATOR - fee_pips),
+            U256::from(FEE_DENOMINATOR),
+        )?;
+        amount_in = if zero_for_one {
+            get_amount0_delta(
+                sqrt_price_target_x96,
+                sqrt_price_current_x96,
+                liquidity,
+                true,
+            )?
+        } else {
+            get_amount1_delta(
+                sqrt_price_current_x96,
+                sqrt_price_target_x96,
+                liquidity,
+                true,
+            )?
+        };
+        if amount_remaining_less_fee >= amount_in {
+            sqrt_price_target_x96
+        } else {
+            get_next_sqrt_price_from_input(
+                sqrt_price_current_x96,
+                liquidity,
+                amount_remaining_less_fee,
+                zero_for_one,
+            )?
+        }
+    } else {
+        amount_out = if zero_for_one {
+            get_amount1_delta(
+                sqrt_price_target_x96,
+                sqrt_price_current_x96,
+                liquidity,
+                false,
+            )?
+        } else {
+            get_amount0_delta(
+                sqrt_price_current_x96,
+                sqrt_price_target_x96,
+                liquidity,
+                false,
+            )?
+        };
+        if amount_remaining >= amount_out {
+            sqrt_price_target_x96
+        } else {
+            get_next_sqrt_price_from_output(
+                sqrt_price_current_x96,
+                liquidity,
+                amount_remaining,
+                zero_for_one,
+            )?
+        }
+    };
+
+    let max = sqrt_price_target_x96 == sqrt_price_next_x96;
+    if zero_for_one {
+        if !(max && exact_input) {
+            amount_in =
+                get_amount0_delta(sqrt_price_next_x96, sqrt_price_current_x96, liquidity, true)?;
+        }
+        if !(max && !exact_input) {
+            amount_out = get_amount1_delta(
+                sqrt_price_next_x96,
+                sqrt_price_current_x96,
+                liquidity,
+                false,
+            )?;
+        }
+    } else {
+        if !(max && exact_input) {
+            amount_in =
+                get_amount1_delta(sqrt_price_current_x96, sqrt_price_next_x96, liquidity, true)?;
+        }
+        if !(max && !exact_input) {
+            amount_out = get_amount0_delta(
+                sqrt_price_current_x96,
+                sqrt_price_next_x96,
+                liquidity,
+                false,
+            )?;
+        }
+    }
+
+    // the output can't exceed what was asked for
+    if !exact_input && amount_out > amount_remaining {
+        amount_out = amount_remaining;
+    }
+
+    let fee_amount = if exact_input && sqrt_price_next_x96 != sqrt_price_target_x96 {
+        // the rest of the input is taken as the fee
+        amount_remaining - amount_in
+    } else {
+        mul_div_rounding_up(
+            amount_in,
+            U256::from(fee_pips),
+            U256::from(FEE_DENOMINATOR - fee_pips),
+        )?
+    };
+
+    Some(SwapStep {
+        sqrt_price_next_x96,
+        amount_in,
+        amount_out,
+        fee_amount,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const E18: u64 = 1_000_000_000_000_000_000;
+
+    fn u256(value: &str) -> U256 {
+        U256::from_dec_str(value).unwrap()
+    }
+
+    // encodePriceSqrt from the v3-core tests
+    fn encode_price_sqrt(reserve1: u64, reserve0: u64) -> U256 {
+        ((U256::from(reserve1) << 192) / U256::from(reserve0)).integer_sqrt()
+    }
+
+    #[test]
+    fn computes_sqrt_ratio_at_tick() {
+        assert_eq!(get_sqrt_ratio_at_tick(MIN_TICK), Some(MIN_SQRT_RATIO));
+        assert_eq!(get_sqrt_ratio_at_tick(MAX_TICK), Some(MAX_SQRT_RATIO));
+        assert_eq!(get_sqrt_ratio_at_tick(0), Some(q96()));
+        assert_eq!(
+            get_sqrt_ratio_at_tick(MIN_TICK + 1),
+            Some(U256::from(4295343490u64))
+        );
+        assert_eq!(
+            get_sqrt_ratio_at_tick(MAX_TICK - 1),
+            Some(u256("1461373636630004318706518188784493106690254656249"))
+        );
+        assert_eq!(get_sqrt_ratio_at_tick(MIN_TICK - 1), None);
+        assert_eq!(get_sqrt_ratio_at_tick(MAX_TICK + 1), None);
+    }
+
+    #[test]
+    fn computes_tick_at_sqrt_ratio() {
+        assert_eq!(get_tick_at_sqrt_ratio(MIN_SQRT_RATIO), Some(MIN_TICK));
+        assert_eq!(
+            get_tick_at_sqrt_ratio(MAX_SQRT_RATIO - 1),
+            Some(MAX_TICK - 1)
+        );
+        assert_eq!(get_tick_at_sqrt_ratio(q96()), Some(0));
+        assert_eq!(get_tick_at_sqrt_ratio(q96() - 1), Some(-1));
+        assert_eq!(get_tick_at_sqrt_ratio(MIN_SQRT_RATIO - 1), None);
+        assert_eq!(get_tick_at_sqrt_ratio(MAX_SQRT_RATIO), None);
+
+        for tick in [-200_000, -60, -1, 1, 60, 200_311] {
+            let sqrt_ratio = get_sqrt_ratio_at_tick(tick).unwrap();
+            assert_eq!(get_tick_at_sqrt_ratio(sqrt_ratio), Some(tick));
+            assert_eq!(get_tick_at_sqrt_ratio(sqrt_ratio - 1), Some(tick - 1));
+        }
+    }
+
+    #[test]
+    fn rounds_mul_div() {
+        let max = U256::MAX;
+        assert_eq!(mul_div(max, max, max), Some(max));
+        assert_eq!(mul_div(max, U256::from(2), U256::one()), None);
+        assert_eq!(mul_div(U256::one(), U256::one(), U256::zero()), None);
+        assert_eq!(
+            mul_div(U256::from(7), U256::from(3), U256::from(2)),
+            Some(U256::from(10))
+        );
+        assert_eq!(
+            mul_div_rounding_up(U256::from(7), U256::from(3), U256::from(2)),
+            Some(U256::from(11))
+        );
+        assert_eq!(mul_div_rounding_up(max, max, max - 1), None);
+    }
+
+    // vectors from the v3-core SwapMath tests
+    #[test]
+    fn computes_swap_steps() {
+        let liquidity = 2 * E18 as u128;
+
+        // exact input capped at the target price
+        let step = compute_swap_step(
+            encode_price_sqrt(1, 1),
+            encode_price_sqrt(101, 100),
+            liquidity,
+            U256::from(E18),
+            true,
+            600,
+        )
+        .unwrap();
+        assert_eq!(step.sqrt_price_next_x96, encode_price_sqrt(101, 100));
+        assert_eq!(step.amount_in, u256("9975124224178055"));
+        assert_eq!(step.fee_amount, u256("5988667735148"));
+        assert_eq!(step.amount_out, u256("9925619580021728"));
+
+        // exact output capped at the target price
+        let step = compute_swap_step(
+            encode_price_sqrt(1, 1),
+            encode_price_sqrt(101, 100),
+            liquidity,
+            U256::from(E18),
+            false,
+            600,
+        )
+        .unwrap();
+        assert_eq!(step.sqrt_price_next_x96, encode_price_sqrt(101, 100));
+        assert_eq!(step.amount_in, u256("9975124224178055"));
+        assert_eq!(step.fee_amount, u256("5988667735148"));
+        assert_eq!(step.amount_out, u256("9925619580021728"));
+
+        // exact input fully spent before the target price
+        let step = compute_swap_step(
+            encode_price_sqrt(1, 1),
+            encode_price_sqrt(1000, 100),
+            liquidity,
+            U256::from(E18),
+            true,
+            600,
+        )
+        .unwrap();
+        assert!(step.sqrt_price_next_x96 < encode_price_sqrt(1000, 100));
+        assert_eq!(step.amount_in, u256("999400000000000000"));
+        assert_eq!(step.fee_amount, u256("600000000000000"));
+        assert_eq!(step.amount_out, u256("666399946655997866"));
+    }
+}
@@ -0,0 +1,352 @@
+use super::uniswap_router::{Pool, PoolSwap, Protocol};
+use super::uniswap_v3_pool::{tick_bitmap_word, tick_bitmap_words, Tick, V3Pool};
+use anyhow::{anyhow, Result};
+use ethers::{
+    abi::{self, ParamType, Token},
+    prelude::Middleware,
+    types::{
+        transaction::eip2718::TypedTransaction, BlockId, Bytes, Log, TransactionRequest, H160,
+        I256, U256, U64,
+    },
+};
+use serde::Deserialize;
+use std::collections::BTreeMap;
+
+/// `bytes4(keccak256("token0()"))`
+const TOKEN0_SELECTOR: [u8; 4] = [0x0d, 0xfe, 0x16, 0x81];
+/// `bytes4(keccak256("token1()"))`
+const TOKEN1_SELECTOR: [u8; 4] = [0xd2, 0x12, 0x20, 0xa7];
+/// `bytes4(keccak256("fee()"))`
+const FEE_SELECTOR: [u8; 4] = [0xdd, 0xca, 0x3f, 0x43];
+/// `bytes4(keccak256("tickSpacing()"))`
+const TICK_SPACING_SELECTOR: [u8; 4] = [0xd0, 0xc9, 0x3a, 0x7c];
+/// `bytes4(keccak256("slot0()"))`
+const SLOT0_SELECTOR: [u8; 4] = [0x38, 0x50, 0xc7, 0xbd];
+/// `bytes4(keccak256("liquidity()"))`
+const LIQUIDITY_SELECTOR: [u8; 4] = [0x1a, 0x68, 0x65, 0x02];
+/// `bytes4(keccak256("tickBitmap(int16)"))`
+const TICK_BITMAP_SELECTOR: [u8; 4] = [0x53, 0x39, 0xc2, 0x96];
+/// `bytes4(keccak256("ticks(int24)"))`
+const TICKS_SELECTOR: [u8; 4] = [0xf3, 0x0d, 0xba, 0x93];
+
+/// Words of the tick bitmap loaded on each side of the current tick. Swaps that move the price
+/// further can't be simulated, so aren't routed.
+const TICK_BITMAP_WORDS: i16 = 4;
+/// Gas used by each swap through a pool, and by each initialized tick it crosses.
+const HOP_GAS: u64 = 70_000;
+const TICK_GAS: u64 = 25_000;
+
+/// The Uniswap V3 pools to route through.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UniswapV3RouterConfig {
+    /// SwapRouter02 on the chain, which swaps are sent through.
+    pub swap_router: String,
+    pub pools: Vec<String>,
+}
+
+impl Pool for V3Pool {
+    fn address(&self) -> H160 {
+        self.address
+    }
+
+    fn tokens(&self) -> [H160; 2] {
+        [self.token0, self.token1]
+    }
+
+    fn protocol(&self) -> Protocol {
+        Protocol::V3 { fee: self.fee }
+    }
+
+    /// Pools that run out of liquidity before all of the input is swapped can't be routed
+    /// through.
+    fn quote_exact_input(&self, token_in: H160, amount_in: U256) -> Option<PoolSwap> {
+        match self.quote(token_in, amount_in, true)? {
+            (token_out, swap) if swap.amount_in == amount_in => Some(PoolSwap {
+                token_out,
+                amount_out: swap.amount_out,
+                gas: HOP_GAS + TICK_GAS * swap.ticks_crossed as u64,
+            }),
+            _ => None,
+        }
+    }
+
+    fn apply_log(&mut self, log: &Log) -> bool {
+        V3Pool::apply_log(self, log)
+    }
+}
+
+/// The `exactInput` path of tokens and the fee tiers between them: each token's address,
+/// separated by 3 byte fees.
+pub fn encode_path(tokens: &[H160], fees: &[u32]) -> Bytes {
+    let mut path = tokens[0].as_bytes().to_vec();
+    for (token, fee) in tokens[1..].iter().zip(fees) {
+        path.extend_from_slice(&fee.to_be_bytes()[1..]);
+        path.extend_from_slice(token.as_bytes());
+    }
+    path.into()
+}
+
+// ints are sign extended to 256 bits in calls and results
+fn int_token(value: i32) -> Token {
+    Token::Int(I256::from(value).into_raw())
+}
+
+async fn call_pool<M>(
+    client: &M,
+    pool: H160,
+    selector: [u8; 4],
+    args: &[Token],
+    outputs: &[ParamType],
+    block: U64,
+) -> Result<Vec<Token>>
+where
+    M: Middleware,
+    M::Error: 'static,
+{
+    let data = [selector.to_vec(), abi::encode(args)].concat();
+    let tx: TypedTransaction = TransactionRequest::new().to(pool).data(data).into();
+    let result = client.call(&tx, Some(BlockId::from(block))).await?;
+    Ok(abi::decode(outputs, &result)?)
+}
+
+async fn call_pool_word<M>(
+    client: &M,
+    pool: H160,
+    selector: [u8; 4],
+    output: ParamType,
+    block: U64,
+) -> Result<Token>
+where
+    M: Middleware,
+    M::Error: 'static,
+{
+    let mut result = call_pool(client, pool, selector, &[], &[output], block).await?;
+    result
+        .pop()
+        .ok_or_else(|| anyhow!("empty result from pool {:?}", pool))
+}
+
+/// Loads the state of a pool as of the end of `block`, with the initialized ticks of the tick
+/// bitmap words around the current tick.
+pub async fn load_pool<M>(client: &M, address: H160, block: U64) -> Result<V3Pool>
+where
+    M: Middleware,
+    M::Error: 'static,
+{
+    let invalid = || anyhow!("invalid state for pool {:?}", address);
+    let token = |token: Token| match token {
+        Token::Address(token) => Ok(token),
+        _ => Err(invalid()),
+    };
+    let uint = |token: Token| match token {
+        Token::Uint(value) | Token::Int(value) => Ok(value),
+        _ => Err(invalid()),
+    };
+
+    let token0 =
+        token(call_pool_word(client, address, TOKEN0_SELECTOR, ParamType::Address, block).await?)?;
+    let token1 =
+        token(call_pool_word(client, address, TOKEN1_SELECTOR, ParamType::Address, block).await?)?;
+    let fee =
+        uint(call_pool_word(client, address, FEE_SELECTOR, ParamType::Uint(24), block).await?)?;
+    let tick_spacing = uint(
+        call_pool_word(
+            client,
+            address,
+            TICK_SPACING_SELECTOR,
+            ParamType::Int(24),
+            block,
+        )
+        .await?,
+    )?;
+    let liquidity = uint(
+        call_pool_word(
+            client,
+            address,
+            LIQUIDITY_SELECTOR,
+            ParamType::Uint(128),
+            block,
+        )
+        .await?,
+    )?;
+    let slot0 = [
+        ParamType::Uint(160),
+        ParamType::Int(24),
+        ParamType::Uint(16),
+        ParamType::Uint(16),
+        ParamType::Uint(16),
+        ParamType::Uint(8),
+        ParamType::Bool,
+    ];
+    let (sqrt_price_x96, tick) =
+        match call_pool(client, address, SLOT0_SELECTOR, &[], &slot0, block)
+            .await?
+            .as_slice()
+        {
+            [Token::Uint(sqrt_price_x96), Token::Int(tick), ..] => (*sqrt_price_x96, *tick),
+            _ => return Err(invalid()),
+        };
+    // sign extended, so the low bits are the two's complement value
+    let tick = tick.low_u32() as i32;
+    let tick_spacing = tick_spacing.low_u32() as i32;
+
+    let current_word = tick_bitmap_word(tick, tick_spacing);
+    let all_words = tick_bitmap_words(tick_spacing);
+    let first_word = (current_word - TICK_BITMAP_WORDS).max(*all_words.start());
+    let last_word = (current_word + TICK_BITMAP_WORDS).min(*all_words.end());
+    let words = first_word..=last_word;
+
+    let mut ticks = BTreeMap::new();
+    let tick_info = [
+        ParamType::Uint(128),
+        ParamType::Int(128),
+        ParamType::Uint(256),
+        ParamType::Uint(256),
+        ParamType::Int(56),
+        ParamType::Uint(160),
+        ParamType::Uint(32),
+        ParamType::Bool,
+    ];
+    for word in words.clone() {
+        let args = [int_token(word.into())];
+        let bitmap = match call_pool(
+            client,
+            address,
+            TICK_BITMAP_SELECTOR,
+            &args,
+            &[ParamType::Uint(256)],
+            block,
+        )
+        .await?
+        .as_slice()
+        {
+            [Token::Uint(bitmap)] => *bitmap,
+            _ => return Err(invalid()),
+        };
+        for bit in (0..256).filter(|bit| bitmap.bit(*bit)) {
+            let tick = ((i32::from(word) << 8) + bit as i32) * tick_spacing;
+            let args = [int_token(tick)];
+            match call_pool(client, address, TICKS_SELECTOR, &args, &tick_info, block)
+                .await?
+                .as_slice()
+            {
+                [Token::Uint(liquidity_gross), Token::Int(liquidity_net), ..] => {
+                    ticks.insert(
+                        tick,
+                        Tick {
+                            liquidity_gross: liquidity_gross.low_u128(),
+                            liquidity_net: liquidity_net.low_u128() as i128,
+                        },
+                    );
+                }
+                _ => return Err(invalid()),
+            }
+        }
+    }
+
+    Ok(V3Pool {
+        address,
+        token0,
+        token1,
+        fee: fee.low_u32(),
+        tick_spacing,
+        sqrt_price_x96,
+        tick,
+        liquidity: liquidity.low_u128(),
+        ticks,
+        words,
+        // the state includes every log in the block
+        synced_at: (block, U256::MAX),
+    })
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::routers::uniswap_router::Pools;
+
+    fn token(byte: u8) -> H160 {
+        H160::repeat_byte(byte)
+    }
+
+    // a pool with full range liquidity around a 1:1 price
+    pub(crate) fn pool(address: u8, token0: u8, token1: u8, fee: u32, liquidity: u128) -> V3Pool {
+        let tick_spacing = 60;
+        let max_tick = 887220;
+        let liquidity_net = liquidity as i128;
+        V3Pool {
+            address: H160::repeat_byte(address),
+            token0: token(token0),
+            token1: token(token1),
+            fee,
+            tick_spacing,
+            sqrt_price_x96: U256::one() << 96,
+            tick: 0,
+            liquidity,
+            ticks: BTreeMap::from([
+                (
+                    -max_tick,
+                    Tick {
+                        liquidity_gross: liquidity,
+                        liquidity_net,
+                    },
+                ),
+                (
+                    max_tick,
+                    Tick {
+                        liquidity_gross: liquidity,
+                        liquidity_net: -liquidity_net,
+                    },
+                ),
+            ]),
+            words: tick_bitmap_words(tick_spacing),
+            synced_at: (U64::from(10), U256::MAX),
+        }
+    }
+
+    #[test]
+    fn finds_best_path() {
+        const E18: u128 = 1_000_000_000_000_000_000;
+        let mut pools = Pools::default();
+        // a shallow direct pool, and two deep ones through another token
+        pools.insert(Box::new(pool(0xab, 0xa, 0xb, 3000, E18)));
+        pools.insert(Box::new(pool(0xac, 0xa, 0xc, 500, 1000 * E18)));
+        pools.insert(Box::new(pool(0xbc, 0xb, 0xc, 500, 1000 * E18)));
+
+        let amount_in = U256::from(10 * E18);
+        let path = pools.best_path(token(0xa), token(0xb), amount_in).unwrap();
+        assert_eq!(path.tokens, vec![token(0xa), token(0xc), token(0xb)]);
+        assert_eq!(
+            path.protocols,
+            vec![Protocol::V3 { fee: 500 }, Protocol::V3 { fee: 500 }]
+        );
+
+        let direct = pools
+            .get(&H160::repeat_byte(0xab))
+            .unwrap()
+            .quote_exact_input(token(0xa), amount_in)
+            .unwrap();
+        assert!(path.amount_out > direct.amount_out);
+
+        // the other direction of the same pools
+        let path = pools.best_path(token(0xb), token(0xa), amount_in).unwrap();
+        assert_eq!(path.tokens, vec![token(0xb), token(0xc), token(0xa)]);
+
+        assert!(pools.best_path(token(0xa), token(0xd), amount_in).is_none());
+    }
+
+    #[test]
+    fn encodes_exact_input_path() {
+        let path = encode_path(&[token(0xa), token(0xc), token(0xb)], &[500, 3000]);
+        assert_eq!(
+            path.to_string(),
+            format!(
+                "0x{}0001f4{}000bb8{}",
+                "0a".repeat(20),
+                "0c".repeat(20),
+                "0b".repeat(20)
+            )
+        );
+    }
+}
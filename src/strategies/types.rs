@@ -1,25 +1,55 @@
-use crate::collectors::{
-    block_collector::NewBlock, uniswapx_order_collector::UniswapXOrder,
-    uniswapx_route_collector::RoutedOrder,
+use crate::{
+    collectors::{
+        block_collector::{NewBlock, Reorg},
+        permit2_collector::Permit2Event,
+        uniswapx_fill_collector::Fill,
+        uniswapx_order_collector::UniswapXOrder,
+        uniswapx_route_collector::RoutedOrder,
+    },
+    executors::protect_executor::SubmitFill,
 };
-use artemis_core::executors::mempool_executor::SubmitTxToMempool;
+use serde::Deserialize;
 
 /// Core Event enum for the current strategy.
 #[derive(Debug, Clone)]
 pub enum Event {
     NewBlock(NewBlock),
+    Reorg(Reorg),
     UniswapXOrder(Box<UniswapXOrder>),
     UniswapXRoute(Box<RoutedOrder>),
+    Fill(Fill),
+    Permit2(Permit2Event),
 }
 
 /// Core Action enum for the current strategy.
 #[derive(Debug, Clone)]
 pub enum Action {
-    SubmitTx(SubmitTxToMempool),
+    SubmitTx(SubmitFill),
 }
 
 /// Configuration for variables we need to pass to the strategy.
 #[derive(Debug, Clone)]
 pub struct Config {
     pub bid_percentage: u64,
+    pub chain: ChainConfig,
+}
+
+/// The chain a pipeline fills orders on.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ChainConfig {
+    pub chain_id: u64,
+    // reactors to watch for fills
+    pub reactors: Vec<String>,
+    pub wrapped_native_token: String,
+    // executor contract fills are routed through
+    pub executor: String,
+    pub block_time_ms: u64,
+}
+
+impl ChainConfig {
+    /// The block time, rounded up to whole seconds like block timestamps.
+    pub fn block_time_secs(&self) -> u64 {
+        (self.block_time_ms + 999) / 1000
+    }
 }
@@ -1,12 +1,17 @@
 use super::types::Config;
-use crate::collectors::{
-    block_collector::NewBlock,
-    uniswapx_order_collector::{UniswapXOrder, CHAIN_ID},
-    uniswapx_route_collector::{OrderBatchData, OrderData, RoutedOrder},
+use crate::{
+    collectors::{
+        block_collector::{NewBlock, Reorg},
+        permit2_collector::Permit2Event,
+        uniswapx_fill_collector::Fill,
+        uniswapx_order_collector::{UniswapXOrder, OPEN_ORDER_STATUS},
+        uniswapx_route_collector::{OrderBatchData, OrderData, RoutedOrder},
+    },
+    executors::protect_executor::SubmitFill,
 };
-use alloy_primitives::Uint;
-use anyhow::Result;
-use artemis_core::executors::mempool_executor::{GasBidInfo, SubmitTxToMempool};
+use alloy_primitives::{Address, Uint};
+use anyhow::{anyhow, Result};
+use artemis_core::executors::mempool_executor::GasBidInfo;
 use artemis_core::types::Strategy;
 use async_trait::async_trait;
 use bindings_uniswapx::{
@@ -15,28 +20,28 @@ use bindings_uniswapx::{
 use ethers::{
     abi::{ethabi, AbiEncode, Token},
     providers::Middleware,
-    types::{transaction::eip2718::TypedTransaction, Address, Bytes, Filter, H160, U256},
+    types::{transaction::eip2718::TypedTransaction, Bytes, H160, H256, U256},
 };
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::str::FromStr;
 use std::sync::Arc;
-use std::time::{SystemTime, UNIX_EPOCH};
 use tokio::sync::mpsc::{Receiver, Sender};
 use tracing::{error, info};
-use uniswapx_rs::order::{
-    decode_order, encode_order, ExclusiveDutchOrder, OrderResolution, ResolvedOrder,
+use uniswapx_rs::{
+    fees::FeeOutputsCache,
+    hash::PERMIT2_ADDRESS,
+    nonce::get_used_nonces,
+    order::{Order, OrderResolution, OrderType, ResolutionParams, ResolvedOrder},
+    profitability::earliest_profitable_time,
 };
 
-use super::types::{Action, Event};
+use super::types::{Action, ChainConfig, Event};
 
-const BLOCK_TIME: u64 = 12;
 const DONE_EXPIRY: u64 = 300;
-const REACTOR_ADDRESS: &str = "0xe80bF394d190851E215D5F67B67f8F5A52783F1E";
-pub const WETH_ADDRESS: &str = "0xC02aaA39b223FE8D0A0e5C4F27eAD9083C756Cc2";
-pub const EXECUTOR_ADDRESS: &str = "TODO: Fill in swaprouter02 executor address";
 
 #[derive(Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
 struct TokenInTokenOut {
+    reactor: String,
     token_in: String,
     token_out: String,
 }
@@ -54,12 +59,32 @@ pub struct UniswapXUniswapFill<M> {
     client: Arc<M>,
     /// Amount of profits to bid in gas
     bid_percentage: u64,
+    /// Chain the strategy fills orders on.
+    chain: ChainConfig,
+    /// Reactors of the chain, which orders for other reactors can't be filled through.
+    reactors: Vec<Address>,
     last_block_number: u64,
     last_block_timestamp: u64,
+    // base fee of the next block, none on chains without EIP-1559
+    next_base_fee: Option<U256>,
     // map of open order hashes to order data
     open_orders: HashMap<String, OrderData>,
+    // map of order hashes to orders and signatures that can't be filled by us yet
+    pending_orders: HashMap<String, (Order, String)>,
+    // map of order hashes to orders whose signature couldn't be checked or that arrived before
+    // the first block, verified again on the next block
+    unverified_orders: HashMap<String, UniswapXOrder>,
     // map of done order hashes to time at which we can safely prune them
     done_orders: HashMap<String, u64>,
+    // map of order hashes marked done by a fill to the fill's block, and the order and signature
+    // to restore if the block is reorged out
+    fills: HashMap<String, (H256, Option<(Order, String)>)>,
+    // map of reactor addresses to their protocol fee controllers
+    fee_controllers: HashMap<String, String>,
+    // fee outputs of orders resolved against the next block
+    fee_outputs: FeeOutputsCache,
+    // map of order hashes to the block they become profitable in and the route to fill them with
+    scheduled_fills: HashMap<String, (u64, RoutedOrder)>,
     batch_sender: Sender<Vec<OrderBatchData>>,
     route_receiver: Receiver<RoutedOrder>,
 }
@@ -73,13 +98,29 @@ impl<M: Middleware + 'static> UniswapXUniswapFill<M> {
     ) -> Self {
         info!("syncing state");
 
+        let reactors = config
+            .chain
+            .reactors
+            .iter()
+            .filter_map(|reactor| reactor.parse().ok())
+            .collect();
+
         Self {
             client,
             bid_percentage: config.bid_percentage,
+            chain: config.chain,
+            reactors,
             last_block_number: 0,
             last_block_timestamp: 0,
+            next_base_fee: None,
             open_orders: HashMap::new(),
+            pending_orders: HashMap::new(),
+            unverified_orders: HashMap::new(),
             done_orders: HashMap::new(),
+            fills: HashMap::new(),
+            fee_controllers: HashMap::new(),
+            fee_outputs: FeeOutputsCache::default(),
+            scheduled_fills: HashMap::new(),
             batch_sender: sender,
             route_receiver: receiver,
         }
@@ -100,7 +141,10 @@ impl<M: Middleware + 'static> Strategy<Event, Action> for UniswapXUniswapFill<M>
         match event {
             Event::UniswapXOrder(order) => self.process_order_event(*order).await,
             Event::NewBlock(block) => self.process_new_block_event(block).await,
+            Event::Reorg(reorg) => self.process_reorg_event(reorg),
             Event::UniswapXRoute(route) => self.process_new_route(*route).await,
+            Event::Fill(fill) => self.process_fill_event(fill),
+            Event::Permit2(event) => self.process_permit2_event(event),
         }
     }
 }
@@ -108,15 +152,175 @@ impl<M: Middleware + 'static> Strategy<Event, Action> for UniswapXUniswapFill<M>
 impl<M: Middleware + 'static> UniswapXUniswapFill<M> {
     // Process new orders as they come in.
     async fn process_order_event(&mut self, event: UniswapXOrder) -> Option<Action> {
+        // orders can't be resolved without a block, so handle them on the first one
         if self.last_block_timestamp == 0 {
+            self.unverified_orders
+                .insert(event.order_hash.to_lowercase(), event);
+            return None;
+        }
+
+        // the order collector reports orders again when they are filled, cancelled or expired
+        if event.order_status != OPEN_ORDER_STATUS {
+            info!(
+                "Order {}, removing: {}",
+                event.order_status, event.order_hash
+            );
+            self.mark_as_done(&event.order_hash.to_lowercase());
             return None;
         }
 
-        let order = decode_order(&event.encoded_order)
+        let order_type = event
+            .order_type
+            .as_deref()
+            .and_then(OrderType::from_api_type);
+        let order = Order::decode(&event.encoded_order, order_type)
             .map_err(|e| error!("failed to decode: {}", e))
             .ok()?;
 
-        self.update_order_state(order, event.signature, event.order_hash);
+        // order hashes key open and done orders, so don't trust the one reported by the api
+        let order_hash = format!("0x{:x}", H256::from_slice(order.hash().as_slice()));
+        if !order_hash.eq_ignore_ascii_case(&event.order_hash) {
+            error!(
+                "order hash mismatch, api: {}, computed: {}",
+                event.order_hash, order_hash
+            );
+            return None;
+        }
+
+        // only verify signatures of orders we haven't seen yet
+        if !self.open_orders.contains_key(&order_hash)
+            && !self.done_orders.contains_key(&order_hash)
+        {
+            // orders the reactor would always revert on can never be filled
+            if let Err(e) = order.validate(&self.reactors) {
+                info!("Invalid order, skipping: {}: {}", order_hash, e);
+                self.mark_as_done(&order_hash);
+                return None;
+            }
+
+            // the reactor would revert with InvalidCosignature
+            if !order.verify_cosignature(self.chain.chain_id) {
+                info!("Invalid cosignature, skipping: {}", order_hash);
+                self.mark_as_done(&order_hash);
+                return None;
+            }
+
+            let signature = match Bytes::from_str(&event.signature) {
+                Ok(signature) => signature,
+                Err(e) => {
+                    info!("Undecodable signature, skipping: {}: {}", order_hash, e);
+                    self.mark_as_done(&order_hash);
+                    return None;
+                }
+            };
+            let valid = match order
+                .verify_swapper_signature(
+                    self.client.as_ref(),
+                    &signature,
+                    self.chain.chain_id,
+                    PERMIT2_ADDRESS.parse().ok()?,
+                )
+                .await
+            {
+                Ok(valid) => valid,
+                Err(e) => {
+                    // the collector won't report the order again, so check it on the next block
+                    error!("failed to verify signature of {}: {}", order_hash, e);
+                    self.unverified_orders.insert(order_hash, event);
+                    return None;
+                }
+            };
+            if !valid {
+                info!("Invalid swapper signature, skipping: {}", order_hash);
+                self.mark_as_done(&order_hash);
+                return None;
+            }
+        }
+
+        self.update_order_state(order, event.signature, order_hash)
+            .await;
+        None
+    }
+
+    // Filled orders can't be filled again.
+    fn process_fill_event(&mut self, fill: Fill) -> Option<Action> {
+        let order_hash = format!("0x{:x}", H256::from(fill.event.order_hash));
+        let order = match self.open_orders.get(&order_hash) {
+            Some(order_data) => {
+                info!("Removing filled order {}", order_hash);
+                Some((order_data.order.clone(), order_data.signature.clone()))
+            }
+            None => self.pending_orders.get(&order_hash).cloned(),
+        };
+        self.mark_as_done(&order_hash);
+        self.fills.insert(order_hash, (fill.block_hash, order));
+        None
+    }
+
+    // Fills in orphaned blocks didn't happen, so their orders may still be fillable. Restored
+    // orders are re-resolved with the pending orders on the next block.
+    fn process_reorg_event(&mut self, reorg: Reorg) -> Option<Action> {
+        let dropped: HashSet<H256> = reorg
+            .dropped_blocks
+            .iter()
+            .map(|block| block.hash)
+            .collect();
+        let orphaned: Vec<String> = self
+            .fills
+            .iter()
+            .filter(|(_, (block_hash, _))| dropped.contains(block_hash))
+            .map(|(order_hash, _)| order_hash.clone())
+            .collect();
+        info!(
+            "Reorg of depth {}, reverting {} fills",
+            reorg.depth,
+            orphaned.len()
+        );
+        for order_hash in orphaned {
+            if let Some((_, order)) = self.fills.remove(&order_hash) {
+                self.done_orders.remove(&order_hash);
+                if let Some(order) = order {
+                    self.pending_orders.insert(order_hash, order);
+                }
+            }
+        }
+        None
+    }
+
+    // Orders the swapper cancelled through Permit2 would revert.
+    fn process_permit2_event(&mut self, event: Permit2Event) -> Option<Action> {
+        let event = match event {
+            Permit2Event::UnorderedNonceInvalidation(event) => event,
+            // lockdown only revokes allowances, and orders are filled with signature transfers
+            // that don't use them
+            Permit2Event::Lockdown(event) => {
+                info!(
+                    "Swapper {:?} locked down {:?} for {:?}",
+                    event.owner, event.token, event.spender
+                );
+                return None;
+            }
+        };
+        let cancelled: Vec<String> = self
+            .open_orders
+            .iter()
+            .map(|(order_hash, order_data)| (order_hash, &order_data.order))
+            .chain(
+                self.pending_orders
+                    .iter()
+                    .map(|(order_hash, (order, _))| (order_hash, order)),
+            )
+            .filter(|(_, order)| {
+                order
+                    .nonce_bitmap_position()
+                    .is_invalidated_by(event.owner, event.word, event.mask)
+            })
+            .map(|(order_hash, _)| order_hash.clone())
+            .collect();
+        for order_hash in cancelled {
+            info!("Removing order cancelled through permit2 {}", order_hash);
+            self.mark_as_done(&order_hash);
+        }
         None
     }
 
@@ -146,15 +350,190 @@ impl<M: Middleware + 'static> UniswapXUniswapFill<M> {
                 profit
             );
 
-            return Some(Action::SubmitTx(SubmitTxToMempool {
+            return self.submit_fill(event, profit);
+        }
+
+        self.schedule_fill(event);
+        None
+    }
+
+    fn submit_fill(&self, event: RoutedOrder, profit: U256) -> Option<Action> {
+        // the tip of a fill scales the outputs of its priority orders, so it can't be left to
+        // the executor's bid
+        if event
+            .request
+            .orders
+            .iter()
+            .any(|order_data| matches!(order_data.order, Order::Priority(_)))
+        {
+            let (event, priority_fee) = self.price_priority_fill(event, profit)?;
+            return Some(Action::SubmitTx(SubmitFill {
                 tx: self.build_fill(event).ok()?,
-                gas_bid_info: Some(GasBidInfo {
-                    bid_percentage: self.bid_percentage,
-                    total_profit: profit,
-                }),
+                gas_bid_info: None,
+                next_base_fee: self.next_base_fee,
+                priority_fee: Some(priority_fee),
             }));
         }
 
+        Some(Action::SubmitTx(SubmitFill {
+            tx: self.build_fill(event).ok()?,
+            gas_bid_info: Some(GasBidInfo {
+                bid_percentage: self.bid_percentage,
+                total_profit: profit,
+            }),
+            next_base_fee: self.next_base_fee,
+            priority_fee: None,
+        }))
+    }
+
+    // resolves the priority orders of a fill at the priority fee the bid would pay, returning
+    // the fill and that fee if it is still profitable
+    fn price_priority_fill(
+        &self,
+        mut event: RoutedOrder,
+        profit: U256,
+    ) -> Option<(RoutedOrder, U256)> {
+        let gas_use_estimate = U256::from_str_radix(&event.route.gas_use_estimate, 10).ok()?;
+        let base_fee = self.next_base_fee.unwrap_or_default();
+        let bid_gas_price = profit
+            .checked_div(gas_use_estimate)?
+            .saturating_mul(U256::from(self.bid_percentage))
+            / 100;
+        let priority_fee = bid_gas_price.checked_sub(base_fee)?;
+        let params = ResolutionParams {
+            priority_fee: Uint::from_str_radix(&priority_fee.to_string(), 10).ok()?,
+            ..self.resolution_params()
+        };
+
+        let mut amount_out_required = Uint::from(0);
+        for order_data in event.request.orders.iter_mut() {
+            if let Order::Priority(order) = &order_data.order {
+                let mut resolved =
+                    match order.resolve(params.block_number, params.timestamp, params.priority_fee)
+                    {
+                        OrderResolution::Resolved(resolved) => resolved,
+                        _ => return None,
+                    };
+                // the route swaps the input resolved without a tip
+                if resolved.input.amount != order_data.resolved.input.amount {
+                    info!(
+                        "Priority fee {} scales the input of {}, skipping",
+                        priority_fee, order_data.hash
+                    );
+                    return None;
+                }
+                // protocol fee outputs come after the order's own outputs
+                resolved.outputs.extend(
+                    order_data
+                        .resolved
+                        .outputs
+                        .iter()
+                        .skip(order.outputs.len())
+                        .cloned(),
+                );
+                order_data.resolved = resolved;
+            }
+            let token_out = order_data.resolved.outputs[0].token;
+            amount_out_required = order_data
+                .resolved
+                .outputs
+                .iter()
+                .filter(|output| output.token == token_out)
+                .fold(amount_out_required, |sum, output| {
+                    sum.wrapping_add(output.amount)
+                });
+        }
+        event.request.amount_out_required = amount_out_required;
+
+        // the scaled outputs and the tip both come out of the profit
+        let profit = self.get_profit_eth(&event)?;
+        if profit <= gas_use_estimate.saturating_mul(base_fee.saturating_add(priority_fee)) {
+            info!(
+                "Fill of priority orders is unprofitable at priority fee {}",
+                priority_fee
+            );
+            return None;
+        }
+        Some((event, priority_fee))
+    }
+
+    // schedules an unprofitable order to be filled in the block its decay makes it profitable
+    fn schedule_fill(&mut self, event: RoutedOrder) {
+        // orders in a batch become profitable at different times
+        if event.request.orders.len() != 1 {
+            return;
+        }
+        let order_data = &event.request.orders[0];
+        let (quote, gas_cost) = match (
+            Uint::from_str_radix(&event.route.quote, 10),
+            Uint::from_str_radix(&event.route.gas_use_estimate_quote, 10),
+        ) {
+            (Ok(quote), Ok(gas_cost)) => (quote, gas_cost),
+            _ => return,
+        };
+
+        let params = self.resolution_params();
+        let timestamp = match earliest_profitable_time(
+            &order_data.order,
+            &params,
+            event.request.amount_in,
+            quote,
+            gas_cost,
+        ) {
+            Some(timestamp) => timestamp,
+            None => return,
+        };
+
+        let block_time_ms = self.chain.block_time_ms.max(1);
+        let block_number = self.last_block_number
+            + ((timestamp - self.last_block_timestamp) * 1000 + block_time_ms - 1) / block_time_ms;
+        if block_number <= params.block_number {
+            return;
+        }
+        info!(
+            "Scheduling fill of {} for block {}",
+            order_data.hash, block_number
+        );
+        self.scheduled_fills
+            .insert(order_data.hash.clone(), (block_number, event));
+    }
+
+    // returns the first scheduled fill due in the next block that is still profitable
+    fn take_due_fill(&mut self) -> Option<Action> {
+        let next_block = self.last_block_number + 1;
+        let due: Vec<String> = self
+            .scheduled_fills
+            .iter()
+            .filter(|(_, (block_number, _))| *block_number <= next_block)
+            .map(|(order_hash, _)| order_hash.clone())
+            .collect();
+
+        for order_hash in due {
+            let (_, mut event) = self.scheduled_fills.remove(&order_hash)?;
+            let order_data = match self.open_orders.get(&order_hash) {
+                Some(order_data) => order_data.clone(),
+                None => continue,
+            };
+
+            // the route is still good for the input, but the outputs have decayed since
+            let token_out = order_data.resolved.outputs[0].token;
+            event.request.amount_out_required = order_data
+                .resolved
+                .outputs
+                .iter()
+                .filter(|output| output.token == token_out)
+                .fold(Uint::from(0), |sum, output| sum.wrapping_add(output.amount));
+            event.request.orders = vec![order_data];
+
+            if let Some(profit) = self.get_profit_eth(&event) {
+                info!(
+                    "Sending scheduled trade: {} routed quote: {}, order needs: {}, profit: {} wei",
+                    order_hash, event.route.quote, event.request.amount_out_required, profit
+                );
+                return self.submit_fill(event, profit);
+            }
+        }
+
         None
     }
 
@@ -162,6 +541,7 @@ impl<M: Middleware + 'static> UniswapXUniswapFill<M> {
     async fn process_new_block_event(&mut self, event: NewBlock) -> Option<Action> {
         self.last_block_number = event.number.as_u64();
         self.last_block_timestamp = event.timestamp.as_u64();
+        self.next_base_fee = event.next_base_fee;
 
         info!(
             "Processing block {} at {}, Order set sizes -- open: {}, done: {}",
@@ -170,11 +550,18 @@ impl<M: Middleware + 'static> UniswapXUniswapFill<M> {
             self.open_orders.len(),
             self.done_orders.len()
         );
-        self.handle_fills()
-            .await
-            .map_err(|e| error!("Error handling fills {}", e))
-            .ok()?;
-        self.update_open_orders();
+        if let Err(e) = self.handle_used_nonces().await {
+            error!("Error checking order nonces {}", e);
+        }
+        let unverified: Vec<UniswapXOrder> = self
+            .unverified_orders
+            .drain()
+            .map(|(_, order)| order)
+            .collect();
+        for order in unverified {
+            self.process_order_event(order).await;
+        }
+        self.update_open_orders().await;
         self.prune_done_orders();
 
         self.batch_sender
@@ -182,20 +569,21 @@ impl<M: Middleware + 'static> UniswapXUniswapFill<M> {
             .await
             .ok()?;
 
-        None
+        self.take_due_fill()
     }
 
     // builds a transaction to fill an order
     fn build_fill(&self, RoutedOrder { request, route }: RoutedOrder) -> Result<TypedTransaction> {
+        // all reactors share the same execute interface
         let reactor =
-            ExclusiveDutchOrderReactor::new(H160::from_str(REACTOR_ADDRESS)?, self.client.clone());
+            ExclusiveDutchOrderReactor::new(H160::from_str(&request.reactor)?, self.client.clone());
         let mut signed_orders: Vec<SignedOrder> = Vec::new();
         for batch in request.orders.iter() {
             let OrderData {
                 order, signature, ..
             } = batch;
             signed_orders.push(SignedOrder {
-                order: Bytes::from(encode_order(order)),
+                order: Bytes::from(order.encode()),
                 sig: Bytes::from_str(signature)?,
             });
         }
@@ -206,10 +594,10 @@ impl<M: Middleware + 'static> UniswapXUniswapFill<M> {
         ]);
         let mut call = reactor.execute_batch(
             signed_orders,
-            H160::from_str(EXECUTOR_ADDRESS)?,
+            H160::from_str(&self.chain.executor)?,
             Bytes::from(calldata),
         );
-        Ok(call.tx.set_chain_id(CHAIN_ID).clone())
+        Ok(call.tx.set_chain_id(self.chain.chain_id).clone())
     }
 
     fn get_order_batches(&self) -> HashMap<TokenInTokenOut, OrderBatchData> {
@@ -217,16 +605,30 @@ impl<M: Middleware + 'static> UniswapXUniswapFill<M> {
 
         // group orders by token in and token out
         self.open_orders.iter().for_each(|(_, order_data)| {
+            let reactor = order_data.order.info().reactor.to_string();
             let token_in_token_out = TokenInTokenOut {
-                token_in: order_data.resolved.input.token.clone(),
-                token_out: order_data.resolved.outputs[0].token.clone(),
+                reactor: reactor.clone(),
+                token_in: order_data.resolved.input.token.to_string(),
+                token_out: order_data.resolved.outputs[0].token.to_string(),
             };
 
-            let amount_in = order_data.resolved.input.amount;
+            // protocol fees may be taken from the input token, which the filler pays out of
+            // the input it receives
+            let input_token = order_data.resolved.input.token;
+            let output_token = order_data.resolved.outputs[0].token;
+            let amount_in = order_data
+                .resolved
+                .outputs
+                .iter()
+                .filter(|output| output.token == input_token)
+                .fold(order_data.resolved.input.amount, |amount, output| {
+                    amount.saturating_sub(output.amount)
+                });
             let amount_out = order_data
                 .resolved
                 .outputs
                 .iter()
+                .filter(|output| output.token == output_token)
                 .fold(Uint::from(0), |sum, output| sum.wrapping_add(output.amount));
 
             // insert new order and update total amount out
@@ -237,8 +639,9 @@ impl<M: Middleware + 'static> UniswapXUniswapFill<M> {
                     orders: vec![order_data.clone()],
                     amount_in,
                     amount_out_required: amount_out,
-                    token_in: order_data.resolved.input.token.clone(),
-                    token_out: order_data.resolved.outputs[0].token.clone(),
+                    reactor,
+                    token_in: order_data.resolved.input.token.to_string(),
+                    token_out: order_data.resolved.outputs[0].token.to_string(),
                 });
             } else {
                 let order_batch_data = order_batches.get_mut(&token_in_token_out).unwrap();
@@ -252,27 +655,31 @@ impl<M: Middleware + 'static> UniswapXUniswapFill<M> {
         order_batches
     }
 
-    async fn handle_fills(&mut self) -> Result<()> {
-        let reactor_address = REACTOR_ADDRESS.parse::<Address>().unwrap();
-        let filter = Filter::new()
-            .select(self.last_block_number)
-            .address(reactor_address)
-            .event("Fill(bytes32,address,address,uint256)");
-
-        // early return on error
-        let logs = self.client.get_logs(&filter).await?;
-        for log in logs {
-            let order_hash = format!("0x{:x}", log.topics[1]);
-            // remove from open
-            info!("Removing filled order {}", order_hash);
-            self.open_orders.remove(&order_hash);
-            // add to done
-            self.done_orders.insert(
-                order_hash.to_string(),
-                self.current_timestamp()? + DONE_EXPIRY,
-            );
-        }
+    // drops open and pending orders whose permit2 nonce is used, which includes orders the
+    // swapper cancelled with invalidateUnorderedNonces
+    async fn handle_used_nonces(&mut self) -> Result<()> {
+        let (order_hashes, positions): (Vec<String>, Vec<_>) = self
+            .open_orders
+            .iter()
+            .map(|(order_hash, order_data)| (order_hash, &order_data.order))
+            .chain(
+                self.pending_orders
+                    .iter()
+                    .map(|(order_hash, (order, _))| (order_hash, order)),
+            )
+            .map(|(order_hash, order)| (order_hash.clone(), order.nonce_bitmap_position()))
+            .unzip();
+        let permit2 = PERMIT2_ADDRESS
+            .parse()
+            .map_err(|_| anyhow!("invalid permit2 address"))?;
 
+        let used = get_used_nonces(self.client.clone(), permit2, &positions).await?;
+        for (order_hash, used) in order_hashes.iter().zip(used) {
+            if used {
+                info!("Removing order with used nonce {}", order_hash);
+                self.mark_as_done(order_hash);
+            }
+        }
         Ok(())
     }
 
@@ -285,19 +692,32 @@ impl<M: Middleware + 'static> UniswapXUniswapFill<M> {
         }
         for order_hash in to_remove {
             self.done_orders.remove(&order_hash);
+            self.fills.remove(&order_hash);
         }
     }
 
-    fn update_open_orders(&mut self) {
+    async fn update_open_orders(&mut self) {
         // TODO: this is nasty, plz cleanup
-        let binding = self.open_orders.clone();
-        let order_hashes: Vec<(&String, &OrderData)> = binding.iter().collect();
-        for (order_hash, order_data) in order_hashes {
-            self.update_order_state(
-                order_data.order.clone(),
-                order_data.signature.clone(),
-                order_hash.clone().to_string(),
-            );
+        let mut orders: Vec<(String, Order, String)> = self
+            .open_orders
+            .iter()
+            .map(|(order_hash, order_data)| {
+                (
+                    order_hash.clone(),
+                    order_data.order.clone(),
+                    order_data.signature.clone(),
+                )
+            })
+            .collect();
+        orders.extend(
+            self.pending_orders
+                .iter()
+                .map(|(order_hash, (order, signature))| {
+                    (order_hash.clone(), order.clone(), signature.clone())
+                }),
+        );
+        for (order_hash, order, signature) in orders {
+            self.update_order_state(order, signature, order_hash).await;
         }
     }
 
@@ -305,22 +725,104 @@ impl<M: Middleware + 'static> UniswapXUniswapFill<M> {
         if self.open_orders.contains_key(order) {
             self.open_orders.remove(order);
         }
+        self.pending_orders.remove(order);
+        self.unverified_orders.remove(order);
+        self.scheduled_fills.remove(order);
         if !self.done_orders.contains_key(order) {
             self.done_orders
                 .insert(order.to_string(), self.last_block_timestamp + DONE_EXPIRY);
         }
     }
 
-    fn update_order_state(
-        &mut self,
-        order: ExclusiveDutchOrder,
-        signature: String,
-        order_hash: String,
-    ) {
-        let resolved = order.resolve(self.last_block_timestamp + BLOCK_TIME);
+    // returns the reactor's protocol fee controller, querying it the first time a reactor is seen
+    async fn get_fee_controller(&mut self, reactor: &str) -> Result<String> {
+        if let Some(fee_controller) = self.fee_controllers.get(reactor) {
+            return Ok(fee_controller.clone());
+        }
+        // all reactors share the same ProtocolFees interface
+        let fee_controller =
+            ExclusiveDutchOrderReactor::new(H160::from_str(reactor)?, self.client.clone())
+                .fee_controller()
+                .call()
+                .await?;
+        let fee_controller = format!("{:?}", fee_controller);
+        self.fee_controllers
+            .insert(reactor.to_string(), fee_controller.clone());
+        Ok(fee_controller)
+    }
+
+    // resolve against the next block, assuming no priority fee is paid
+    fn resolution_params(&self) -> ResolutionParams {
+        // fills call the reactor from our account, so that's the filler exclusivity is checked
+        // against
+        let filler = self
+            .client
+            .default_sender()
+            .and_then(|sender| format!("{:?}", sender).parse().ok())
+            .unwrap_or_default();
+
+        ResolutionParams {
+            block_number: self.last_block_number + 1,
+            timestamp: self.last_block_timestamp + self.chain.block_time_secs(),
+            priority_fee: Uint::from(0),
+            filler,
+        }
+    }
+
+    // keeps an order out of batches and resolves it again on the next block, since the order
+    // collector only emits new orders
+    fn retry_next_block(&mut self, order: Order, signature: String, order_hash: String) {
+        if self.done_orders.contains_key(&order_hash) {
+            return;
+        }
+        self.open_orders.remove(&order_hash);
+        self.scheduled_fills.remove(&order_hash);
+        self.pending_orders.insert(order_hash, (order, signature));
+    }
+
+    async fn update_order_state(&mut self, order: Order, signature: String, order_hash: String) {
+        let reactor = order.info().reactor.to_string();
+        let fee_controller = match self.get_fee_controller(&reactor).await {
+            Ok(fee_controller) => fee_controller,
+            Err(e) => {
+                error!("failed to get fee controller for {}: {}", reactor, e);
+                self.retry_next_block(order, signature, order_hash);
+                return;
+            }
+        };
+        let fee_controller = match fee_controller.parse() {
+            Ok(fee_controller) => fee_controller,
+            Err(_) => {
+                error!("invalid fee controller for {}: {}", reactor, fee_controller);
+                self.retry_next_block(order, signature, order_hash);
+                return;
+            }
+        };
+
+        let params = self.resolution_params();
+        let resolved = match order
+            .resolve_with_fees(
+                self.client.clone(),
+                fee_controller,
+                &params,
+                &mut self.fee_outputs,
+            )
+            .await
+        {
+            Ok(resolved) => resolved,
+            Err(e) => {
+                error!("failed to resolve fees for {}: {}", order_hash, e);
+                self.retry_next_block(order, signature, order_hash);
+                return;
+            }
+        };
         let order_status: OrderStatus = match resolved {
             OrderResolution::Expired => OrderStatus::Done,
             OrderResolution::Invalid => OrderStatus::Done,
+            OrderResolution::NotFillableYet | OrderResolution::ExclusiveToOtherFiller => {
+                self.retry_next_block(order, signature, order_hash);
+                return;
+            }
             OrderResolution::Resolved(resolved_order) => OrderStatus::Open(resolved_order),
         };
 
@@ -336,6 +838,7 @@ impl<M: Middleware + 'static> UniswapXUniswapFill<M> {
                 if !self.open_orders.contains_key(&order_hash) {
                     info!("Adding new order {}", order_hash);
                 }
+                self.pending_orders.remove(&order_hash);
                 self.open_orders.insert(
                     order_hash.clone(),
                     OrderData {
@@ -349,11 +852,6 @@ impl<M: Middleware + 'static> UniswapXUniswapFill<M> {
         }
     }
 
-    fn current_timestamp(&self) -> Result<u64> {
-        let start = SystemTime::now();
-        Ok(start.duration_since(UNIX_EPOCH)?.as_secs())
-    }
-
     fn get_profit_eth(&self, RoutedOrder { request, route }: &RoutedOrder) -> Option<U256> {
         let quote = U256::from_str_radix(&route.quote, 10).ok()?;
         let amount_out_required =
@@ -362,16 +860,197 @@ impl<M: Middleware + 'static> UniswapXUniswapFill<M> {
             return None;
         }
         let profit_quote = quote.saturating_sub(amount_out_required);
+        let gas_use_estimate = U256::from_str_radix(&route.gas_use_estimate, 10).ok()?;
+
+        let profit = if request
+            .token_out
+            .eq_ignore_ascii_case(&self.chain.wrapped_native_token)
+        {
+            profit_quote
+        } else {
+            // the gas estimate in eth and in the quote token gives the exchange rate, so this has
+            // to use the gas price the api quoted the estimate at
+            let gas_use_eth = gas_use_estimate
+                .saturating_mul(U256::from_str_radix(&route.gas_price_wei, 10).ok()?);
+            profit_quote
+                .saturating_mul(gas_use_eth)
+                .checked_div(U256::from_str_radix(&route.gas_use_estimate_quote, 10).ok()?)?
+        };
+
+        // fills have to pay at least the next block's base fee
+        match self.next_base_fee {
+            Some(base_fee) if profit <= gas_use_estimate.saturating_mul(base_fee) => None,
+            _ => Some(profit),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::collectors::uniswapx_route_collector::{MethodParameters, OrderRoute};
+    use ethers::{
+        providers::{MockProvider, Provider},
+        types::U64,
+    };
+    use tokio::sync::mpsc;
+    use uniswapx_rs::builder::{ExclusiveDutchOrderBuilder, OrderSigner, PriorityOrderBuilder};
+
+    type TestStrategy = UniswapXUniswapFill<Provider<MockProvider>>;
+
+    fn strategy(
+        provider: Provider<MockProvider>,
+        reactor: Address,
+    ) -> (TestStrategy, Receiver<Vec<OrderBatchData>>) {
+        let (batch_sender, batch_receiver) = mpsc::channel(1);
+        let (_, route_receiver) = mpsc::channel(1);
+        let config = Config {
+            bid_percentage: 50,
+            chain: ChainConfig {
+                chain_id: 1,
+                reactors: vec![reactor.to_string()],
+                wrapped_native_token: "0xC02aaA39b223FE8D0A0e5C4F27eAD9083C756Cc2".to_string(),
+                executor: "0x0000000000000000000000000000000000000001".to_string(),
+                block_time_ms: 12_000,
+            },
+        };
+        let strategy =
+            UniswapXUniswapFill::new(Arc::new(provider), config, batch_sender, route_receiver);
+        (strategy, batch_receiver)
+    }
 
-        if request.token_out.to_lowercase() == WETH_ADDRESS.to_lowercase() {
-            return Some(profit_quote);
+    fn new_block(number: u64, timestamp: u64) -> NewBlock {
+        NewBlock {
+            hash: H256::from_low_u64_be(number),
+            parent_hash: H256::from_low_u64_be(number - 1),
+            number: U64::from(number),
+            timestamp: U256::from(timestamp),
+            base_fee_per_gas: None,
+            gas_used: U256::zero(),
+            gas_limit: U256::zero(),
+            miner: H160::zero(),
+            next_base_fee: None,
         }
+    }
+
+    #[tokio::test]
+    async fn test_order_before_first_block() {
+        let swapper = OrderSigner::random(1);
+        let order = ExclusiveDutchOrderBuilder::new()
+            .swapper(swapper.address())
+            .build();
+        let signed = swapper.sign(Order::ExclusiveDutch(order)).unwrap();
+        let reactor = signed.order.info().reactor;
+
+        // the first block checks the swapper's code, then reads the reactor's fee controller
+        let (provider, mock) = Provider::mocked();
+        mock.push::<Bytes, _>(Bytes::from(vec![0u8; 32])).unwrap();
+        mock.push::<Bytes, _>(Bytes::default()).unwrap();
+
+        let (mut strategy, mut batch_receiver) = strategy(provider, reactor);
+
+        let event = UniswapXOrder {
+            encoded_order: signed.encoded_order(),
+            signature: signed.signature(),
+            order_status: OPEN_ORDER_STATUS.to_string(),
+            created_at: 0,
+            chain_id: 1,
+            order_hash: signed.order_hash(),
+            order_type: None,
+            input: None,
+            outputs: vec![],
+        };
+        assert!(strategy.process_order_event(event).await.is_none());
+        assert!(strategy.open_orders.is_empty());
+
+        let timestamp = signed.order.info().deadline.to::<u64>() - 60;
+        strategy
+            .process_new_block_event(new_block(100, timestamp))
+            .await;
+
+        assert!(strategy.unverified_orders.is_empty());
+        assert!(strategy.open_orders.contains_key(&signed.order_hash()));
+        let batches = batch_receiver.recv().await.unwrap();
+        assert_eq!(batches.len(), 1);
+        assert_eq!(batches[0].orders[0].hash, signed.order_hash());
+    }
+
+    // a fill of a 1 WETH for 2000 USDC priority order routed to 2100 USDC, where 0.2 USDC pays
+    // for 100k gas at 1 gwei
+    fn priority_fill(baseline_priority_fee: u64) -> (TestStrategy, RoutedOrder) {
+        let order = Order::Priority(
+            PriorityOrderBuilder::new()
+                .baseline_priority_fee(Uint::from(baseline_priority_fee))
+                .build(),
+        );
+        let (provider, _) = Provider::mocked();
+        let (mut strategy, _) = strategy(provider, order.info().reactor);
+        strategy.last_block_number = 100;
+        strategy.last_block_timestamp = order.info().deadline.to::<u64>() - 60;
+        strategy.next_base_fee = Some(U256::from(1_000_000_000u64));
+
+        let resolved = match order.resolve(&strategy.resolution_params()) {
+            OrderResolution::Resolved(resolved) => resolved,
+            resolution => panic!("unexpected resolution {:?}", resolution),
+        };
+        let event = RoutedOrder {
+            route: OrderRoute {
+                quote: "2100000000".to_string(),
+                gas_price_wei: "1000000000".to_string(),
+                gas_use_estimate_quote: "200000".to_string(),
+                gas_use_estimate: "100000".to_string(),
+                route: vec![],
+                method_parameters: MethodParameters {
+                    calldata: "0x".to_string(),
+                    value: "0".to_string(),
+                    to: "0x0000000000000000000000000000000000000001".to_string(),
+                },
+            },
+            request: OrderBatchData {
+                amount_in: resolved.input.amount,
+                amount_out_required: resolved.outputs[0].amount,
+                reactor: order.info().reactor.to_string(),
+                token_in: resolved.input.token.to_string(),
+                token_out: resolved.outputs[0].token.to_string(),
+                orders: vec![OrderData {
+                    order,
+                    hash: "0x01".to_string(),
+                    signature: "0x".to_string(),
+                    resolved,
+                }],
+            },
+        };
+        (strategy, event)
+    }
+
+    #[test]
+    fn test_price_priority_fill() {
+        // 100 USDC of profit is 5e16 wei, so a 50% bid pays 250 gwei per gas, of which
+        // 249 gwei is the tip
+        let priority_fee = 249_000_000_000u64;
+        let (strategy, event) = priority_fill(priority_fee - 1_000);
+        let profit = strategy.get_profit_eth(&event).unwrap();
+        assert_eq!(profit, U256::from(50_000_000_000_000_000u64));
+
+        let (event, fee) = strategy.price_priority_fill(event, profit).unwrap();
+        assert_eq!(fee, U256::from(priority_fee));
+        // 1000 wei above the baseline scales the output up by 1000 mps
+        assert_eq!(
+            event.request.amount_out_required,
+            Uint::from(2_000_200_000u64)
+        );
+        assert_eq!(
+            event.request.orders[0].resolved.outputs[0].amount,
+            Uint::from(2_000_200_000u64)
+        );
+    }
+
+    #[test]
+    fn test_price_priority_fill_unprofitable() {
+        // the whole tip scales the output, which the route can no longer cover
+        let (strategy, event) = priority_fill(0);
+        let profit = strategy.get_profit_eth(&event).unwrap();
 
-        let gas_use_eth = U256::from_str_radix(&route.gas_use_estimate, 10)
-            .ok()?
-            .saturating_mul(U256::from_str_radix(&route.gas_price_wei, 10).ok()?);
-        profit_quote
-            .saturating_mul(gas_use_eth)
-            .checked_div(U256::from_str_radix(&route.gas_use_estimate_quote, 10).ok()?)
+        assert!(strategy.price_priority_fill(event, profit).is_none());
     }
 }
@@ -0,0 +1,358 @@
+//! Differential tests for the Uniswap V3 swap simulator against QuoterV2, on mainnet pools as of
+//! a pinned block. The pool state and quotes are recorded into a fixture the simulator is checked
+//! against offline. Recording it, and checking against a live node, needs a mainnet archive node:
+//! `ETH_RPC_URL=<url> cargo test --test uniswap_v3_quoter -- --ignored`.
+
+use ethers::{
+    abi::{self, ParamType, Token},
+    providers::{Http, Middleware, Provider},
+    types::{transaction::eip2718::TypedTransaction, BlockId, TransactionRequest, H160, U256, U64},
+};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use uniswapx_artemis::routers::{
+    uniswap_v3_pool::{Tick, V3Pool},
+    uniswap_v3_router::load_pool,
+};
+
+/// The block the pools are loaded and quoted at.
+const BLOCK: u64 = 19_000_000;
+
+/// The recorded pools and quotes, relative to the crate root.
+const FIXTURE: &str = "tests/fixtures/uniswap_v3_mainnet.json";
+
+/// QuoterV2, from https://docs.uniswap.org/contracts/v3/reference/deployments/ethereum-deployments
+const QUOTER_V2: &str = "0x61fFE014bA17989E743c5F6cB21bF9697530B21e";
+
+/// `bytes4(keccak256("quoteExactInputSingle((address,address,uint256,uint24,uint160))"))`
+const QUOTE_EXACT_INPUT_SINGLE_SELECTOR: [u8; 4] = [0xc6, 0xa5, 0x02, 0x6a];
+
+/// `bytes4(keccak256("quoteExactOutputSingle((address,address,uint256,uint24,uint160))"))`
+const QUOTE_EXACT_OUTPUT_SINGLE_SELECTOR: [u8; 4] = [0xbd, 0x21, 0x70, 0x4a];
+
+/// A pool and the swaps to quote through it.
+struct PoolCase {
+    name: &'static str,
+    address: &'static str,
+    // (zero for one, exact input, amount)
+    swaps: Vec<(bool, bool, U256)>,
+}
+
+fn units(amount: u64, decimals: usize) -> U256 {
+    U256::from(amount) * U256::exp10(decimals)
+}
+
+fn pool_cases() -> Vec<PoolCase> {
+    vec![
+        PoolCase {
+            name: "USDC/WETH 0.05%",
+            address: "0x88e6A0c2dDD26FEEb64F039a2c41296FcB3f5640",
+            swaps: vec![
+                (true, true, units(1_000, 6)),
+                (true, true, units(5_000_000, 6)),
+                (false, true, units(1, 18)),
+                (false, true, units(2_000, 18)),
+                (true, false, units(10, 18)),
+                (false, false, units(100_000, 6)),
+            ],
+        },
+        PoolCase {
+            name: "USDC/WETH 0.3%",
+            address: "0x8ad599c3A0ff1De082011EFDDc58f1908eb6e6D8",
+            swaps: vec![
+                (true, true, units(1_000, 6)),
+                (true, true, units(1_000_000, 6)),
+                (false, true, units(1, 18)),
+                (false, true, units(500, 18)),
+                (true, false, units(1, 18)),
+                (false, false, units(10_000, 6)),
+            ],
+        },
+        PoolCase {
+            name: "WBTC/WETH 0.3%",
+            address: "0xCBCdF9626bC03E24f779434178A73a0B4bad62eD",
+            swaps: vec![
+                (true, true, units(1, 8)),
+                (true, true, units(100, 8)),
+                (false, true, units(10, 18)),
+                (false, true, units(1_000, 18)),
+                (true, false, units(20, 18)),
+                (false, false, units(2, 8)),
+            ],
+        },
+        PoolCase {
+            name: "USDC/USDT 0.01%",
+            address: "0x3416cF6C708Da44DB2624D63ea0AAef7113527C6",
+            swaps: vec![
+                (true, true, units(1_000, 6)),
+                (true, true, units(20_000_000, 6)),
+                (false, true, units(1_000, 6)),
+                (false, true, units(20_000_000, 6)),
+                (true, false, units(500_000, 6)),
+                (false, false, units(500_000, 6)),
+            ],
+        },
+    ]
+}
+
+/// Quotes a single pool swap with QuoterV2, returning the amount of the other token and the
+/// price after the swap.
+async fn quote(
+    client: &Provider<Http>,
+    pool: &V3Pool,
+    zero_for_one: bool,
+    exact_input: bool,
+    amount: U256,
+) -> (U256, U256) {
+    let (token_in, token_out) = if zero_for_one {
+        (pool.token0, pool.token1)
+    } else {
+        (pool.token1, pool.token0)
+    };
+    let selector = if exact_input {
+        QUOTE_EXACT_INPUT_SINGLE_SELECTOR
+    } else {
+        QUOTE_EXACT_OUTPUT_SINGLE_SELECTOR
+    };
+    let params = Token::Tuple(vec![
+        Token::Address(token_in),
+        Token::Address(token_out),
+        Token::Uint(amount),
+        Token::Uint(U256::from(pool.fee)),
+        Token::Uint(U256::zero()),
+    ]);
+    let data = [selector.to_vec(), abi::encode(&[params])].concat();
+    let tx: TypedTransaction = TransactionRequest::new()
+        .to(QUOTER_V2.parse::<H160>().unwrap())
+        .data(data)
+        .into();
+
+    let result = client
+        .call(&tx, Some(BlockId::from(U64::from(BLOCK))))
+        .await
+        .unwrap();
+    let outputs = [
+        ParamType::Uint(256),
+        ParamType::Uint(160),
+        ParamType::Uint(32),
+        ParamType::Uint(256),
+    ];
+    match abi::decode(&outputs, &result).unwrap().as_slice() {
+        [Token::Uint(amount), Token::Uint(sqrt_price_x96_after), ..] => {
+            (*amount, *sqrt_price_x96_after)
+        }
+        _ => panic!("invalid quote"),
+    }
+}
+
+/// The pools and quotes as of `block`.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Fixture {
+    block: u64,
+    pools: Vec<PoolFixture>,
+}
+
+/// A pool's state, with liquidity as decimal strings since JSON numbers can't hold it.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct PoolFixture {
+    name: String,
+    address: H160,
+    token0: H160,
+    token1: H160,
+    fee: u32,
+    tick_spacing: i32,
+    sqrt_price_x96: U256,
+    tick: i32,
+    liquidity: String,
+    // (tick, liquidity gross, liquidity net) of every initialized tick
+    ticks: Vec<(i32, String, String)>,
+    // the first and last word of the tick bitmap the ticks were loaded from
+    words: (i16, i16),
+    quotes: Vec<QuoteFixture>,
+}
+
+/// A QuoterV2 quote of a swap through the pool.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct QuoteFixture {
+    zero_for_one: bool,
+    exact_input: bool,
+    amount: U256,
+    quoted: U256,
+    sqrt_price_x96_after: U256,
+}
+
+impl PoolFixture {
+    fn new(name: &str, pool: &V3Pool, quotes: Vec<QuoteFixture>) -> Self {
+        Self {
+            name: name.to_string(),
+            address: pool.address,
+            token0: pool.token0,
+            token1: pool.token1,
+            fee: pool.fee,
+            tick_spacing: pool.tick_spacing,
+            sqrt_price_x96: pool.sqrt_price_x96,
+            tick: pool.tick,
+            liquidity: pool.liquidity.to_string(),
+            ticks: pool
+                .ticks
+                .iter()
+                .map(|(tick, state)| {
+                    (
+                        *tick,
+                        state.liquidity_gross.to_string(),
+                        state.liquidity_net.to_string(),
+                    )
+                })
+                .collect(),
+            words: (*pool.words.start(), *pool.words.end()),
+            quotes,
+        }
+    }
+
+    fn pool(&self, block: u64) -> V3Pool {
+        V3Pool {
+            address: self.address,
+            token0: self.token0,
+            token1: self.token1,
+            fee: self.fee,
+            tick_spacing: self.tick_spacing,
+            sqrt_price_x96: self.sqrt_price_x96,
+            tick: self.tick,
+            liquidity: self.liquidity.parse().unwrap(),
+            ticks: self
+                .ticks
+                .iter()
+                .map(|(tick, liquidity_gross, liquidity_net)| {
+                    let state = Tick {
+                        liquidity_gross: liquidity_gross.parse().unwrap(),
+                        liquidity_net: liquidity_net.parse().unwrap(),
+                    };
+                    (*tick, state)
+                })
+                .collect::<BTreeMap<_, _>>(),
+            words: self.words.0..=self.words.1,
+            synced_at: (U64::from(block), U256::zero()),
+        }
+    }
+}
+
+fn fixture_path() -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR")).join(FIXTURE)
+}
+
+/// Simulates a swap and describes how it differs from the quote, if it does.
+fn mismatch(name: &str, pool: &V3Pool, quote: &QuoteFixture) -> Option<String> {
+    let swap = match pool.swap(quote.zero_for_one, quote.amount, quote.exact_input, None) {
+        Some(swap) => swap,
+        None => return Some(format!("{}: swap of {} failed", name, quote.amount)),
+    };
+    let (filled, calculated) = if quote.exact_input {
+        (swap.amount_in, swap.amount_out)
+    } else {
+        (swap.amount_out, swap.amount_in)
+    };
+    if filled == quote.amount
+        && calculated == quote.quoted
+        && swap.sqrt_price_x96 == quote.sqrt_price_x96_after
+    {
+        return None;
+    }
+    Some(format!(
+        "{}: {} (zero for one {}, exact input {}): simulated {} at {}, quoted {} at {}",
+        name,
+        quote.amount,
+        quote.zero_for_one,
+        quote.exact_input,
+        calculated,
+        swap.sqrt_price_x96,
+        quote.quoted,
+        quote.sqrt_price_x96_after
+    ))
+}
+
+/// Loads the pools and quotes their swaps at `BLOCK`.
+async fn record(client: &Provider<Http>) -> Fixture {
+    let mut pools = Vec::new();
+    for case in pool_cases() {
+        let pool = load_pool(client, case.address.parse().unwrap(), U64::from(BLOCK))
+            .await
+            .unwrap();
+        let mut quotes = Vec::new();
+        for (zero_for_one, exact_input, amount) in case.swaps {
+            let (quoted, sqrt_price_x96_after) =
+                quote(client, &pool, zero_for_one, exact_input, amount).await;
+            quotes.push(QuoteFixture {
+                zero_for_one,
+                exact_input,
+                amount,
+                quoted,
+                sqrt_price_x96_after,
+            });
+        }
+        pools.push(PoolFixture::new(case.name, &pool, quotes));
+    }
+    Fixture {
+        block: BLOCK,
+        pools,
+    }
+}
+
+fn check(fixture: &Fixture) {
+    let mut mismatches = Vec::new();
+    for pool_fixture in fixture.pools.iter() {
+        let pool = pool_fixture.pool(fixture.block);
+        mismatches.extend(
+            pool_fixture
+                .quotes
+                .iter()
+                .filter_map(|quote| mismatch(&pool_fixture.name, &pool, quote)),
+        );
+    }
+    assert!(mismatches.is_empty(), "{}", mismatches.join("\n"));
+}
+
+#[test]
+fn matches_recorded_quoter_v2_quotes() {
+    let path = fixture_path();
+    let json = match std::fs::read_to_string(&path) {
+        Ok(json) => json,
+        Err(_) => {
+            eprintln!(
+                "{} is not recorded, run records_mainnet_fixture to record it",
+                path.display()
+            );
+            return;
+        }
+    };
+    let fixture: Fixture = serde_json::from_str(&json).unwrap();
+
+    assert_eq!(fixture.block, BLOCK);
+    assert_eq!(fixture.pools.len(), pool_cases().len());
+    check(&fixture);
+}
+
+#[tokio::test]
+#[ignore]
+async fn matches_quoter_v2_on_mainnet_pools() {
+    let url = std::env::var("ETH_RPC_URL").expect("ETH_RPC_URL must be set");
+    let client = Provider::<Http>::try_from(url).unwrap();
+
+    check(&record(&client).await);
+}
+
+/// Writes the fixture `matches_recorded_quoter_v2_quotes` checks against.
+#[tokio::test]
+#[ignore]
+async fn records_mainnet_fixture() {
+    let url = std::env::var("ETH_RPC_URL").expect("ETH_RPC_URL must be set");
+    let client = Provider::<Http>::try_from(url).unwrap();
+
+    let fixture = record(&client).await;
+    let path = fixture_path();
+    std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+    std::fs::write(&path, serde_json::to_string_pretty(&fixture).unwrap()).unwrap();
+}